@@ -1 +1,2 @@
-pub mod synchronization_graph_tasks_basics;
+pub mod objekt_snapshot;
+pub mod synchronization_graph_tasks_basics;
\ No newline at end of file
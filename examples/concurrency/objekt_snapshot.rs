@@ -0,0 +1,134 @@
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+        RwLock,
+    },
+};
+
+use molecule_engine::{
+    concurrency::{
+        molecule_objekt::{
+            clone_objekt_in_list,
+            MoleculeObjekt,
+            ObjektList,
+        },
+        objekt_snapshot::{
+            load_snapshot,
+            read_snapshot_header,
+            save_snapshot,
+            write_snapshot,
+            ObjektTypeRegistry,
+            SerializableObjekt,
+        },
+    },
+    math::vectors::{
+        Vector3U64,
+        VoxelLocation,
+    },
+    objekt_impl::storage::{
+        hybrid_octree::{
+            HybridOctree,
+            HybridOctreeObjekt,
+        },
+        level_generator::HashNoiseGenerator,
+    },
+    utils::binary::{
+        ByteReader,
+        write_u32,
+    },
+};
+
+#[derive(Clone)]
+struct CounterObjekt {
+    name: String,
+    count: u32,
+}
+
+impl MoleculeObjekt for CounterObjekt {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl SerializableObjekt for CounterObjekt {
+    fn type_tag() -> &'static str {
+        "examples::Counter"
+    }
+
+    fn serialize(&self, out: &mut Vec<u8>) -> Result<(), String> {
+        write_u32(out, self.count);
+        Ok(())
+    }
+
+    fn deserialize(name: String, reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(Self {
+            name: name,
+            count: reader.read_u32()?,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct TransientObjekt {
+    name: String,
+}
+
+impl MoleculeObjekt for TransientObjekt {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[allow(dead_code)]
+pub fn snapshot_round_trip() {
+    let octree = HybridOctree::new(4, 2, Box::new(HashNoiseGenerator::new(0)));
+    octree.load_level(VoxelLocation { lod: 1, vec: Vector3U64 { x: 0, y: 0, z: 0 } }).unwrap();
+
+    let objekt_list: ObjektList = Arc::new(Mutex::new(vec![
+        Arc::new(RwLock::new(CounterObjekt { name: String::from("bert"), count: 7 })),
+        Arc::new(RwLock::new(TransientObjekt { name: String::from("not_saved") })),
+        Arc::new(RwLock::new(HybridOctreeObjekt::new(String::from("world"), octree))),
+    ]));
+
+    let mut registry = ObjektTypeRegistry::default();
+    registry.register::<CounterObjekt>().unwrap();
+    registry.register::<HybridOctreeObjekt>().unwrap();
+    assert!(registry.register::<CounterObjekt>().is_err(), "Registering a type tag twice should fail");
+
+    let path = std::env::temp_dir().join("molecule_engine_snapshot_round_trip.molsnap");
+    assert_eq!(save_snapshot(&objekt_list, &registry, &path).unwrap(), vec![String::from("not_saved")]);
+    let restored_list = load_snapshot(&registry, &path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let restored = restored_list.lock().unwrap();
+    assert_eq!(restored.len(), 2, "unregistered objekts must be left out");
+    assert_eq!(restored[0].read().unwrap().name(), "bert");
+    assert_eq!(restored[1].read().unwrap().name(), "world");
+
+    let counter: Box<CounterObjekt> = clone_objekt_in_list(&restored, "bert").unwrap();
+    assert_eq!(counter.count, 7);
+
+    let original: Box<HybridOctreeObjekt> = clone_objekt_in_list(&objekt_list.lock().unwrap(), "world").unwrap();
+    let mut world: Box<HybridOctreeObjekt> = clone_objekt_in_list(&restored, "world").unwrap();
+    assert!(world.set_generator(Box::new(HashNoiseGenerator::new(0))).is_err(), "The restored list still shares the octree");
+    let mut unshared = HybridOctreeObjekt::new(String::from("unshared"), HybridOctree::new(1, 2, Box::new(HashNoiseGenerator::new(0))));
+    assert!(unshared.set_generator(Box::new(HashNoiseGenerator::new(1))).is_ok());
+    let original = original.inner();
+    let world = world.inner();
+    assert_eq!(world.level_depth, original.level_depth);
+    assert_eq!(world.level_length, original.level_length);
+    assert_eq!(world.levels.len(), original.levels.len());
+    for (a, b) in world.levels.levels().iter().zip(original.levels.levels().iter()) {
+        assert_eq!(a.ordinal, b.ordinal);
+        assert_eq!(a.level.contents.read().unwrap().data, b.level.contents.read().unwrap().data);
+    }
+
+    let snapshot = write_snapshot(&restored, &registry).unwrap();
+    assert!(snapshot.skipped.is_empty());
+    let header = read_snapshot_header(&snapshot.data).unwrap();
+    assert_eq!(header.objekt_count, 2);
+    assert_eq!(header.engine_version, molecule_engine::get_engine_version());
+
+    println!("Snapshot round trip passed");
+}
\ No newline at end of file
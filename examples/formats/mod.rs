@@ -0,0 +1,393 @@
+use std::convert::TryInto;
+
+use molecule_engine::{
+    formats::{
+        heightmap::{
+            Heightmap,
+            HeightmapGenerator,
+            TerrainLayer,
+        },
+        mesh_export::{
+            level_node_name,
+            GLB_MAGIC,
+        },
+        ply::{
+            PlyFormat,
+            PlyOptions,
+        },
+        vox::{
+            default_vox_palette,
+            VoxAxes,
+            VoxFile,
+            VoxInstance,
+            VoxModel,
+            VoxPaletteMapping,
+        },
+    },
+    math::{
+        octree_math::index_in_level,
+        shapes::Aabb,
+        vectors::{
+            Vector3U16,
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    meshing::mesh::{
+        MeshMode,
+        MeshOptions,
+    },
+    objekt_impl::{
+        materials::{
+            MaterialDefinition,
+            MaterialRegistry,
+        },
+        storage::{
+            downsampling::DownsamplePolicy,
+            hybrid_octree::HybridOctree,
+            level_generator::EmptyGenerator,
+            material_composition::MaterialComposition,
+            particle::{
+                one_hot_material,
+                particle_position,
+                HALF_LIMIT,
+            },
+        },
+    },
+};
+
+fn voxel(lod: u64, x: u64, y: u64, z: u64) -> VoxelLocation {
+    VoxelLocation {
+        lod: lod,
+        vec: Vector3U64 { x: x, y: y, z: z },
+    }
+}
+
+fn registry() -> MaterialRegistry {
+    let mut registry = MaterialRegistry::new();
+    let mut stone = MaterialDefinition::new(String::from("stone"));
+    stone.color = [128, 128, 128, 255];
+    registry.define(1, stone).unwrap();
+    let mut grass = MaterialDefinition::new(String::from("grass"));
+    grass.color = [0, 200, 0, 255];
+    registry.define(2, grass).unwrap();
+    let mut sand = MaterialDefinition::new(String::from("sand"));
+    sand.color = [230, 210, 150, 255];
+    registry.define(5, sand).unwrap();
+    registry
+}
+
+fn sorted(mut voxels: Vec<([i32; 3], u8)>) -> Vec<([i32; 3], u8)> {
+    voxels.sort();
+    voxels
+}
+
+#[allow(dead_code)]
+pub fn vox_round_trip() {
+    let palette = default_vox_palette();
+    assert_eq!(palette[0], [0, 0, 0, 0]);
+    assert_eq!(palette[1], [255, 255, 255, 255]);
+    assert_eq!(palette[2], [255, 255, 204, 255]);
+    assert_eq!(palette[215], [0, 0, 51, 255]);
+    assert_eq!(palette[216], [0xee, 0, 0, 255]);
+    assert_eq!(palette[255], [0x11, 0x11, 0x11, 255]);
+
+    //a lone model is written without a scene graph and placed at 0
+    let mut vox = VoxFile::default();
+    vox.palette[10] = [120, 120, 120, 255];
+    vox.palette[20] = [10, 190, 10, 255];
+    vox.models.push(VoxModel {
+        size: [3, 2, 2],
+        voxels: vec![([0, 0, 0], 10), ([1, 0, 0], 10), ([2, 1, 0], 20), ([2, 1, 1], 20)],
+    });
+    let written = vox.write();
+    assert!(!written.windows(4).any(|chunk_id| chunk_id==b"nTRN" || chunk_id==b"nSHP"));
+    let read = VoxFile::read(&written).unwrap();
+    assert_eq!(read, vox);
+    assert_eq!(sorted(read.placed_voxels()), vec![([0, 0, 0], 10), ([1, 0, 0], 10), ([2, 1, 0], 20), ([2, 1, 1], 20)]);
+    //so are several models without instances, which all sit at 0 again once read back
+    let mut models = vox.clone();
+    models.models.push(VoxModel {
+        size: [1, 1, 1],
+        voxels: vec![([0, 0, 0], 20)],
+    });
+    let written = models.write();
+    assert!(!written.windows(4).any(|chunk_id| chunk_id==b"nTRN" || chunk_id==b"nSHP"));
+    let read = VoxFile::read(&written).unwrap();
+    assert_eq!(read, models);
+    assert_eq!(sorted(read.placed_voxels()), vec![([0, 0, 0], 10), ([0, 0, 0], 20), ([1, 0, 0], 10), ([2, 1, 0], 20), ([2, 1, 1], 20)]);
+
+    //instances keep their rotation and translation through a write
+    let mut scene = vox.clone();
+    scene.instances.push(VoxInstance::at(0, [3, 2, 2], [10, 0, 0]));
+    scene.instances.push(VoxInstance {
+        model: 0,
+        rotation: [[0, -1, 0], [1, 0, 0], [0, 0, 1]],
+        translation: [0, 0, 5],
+    });
+    let read = VoxFile::read(&scene.write()).unwrap();
+    assert_eq!(read, scene);
+    let placed = read.placed_voxels();
+    assert!(placed.contains(&([10, 0, 0], 10)));
+    //(2, 1, 0) less the half size (1, 1, 1) is (1, 0, -1), which turns a quarter to (0, 1, -1) before moving up by 5
+    assert!(placed.contains(&([0, 1, 4], 20)));
+
+    let mut truncated = vox.write();
+    truncated.truncate(truncated.len()-3);
+    assert!(VoxFile::read(&truncated).is_err(), "A truncated file should not parse");
+    assert!(VoxFile::read(b"VOX?").is_err());
+
+    //mappings come from a table or from the closest colors
+    let registry = registry();
+    let mapping = VoxPaletteMapping::parse("# props\n10 = stone\n20 = 2 # grass by index\n", &registry).unwrap();
+    assert_eq!((mapping.material(10), mapping.material(20), mapping.material(30)), (Some(1), Some(2), None));
+    assert_eq!(mapping.color(2), Some(20));
+    assert_eq!(VoxPaletteMapping::parse(&mapping.to_text(&registry), &registry).unwrap(), mapping);
+    assert!(VoxPaletteMapping::parse("10 = granite", &registry).is_err());
+    assert!(VoxPaletteMapping::parse("0 = stone", &registry).is_err());
+    let nearest = VoxPaletteMapping::nearest_colors(&vox.palette, &registry);
+    assert_eq!((nearest.material(10), nearest.material(20), nearest.material(1)), (Some(1), Some(2), Some(5)));
+
+    //imported voxels become full weights of their materials, and unmapped colors refuse the whole import
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    let report = octree.import_vox(&vox, &mapping, &voxel(0, 3, 3, 3), VoxAxes::Unchanged).unwrap();
+    assert_eq!(report.changed_voxels, 4);
+    assert_eq!(report.loaded_levels.len(), 4);
+    assert_eq!(octree.query(voxel(0, 4, 3, 3)).unwrap().material, one_hot_material(1));
+    assert_eq!(octree.query(voxel(0, 5, 4, 4)).unwrap().material, one_hot_material(2));
+    assert!(octree.query(voxel(0, 5, 3, 3)).unwrap().material.is_empty());
+    let mut partial = VoxPaletteMapping::new();
+    partial.set(10, Some(1)).unwrap();
+    assert!(octree.import_vox(&vox, &partial, &voxel(0, 0, 0, 0), VoxAxes::Unchanged).is_err());
+    assert!(octree.query(voxel(0, 0, 0, 0)).unwrap().material.is_empty());
+
+    //exporting the same region gives back the same voxels and colors
+    let exported = octree.export_vox(&Aabb::new([3.0; 3], [6.0, 5.0, 5.0]), 0, &mapping, &registry, VoxAxes::Unchanged).unwrap();
+    assert_eq!(exported.models.len(), 1);
+    assert_eq!(sorted(exported.placed_voxels()), sorted(vox.placed_voxels()));
+    assert_eq!(exported.palette[10], [128, 128, 128, 255]);
+    assert_eq!(exported.palette[20], [0, 200, 0, 255]);
+
+    //materials without a color take the first unmapped one
+    octree.set_voxel_material(&voxel(0, 3, 4, 3), 5).unwrap();
+    let exported = octree.export_vox(&Aabb::new([3.0; 3], [6.0, 5.0, 5.0]), 0, &mapping, &registry, VoxAxes::Unchanged).unwrap();
+    assert!(exported.placed_voxels().contains(&([0, 1, 0], 1)));
+    assert_eq!(exported.palette[1], [230, 210, 150, 255]);
+
+    //MagicaVoxel's z up becomes y up
+    let upright = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    upright.import_vox(&vox, &mapping, &voxel(0, 0, 0, 0), VoxAxes::YUp).unwrap();
+    assert_eq!(upright.query(voxel(0, 2, 1, 0)).unwrap().material, one_hot_material(2));
+    assert_eq!(upright.query(voxel(0, 2, 0, 0)).unwrap().material, one_hot_material(2));
+    assert_eq!(upright.query(voxel(0, 0, 0, 1)).unwrap().material, one_hot_material(1));
+    let exported = upright.export_vox(&Aabb::new([0.0; 3], [4.0; 3]), 0, &mapping, &registry, VoxAxes::YUp).unwrap();
+    assert_eq!(sorted(exported.placed_voxels()), sorted(vox.placed_voxels()));
+
+    //regions too large for one model are split, and come back whole
+    let long = HybridOctree::new(1, 8, Box::new(EmptyGenerator));
+    long.fill_box(&Aabb::new([0.0; 3], [300.0, 1.0, 1.0]), 0, 1).unwrap();
+    let exported = long.export_vox(&Aabb::new([0.0; 3], [400.0, 1.0, 1.0]), 0, &mapping, &registry, VoxAxes::Unchanged).unwrap();
+    assert_eq!(exported.models.len(), 2);
+    assert_eq!(exported.models[0].size, [256, 1, 1]);
+    let read = VoxFile::read(&exported.write()).unwrap();
+    let copy = HybridOctree::new(1, 8, Box::new(EmptyGenerator));
+    assert_eq!(copy.import_vox(&read, &mapping, &voxel(0, 0, 0, 0), VoxAxes::Unchanged).unwrap().changed_voxels, 300);
+    assert_eq!(copy.query(voxel(0, 299, 0, 0)).unwrap().material, one_hot_material(1));
+    assert!(copy.export_vox(&Aabb::new([0.0; 3], [f64::INFINITY; 3]), 0, &mapping, &registry, VoxAxes::Unchanged).is_err());
+    println!("Vox round trip passed");
+}
+
+//loads the level the generator fills in first
+fn generated(octree: &HybridOctree, location: VoxelLocation) -> MaterialComposition {
+    octree.load_level(location.clone()).unwrap();
+    octree.query(location).unwrap().material
+}
+
+#[allow(dead_code)]
+pub fn heightmap_terrain() {
+    let heights: Vec<u16> = (0..64u16).map(|i| (i%8)*2+(i/8)*3/2+4).collect();
+    let heightmap = Heightmap::new(8, 8, heights).unwrap();
+    assert_eq!(Heightmap::read_r16(&heightmap.write_r16(), 8).unwrap(), heightmap);
+    assert!(Heightmap::read_r16(&heightmap.write_r16(), 7).is_err(), "Rows of 7 do not fit 64 samples");
+    assert!(Heightmap::read_r16(&[0, 1, 2], 1).is_err());
+    assert_eq!(Heightmap::read_png(&heightmap.write_png().unwrap()).unwrap(), heightmap);
+    assert!(Heightmap::read_png(b"not a png").is_err());
+    assert!(Heightmap::new(2, 2, vec![0; 3]).is_err());
+
+    //files on disk, including raw ones that are not square
+    let directory = std::env::temp_dir().join("molecule_engine_heightmap_terrain");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let wide = Heightmap::new(8, 2, (0..16).collect()).unwrap();
+    std::fs::write(directory.join("wide.r16"), wide.write_r16()).unwrap();
+    std::fs::write(directory.join("wide.png"), wide.write_png().unwrap()).unwrap();
+    assert_eq!(Heightmap::load_r16(&directory.join("wide.r16"), 8).unwrap(), wide);
+    assert_eq!(Heightmap::load_png(&directory.join("wide.png")).unwrap(), wide);
+    assert!(Heightmap::load_r16(&directory.join("wide.r16"), 3).is_err());
+    assert!(Heightmap::load_png(&directory.join("missing.png")).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(heightmap.sample(0.5, 0.0), 5.0);
+    assert_eq!(heightmap.sample(-3.0, 100.0), heightmap.get(0, 7) as f64);
+    assert_eq!((wide.width(), wide.depth(), wide.heights().len()), (8, 2, 16));
+
+    //one voxel of grass over two of dirt over stone
+    let layers = vec![
+        TerrainLayer { material: 2, thickness: 1.0 },
+        TerrainLayer { material: 3, thickness: 2.0 },
+        TerrainLayer { material: 1, thickness: 0.0 },
+    ];
+    let octree = HybridOctree::new(3, 4, Box::new(HeightmapGenerator::new(heightmap.clone(), layers.clone())));
+    //column (0, 0) has its surface at 4
+    let column: Vec<_> = (0..6).map(|y| generated(&octree, voxel(0, 0, y, 0))).collect();
+    assert_eq!(column[0], one_hot_material(1));
+    assert_eq!(column[1], one_hot_material(3));
+    assert_eq!(column[2], one_hot_material(3));
+    assert_eq!(column[3], one_hot_material(2));
+    assert!(column[4].is_empty() && column[5].is_empty());
+    //beyond the heightmap the edge continues
+    assert_eq!(generated(&octree, voxel(0, 20, 21, 3)), one_hot_material(2));
+    assert!(generated(&octree, voxel(0, 20, 22, 3)).is_empty());
+
+    //coarse levels match what downsampling the finer ones makes of them
+    for lod in 1..3 {
+        for x in 0..8 >> (lod-1) {
+            for y in 0..16 >> (lod-1) {
+                for z in 0..8 >> (lod-1) {
+                    let parent = voxel(lod, x, y, z);
+                    let mut children = vec![];
+                    for child in 0..8 {
+                        let location = voxel(lod-1, x*2+(child>>2&1), y*2+(child>>1&1), z*2+(child&1));
+                        children.push(generated(&octree, location));
+                    }
+                    assert_eq!(generated(&octree, parent.clone()), DownsamplePolicy::Majority.aggregate(&children), "{:?}", parent);
+                }
+            }
+        }
+    }
+
+    //scale and offset move the surface
+    let mut generator = HeightmapGenerator::new(heightmap, layers);
+    generator.horizontal_scale = 2.0;
+    generator.vertical_scale = 0.5;
+    generator.offset = [4.0, 10.0, 0.0];
+    assert_eq!(generator.surface_height(5.0, 1.0), 12.0);
+    assert_eq!(generator.surface_height(7.0, 1.0), 13.0);
+    let scaled = HybridOctree::new(2, 4, Box::new(generator));
+    assert_eq!(generated(&scaled, voxel(0, 4, 11, 0)), one_hot_material(2));
+    assert!(generated(&scaled, voxel(0, 4, 12, 0)).is_empty());
+    println!("Heightmap terrain passed");
+}
+
+#[allow(dead_code)]
+pub fn ply_point_cloud() {
+    let center = Vector3U16 { x: HALF_LIMIT, y: HALF_LIMIT, z: HALF_LIMIT };
+    assert_eq!(particle_position(&voxel(1, 2, 0, 1), &center), [5.0, 1.0, 3.0]);
+
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    octree.set_voxels(0, &[
+        (Vector3U64 { x: 1, y: 2, z: 3 }, one_hot_material(1)),
+        (Vector3U64 { x: 5, y: 0, z: 0 }, MaterialComposition::from_entries(vec![(1, 100), (2, 200)])),
+    ]).unwrap();
+    //the first particle sits in the lowest corner of its voxel
+    let stone = voxel(0, 1, 2, 3);
+    octree.with_level_mut(&voxel(0, 0, 0, 0), |contents| {
+        contents.data.pos[index_in_level(&stone, 4)] = Vector3U16 { x: 0, y: 0, z: 0 };
+    }).unwrap();
+
+    let cloud = octree.point_cloud(&Aabb::new([0.0; 3], [8.0; 3]), 0).unwrap();
+    assert_eq!(cloud.points.len(), 2);
+    assert_eq!(cloud.points[0].position, [1.0, 2.0, 3.0]);
+    assert_eq!(cloud.points[1].position, [5.5, 0.5, 0.5]);
+    assert_eq!(cloud.materials(), vec![1, 2]);
+    assert_eq!(octree.point_cloud(&Aabb::new([0.0; 3], [4.0; 3]), 0).unwrap().points.len(), 1);
+    assert!(octree.point_cloud(&Aabb::new([f64::NEG_INFINITY; 3], [4.0; 3]), 0).is_err());
+
+    let registry = registry();
+    let mut options = PlyOptions::new(PlyFormat::Ascii);
+    options.material_weights = true;
+    let text = String::from_utf8(cloud.write_ply(&registry, &options)).unwrap();
+    let (header, body) = text.split_at(text.find("end_header\n").unwrap());
+    assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+    assert!(header.contains("element vertex 2\n"));
+    assert!(header.contains("comment weight_2 is grass\n"));
+    assert!(header.contains("property uchar weight_1\nproperty uchar weight_2\n"));
+    let lines: Vec<_> = body.lines().skip(1).collect();
+    assert_eq!(lines, vec!["1 2 3 128 128 128 255 1 255 0", "5.5 0.5 0.5 0 200 0 255 2 100 200"]);
+
+    //binary points are three doubles, four color bytes and the material
+    let binary = cloud.write_ply(&registry, &PlyOptions::new(PlyFormat::BinaryLittleEndian));
+    let start = binary.windows(11).position(|window| window == b"end_header\n").unwrap()+11;
+    assert!(binary.starts_with(b"ply\nformat binary_little_endian 1.0\n"));
+    assert_eq!(binary.len(), start+2*30);
+    let second = &binary[start+30..];
+    assert_eq!(f64::from_le_bytes(second[0..8].try_into().unwrap()), 5.5);
+    assert_eq!(&second[24..30], &[0, 200, 0, 255, 2, 0]);
+    println!("PLY point cloud passed");
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset+4].try_into().unwrap())
+}
+
+#[allow(dead_code)]
+pub fn mesh_export() {
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    //a stone voxel with grass on top in one level, and a lone sand voxel in the next
+    octree.set_voxels(0, &[
+        (Vector3U64 { x: 1, y: 1, z: 1 }, one_hot_material(1)),
+        (Vector3U64 { x: 1, y: 2, z: 1 }, one_hot_material(2)),
+        (Vector3U64 { x: 5, y: 1, z: 1 }, one_hot_material(5)),
+    ]).unwrap();
+    let options = MeshOptions {
+        mode: MeshMode::Greedy,
+        ignored_materials: vec![],
+    };
+    let origins = vec![voxel(0, 0, 0, 0), voxel(0, 4, 0, 0), voxel(0, 0, 4, 0)];
+    let meshes = octree.level_meshes(&origins, &options).unwrap();
+    assert_eq!(meshes.levels.len(), 3);
+    assert!(meshes.levels[2].1.is_empty());
+    assert_eq!(meshes.materials(), vec![1, 2, 5]);
+    assert_eq!(level_node_name(&voxel(1, 4, 0, 8)), "level_1_4_0_8");
+    let registry = registry();
+
+    //a header, then a JSON chunk and a binary chunk, each of a length divisible by four
+    let glb = meshes.write_glb(&registry);
+    assert_eq!(&glb[0..4], GLB_MAGIC);
+    assert_eq!(read_u32(&glb, 4), 2);
+    assert_eq!(read_u32(&glb, 8) as usize, glb.len());
+    let json_len = read_u32(&glb, 12) as usize;
+    assert_eq!(json_len%4, 0);
+    assert_eq!(&glb[16..20], b"JSON");
+    let json = std::str::from_utf8(&glb[20..20+json_len]).unwrap();
+    let bin_len = read_u32(&glb, 20+json_len) as usize;
+    assert_eq!(&glb[24+json_len..28+json_len], b"BIN\0");
+    assert_eq!(28+json_len+bin_len, glb.len());
+    assert!(json.contains(&format!("\"buffers\":[{{\"byteLength\":{}}}]", bin_len)));
+    //three nodes but two meshes, with one primitive per material
+    assert_eq!(json.matches("\"name\":\"level_").count(), 5);
+    assert!(json.contains("{\"name\":\"level_0_0_4_0\",\"translation\":[0,4,0]}"));
+    assert!(json.contains("\"mesh\":1,\"translation\":[4,0,0]"));
+    assert_eq!(json.matches("\"indices\"").count(), 3);
+    assert!(json.contains("{\"name\":\"grass\",\"pbrMetallicRoughness\":{\"baseColorFactor\":[0,"));
+    assert!(json.contains("\"name\":\"stone\"") && json.contains("\"name\":\"sand\""));
+
+    //OBJ vertices are in world units, with face indices counting across all levels
+    let (obj, mtl) = meshes.write_obj(&registry, "terrain.mtl");
+    assert!(obj.starts_with("# exported by molecule-engine\nmtllib terrain.mtl\n"));
+    let vertices: usize = meshes.levels.iter().map(|(_origin, mesh)| mesh.vertex_count()).sum();
+    let triangles: usize = meshes.levels.iter().map(|(_origin, mesh)| mesh.triangle_count()).sum();
+    assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), vertices);
+    assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), triangles);
+    assert_eq!(obj.lines().filter(|line| line.starts_with("o ")).count(), 3);
+    let highest_index = obj.lines()
+        .filter_map(|line| line.strip_prefix("f "))
+        .flat_map(|face| face.split(' ').map(|corner| corner.split("//").next().unwrap().parse::<usize>().unwrap()).collect::<Vec<_>>())
+        .max()
+        .unwrap();
+    assert_eq!(highest_index, vertices);
+    assert!(obj.contains("v 5 1 1\n") && obj.contains("v 6 2 2\n"));
+    assert!(obj.contains("usemtl sand\n"));
+    assert!(mtl.contains("newmtl stone\nKd 0.501961 0.501961 0.501961\nd 1.000000\n"));
+    assert_eq!(mtl.matches("newmtl").count(), 3);
+    println!("Mesh export passed");
+}
\ No newline at end of file
@@ -1,5 +1,1344 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        Mutex,
+        RwLock,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use molecule_engine::{
+    concurrency::{
+        molecule_objekt::ObjektList,
+        tasks::task::{
+            Task,
+            TaskControlFlow,
+        },
+    },
+    math::{
+        hashing::{
+            hash_u64s,
+            mix64,
+        },
+        octree_math::{
+            child,
+            children,
+            index_in_level,
+            level_key,
+            level_key_origin,
+            level_origin,
+            location_in_level,
+            morton_decode,
+            morton_encode,
+            octant,
+            parent,
+            siblings,
+            max_level_depth,
+            max_voxel_coord,
+            to_lod,
+            voxel_center,
+            voxel_range,
+            MAX_LEVEL_COORD,
+            MAX_LOD,
+        },
+        shapes::{
+            Aabb,
+            Cylinder,
+            Frustum,
+            Sdf,
+            SdfFn,
+            Sphere,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        compression::{
+            ByteCompressor,
+            ColumnCodec,
+            LevelCompression,
+            LzCompressor,
+        },
+        downsampling::DownsamplePolicy,
+        fsck::{
+            FsckIssue,
+            FsckOptions,
+        },
+        gpu_layout::{
+            unpack_levels,
+            LevelPacker,
+            GPU_LEVEL_SIZE,
+            GPU_PARTICLE_SIZE,
+            NO_LEVEL,
+        },
+        hybrid_octree::{
+            HybridOctree,
+            HybridOctreeObjekt,
+            Level,
+            ParticleRef,
+        },
+        level_generator::{
+            EmptyGenerator,
+            FillGenerator,
+            HashNoiseGenerator,
+            LayeredGenerator,
+        },
+        level_payload::write_level_payload,
+        level_storage::{
+            LevelStorage,
+            MemoryLevelStorage,
+        },
+        material_composition::MaterialComposition,
+        particle::{
+            empty_particle_vec,
+            one_hot_material,
+            ParticleVec,
+            write_particle_vec,
+        },
+        raycast::{
+            Ray,
+            RaycastOptions,
+        },
+        region_file::{
+            RegionFile,
+            RegionStorage,
+            REGION_FORMAT_VERSION,
+        },
+        sorted_level_list::SortedLevel,
+        svo::{
+            Svo,
+            SvoChild,
+            SVO_EMPTY_VOXEL,
+            SVO_FORMAT_VERSION,
+            SVO_MAX_DEPTH,
+        },
+        voxel_editing::Brush,
+    },
+    task_impl::streaming::LevelStreaming,
+    utils::binary::ByteReader,
+};
+
+fn voxel(lod: u64, x: u64, y: u64, z: u64) -> VoxelLocation {
+    VoxelLocation {
+        lod: lod,
+        vec: Vector3U64 { x: x, y: y, z: z },
+    }
+}
+
 #[allow(dead_code)]
 pub fn storage_order() {
-    //test that levels are stored in the correct order, with lod 1 defining the smallest level size, stored after larger lods
-    unimplemented!();//coming soon
+    //test that levels are stored in the correct order, with lod 0 defining the smallest level size, stored after larger lods
+    let corners = [0, 1, 2, 3, 7, 1000, 123_456_789, MAX_LEVEL_COORD];
+    for x in &corners {
+        for y in &corners {
+            for z in &corners {
+                let coords = Vector3U64 { x: *x, y: *y, z: *z };
+                assert_eq!(morton_decode(morton_encode(&coords).unwrap()), coords);
+            }
+        }
+    }
+    assert_eq!(morton_encode(&Vector3U64 { x: MAX_LEVEL_COORD+1, y: 0, z: 0 }), None);
+    assert_eq!(morton_encode(&Vector3U64 { x: 1, y: 0, z: 0 }), Some(0b001));
+    assert_eq!(morton_encode(&Vector3U64 { x: 0, y: 1, z: 0 }), Some(0b010));
+    assert_eq!(morton_encode(&Vector3U64 { x: 0, y: 0, z: 1 }), Some(0b100));
+
+    for lod in &[0, 1, 5, MAX_LOD] {
+        for origin in &[voxel(*lod, 0, 0, 0), voxel(*lod, 12, 4, 96), voxel(*lod, MAX_LEVEL_COORD*4, 0, 8)] {
+            let key = level_key(origin, 4).unwrap();
+            assert_eq!(&level_key_origin(key, 4).unwrap(), origin);
+        }
+    }
+    assert_eq!(level_key(&voxel(MAX_LOD+1, 0, 0, 0), 4), None);
+    //a key made with a small level_length may name an origin that a large one can not reach
+    let far_key = level_key(&voxel(0, MAX_LEVEL_COORD, 0, 0), 1).unwrap();
+    assert_eq!(level_key_origin(far_key, 1 << 30), None);
+
+    //the coarsest voxel of a finer lod still sorts after the farthest voxel of a coarser lod
+    let far_coarse = level_key(&voxel(1, MAX_LEVEL_COORD*4, MAX_LEVEL_COORD*4, MAX_LEVEL_COORD*4), 4).unwrap();
+    let near_fine = level_key(&voxel(0, 0, 0, 0), 4).unwrap();
+    assert!(far_coarse<near_fine);
+
+    let octree = HybridOctree::new(4, 2, Box::new(HashNoiseGenerator::new(0)));
+    for location in &[voxel(0, 6, 0, 2), voxel(3, 0, 0, 0), voxel(1, 2, 2, 2), voxel(0, 0, 0, 0), voxel(2, 4, 0, 0), voxel(1, 0, 0, 0), voxel(0, 2, 4, 0)] {
+        octree.load_level(location.clone()).unwrap();
+    }
+    //loading a level that already exists must not add another entry
+    octree.load_level(voxel(1, 3, 3, 3)).unwrap();
+    assert!(octree.load_level(voxel(4, 0, 0, 0)).is_err(), "Loading past the deepest lod should fail instead of panicking");
+    assert_eq!(octree.levels.len(), 7);
+    for pair in octree.levels.levels().windows(2) {
+        assert!(pair[0].ordinal<pair[1].ordinal);
+        assert!(pair[0].location.lod>=pair[1].location.lod);
+    }
+    for sorted_level in octree.levels.levels() {
+        assert_eq!(level_key_origin(sorted_level.ordinal, 2).unwrap(), sorted_level.location);
+    }
+    println!("Storage order passed");
+}
+
+#[allow(dead_code)]
+pub fn octree_index_math() {
+    let location = voxel(2, 5, 2, 7);
+    assert_eq!(parent(&location), voxel(3, 2, 1, 3));
+    assert_eq!(octant(&location), 0b101);
+    assert_eq!(child(&parent(&location), octant(&location)), Some(location.clone()));
+
+    let kids = children(&location).unwrap();
+    assert_eq!(kids.len(), 8);
+    for (i, kid) in kids.iter().enumerate() {
+        assert_eq!(parent(kid), location);
+        assert_eq!(octant(kid) as usize, i);
+    }
+    assert_eq!(children(&voxel(0, 1, 1, 1)), None);
+
+    let sibs = siblings(&location);
+    assert_eq!(sibs.len(), 7);
+    assert!(!sibs.contains(&location));
+    assert!(sibs.iter().all(|sibling| parent(sibling)==parent(&location)));
+
+    assert_eq!(to_lod(&location, 4), voxel(4, 1, 0, 1));
+    assert_eq!(to_lod(&location, 0), voxel(0, 20, 8, 28));
+    assert_eq!(to_lod(&to_lod(&location, 0), 2), location);
+
+    //octrees stop short of LODs whose voxels would not fit in LOD 0 coordinates
+    assert_eq!((max_level_depth(2), max_level_depth(4), max_level_depth(5), max_level_depth(1<<30)), (24, 23, 22, 1));
+    let far = max_voxel_coord(4);
+    assert_eq!(to_lod(&voxel(22, far, far, far), 0).vec.x as u128, (far as u128)<<22);
+
+    assert_eq!(level_origin(&voxel(1, 5, 3, 4), 4), voxel(1, 4, 0, 4));
+    for index in 0..64 {
+        let inner = location_in_level(&voxel(1, 4, 0, 4), index, 4);
+        assert_eq!(index_in_level(&inner, 4), index);
+        assert_eq!(level_origin(&inner, 4), voxel(1, 4, 0, 4));
+    }
+    println!("Octree index math passed");
+}
+
+#[allow(dead_code)]
+pub fn octree_point_queries() {
+    let octree = HybridOctree::new(3, 2, Box::new(HashNoiseGenerator::new(0)));
+    octree.load_level(voxel(2, 0, 0, 0)).unwrap();
+    octree.load_level(voxel(0, 1, 1, 1)).unwrap();
+
+    let fine = octree.query(voxel(0, 1, 1, 0)).unwrap();
+    assert_eq!(fine.location, voxel(0, 1, 1, 0));
+    assert_eq!(fine.level, voxel(0, 0, 0, 0));
+    assert_eq!(fine.index, 6);
+
+    //no level is loaded at lod 0 or 1 here, so the walk stops at lod 2
+    let coarse = octree.query(voxel(0, 3, 0, 0)).unwrap();
+    assert_eq!(coarse.location, voxel(2, 0, 0, 0));
+    assert_eq!(coarse.index, 0);
+
+    assert_eq!(octree.query(voxel(0, 8, 0, 0)), None);
+    assert_eq!(octree.query(voxel(3, 0, 0, 0)), None);
+    println!("Octree point queries passed");
+}
+
+#[allow(dead_code)]
+pub fn level_eviction() {
+    let octree = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(0)));
+    let locations = [voxel(0, 0, 0, 0), voxel(0, 2, 0, 0), voxel(0, 4, 0, 0), voxel(0, 6, 0, 0)];
+    for location in &locations {
+        octree.load_level(location.clone()).unwrap();
+    }
+    let level_usage = octree.level_memory_usage(&locations[0]).unwrap();
+    assert!(level_usage>=8*512);
+    assert_eq!(octree.memory_usage(), 4*level_usage);
+
+    //without storage a dirty level cannot be evicted, and an unloaded level is really gone
+    octree.get_level(locations[1].clone()).level.contents.write().unwrap().dirty = true;
+    assert!(octree.unload_level(locations[1].clone()).is_err(), "Unloading a dirty level without storage should fail");
+    octree.get_level(locations[1].clone()).level.contents.write().unwrap().dirty = false;
+    assert_eq!(octree.unload_level(locations[3].clone()), Ok(true));
+    assert_eq!(octree.unload_level(locations[3].clone()), Ok(false));
+    assert_eq!(octree.memory_usage(), 3*level_usage);
+
+    //touch level 0 so level 1 becomes the least recently used, then pin level 1 so level 2 is evicted instead
+    octree.set_storage(Some(Box::new(MemoryLevelStorage::default())));
+    octree.query(locations[0].clone()).unwrap();
+    assert!(octree.pin_level(&locations[1], true));
+    let evicted = octree.set_memory_budget(Some(2*level_usage)).unwrap();
+    assert_eq!(evicted, vec![locations[2].clone()]);
+    assert!(octree.find_level(&locations[1]).is_some());
+    assert!(octree.pin_level(&locations[1], false));
+
+    //a dirty level is written back before eviction and read back instead of being generated again
+    let (edited, _loaded) = octree.with_level_mut(&locations[1], |contents| {
+        contents.data.material[0] = MaterialComposition::from_weights(&[7; 512]);
+        contents.dirty = true;
+        contents.data.material[0].clone()
+    }).unwrap();
+    octree.query(locations[0].clone()).unwrap();
+    assert_eq!(octree.set_memory_budget(Some(level_usage)).unwrap(), vec![locations[1].clone()]);
+    octree.load_level(locations[1].clone()).unwrap();
+    assert_eq!(octree.get_level(locations[1].clone()).level.contents.read().unwrap().data.material[0], edited);
+
+    //loading keeps the new level and evicts the older one instead, and evicted levels leave no entries behind
+    octree.load_level(locations[3].clone()).unwrap();
+    assert_eq!(octree.loaded_level_count(), 1);
+    assert_eq!(octree.levels.len(), 1);
+    assert_eq!(octree.query(locations[3].clone()).unwrap().level, locations[3]);
+
+    //a stored level with the wrong number of Particles is refused, and leaves no placeholder behind
+    let mut storage = MemoryLevelStorage::default();
+    storage.write_level(&locations[0], &empty_particle_vec(7)).unwrap();
+    let octree = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(0)));
+    octree.set_storage(Some(Box::new(storage)));
+    assert!(octree.load_level(locations[0].clone()).is_err(), "A level of the wrong length should not load");
+    assert_eq!(octree.levels.len(), 0);
+    println!("Level eviction passed");
+}
+
+#[allow(dead_code)]
+pub fn region_storage_round_trip() {
+    let directory = std::env::temp_dir().join("molecule_engine_region_storage_round_trip");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let octree = HybridOctree::new(3, 2, Box::new(HashNoiseGenerator::new(0)));
+    octree.set_storage(Some(Box::new(RegionStorage::new(&directory, 2).unwrap())));
+    //the first two share a region file, the others each get their own
+    let locations = [voxel(0, 0, 0, 0), voxel(0, 2, 4, 0), voxel(0, 16, 0, 0), voxel(2, 0, 0, 0)];
+    for location in &locations {
+        octree.load_level(location.clone()).unwrap();
+    }
+    octree.with_level_mut(&locations[1], |contents| {
+        contents.data.material[3] = MaterialComposition::from_weights(&[9; 512]);
+        contents.dirty = true;
+    }).unwrap();
+    octree.get_level(locations[2].clone()).level.contents.write().unwrap().dirty = true;
+    assert_eq!(octree.save_dirty_levels().unwrap(), 2);
+    assert_eq!(octree.save_dirty_levels().unwrap(), 0);
+    assert!(octree.save_level(&locations[0]).unwrap());
+    assert!(!octree.save_level(&voxel(1, 0, 0, 0)).unwrap());
+
+    let storage = RegionStorage::new(&directory, 2).unwrap();
+    let region = storage.read_region(&storage.region_path(&locations[0])).unwrap().unwrap();
+    assert_eq!(region.entries.len(), 2);
+    assert_eq!(region.engine_version, molecule_engine::get_engine_version());
+    assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+
+    //a fresh octree reads the saved levels back rather than generating them
+    let restored = HybridOctree::new(3, 2, Box::new(HashNoiseGenerator::new(0)));
+    restored.set_storage(Some(Box::new(storage)));
+    for location in &locations[0..3] {
+        restored.load_level(location.clone()).unwrap();
+        assert_eq!(
+            restored.get_level(location.clone()).level.contents.read().unwrap().data,
+            octree.get_level(location.clone()).level.contents.read().unwrap().data
+        );
+    }
+
+    //rewriting a level appends its payload and patches its slot, until the space left behind outweighs the levels in use
+    let mut storage = RegionStorage::new(&directory, 2).unwrap();
+    let path = storage.region_path(&locations[0]);
+    let compact_len = std::fs::metadata(&path).unwrap().len();
+    let sorted_level = octree.get_level(locations[0].clone());
+    let contents = sorted_level.level.contents.read().unwrap();
+    storage.write_level(&locations[0], &contents.data).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), compact_len+storage.compression.encode(&contents.data).len() as u64);
+    assert_eq!(storage.read_level(&locations[0]).unwrap().as_ref(), Some(&contents.data));
+    let compacted = (0..10).any(|_i| {
+        storage.write_level(&locations[0], &contents.data).unwrap();
+        std::fs::metadata(&path).unwrap().len()==compact_len
+    });
+    assert!(compacted, "A region file should be written out compactly once it is mostly dead space");
+    assert_eq!(storage.read_level(&locations[1]).unwrap().as_ref(), Some(&octree.get_level(locations[1].clone()).level.contents.read().unwrap().data));
+
+    let mut truncated = region.write();
+    truncated.truncate(truncated.len()-1);
+    assert!(RegionFile::read(&truncated).is_err(), "A truncated region file should not parse");
+    std::fs::remove_dir_all(&directory).unwrap();
+    println!("Region storage round trip passed");
+}
+
+#[allow(dead_code)]
+pub fn seeded_generation() {
+    //these values are part of the world format, if they change then every saved seed produces a different world
+    assert_eq!(mix64(0), 0);
+    assert_eq!(hash_u64s(0, &[]), 0xe220a8397b1dcdaf);//the first output of SplitMix64 seeded with 0
+    assert_eq!(hash_u64s(42, &[1, 2, 3]), 0x08c021dea3b72edd);
+
+    let locations = [voxel(0, 0, 0, 0), voxel(0, 6, 2, 4), voxel(1, 2, 0, 0)];
+    let first = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1234)));
+    let second = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1234)));
+    let other_seed = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1235)));
+    //load in a different order, so nothing but the seed and level can influence the result
+    for location in &locations {
+        first.load_level(location.clone()).unwrap();
+        other_seed.load_level(location.clone()).unwrap();
+    }
+    for location in locations.iter().rev() {
+        second.load_level(location.clone()).unwrap();
+    }
+    for location in &locations {
+        let level = first.get_level(location.clone());
+        let contents = level.level.contents.read().unwrap();
+        assert_eq!(contents.data.len(), 8);
+        assert_eq!(contents.data, second.get_level(location.clone()).level.contents.read().unwrap().data);
+        assert!(contents.data != other_seed.get_level(location.clone()).level.contents.read().unwrap().data);
+    }
+
+    let layered = LayeredGenerator::default()
+        .with(Box::new(HashNoiseGenerator::new(1234)))
+        .with(Box::new(FillGenerator::new(3, 0).unwrap()))
+        .with(Box::new(FillGenerator::new(5, 200).unwrap()));
+    let composed = HybridOctree::new(2, 2, Box::new(layered));
+    composed.load_level(locations[1].clone()).unwrap();
+    let composed_level = composed.get_level(locations[1].clone());
+    let noise_level = first.get_level(locations[1].clone());
+    let composed_data = &composed_level.level.contents.read().unwrap().data.material;
+    let noise_data = &noise_level.level.contents.read().unwrap().data.material;
+    for (composed_material, noise_material) in composed_data.iter().zip(noise_data.iter()) {
+        assert_eq!(composed_material.weight(3), 0);
+        assert_eq!(composed_material.weight(5), 200);
+        assert_eq!(composed_material.weight(0), noise_material.weight(0));
+    }
+    assert!(FillGenerator::new(512, 255).is_err(), "Filling with a material past MATERIAL_COUNT should fail");
+    println!("Seeded generation passed");
+}
+
+#[allow(dead_code)]
+pub fn voxel_editing() {
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    let material_at = |octree:&HybridOctree, x, y, z| octree.query(voxel(0, x, y, z)).map(|particle| particle.material);
+
+    //a sphere around a corner shared by eight levels loads and touches all of them
+    let sphere = Sphere::new([4.0, 4.0, 4.0], 2.5);
+    let report = octree.fill_shape(&sphere, 0, 3).unwrap();
+    assert_eq!(report.loaded_levels.len(), 8);
+    assert_eq!(report.touched_levels, report.loaded_levels);
+    let mut inside = 0;
+    for x in 0..8u64 {
+        for y in 0..8u64 {
+            for z in 0..8u64 {
+                let offset = [x as f64+0.5-4.0, y as f64+0.5-4.0, z as f64+0.5-4.0];
+                if offset.iter().map(|o| o*o).sum::<f64>()<=6.25 {
+                    inside+=1;
+                    assert_eq!(material_at(&octree, x, y, z), Some(one_hot_material(3)));
+                } else {
+                    assert_eq!(material_at(&octree, x, y, z), Some(MaterialComposition::Empty));
+                }
+            }
+        }
+    }
+    assert_eq!(report.changed_voxels, inside);
+    assert!(octree.levels.levels().iter().all(|sorted_level| sorted_level.level.contents.read().unwrap().dirty));
+
+    //filling again changes nothing, and a level without any voxel inside the shape is not loaded
+    let report = octree.fill_shape(&sphere, 0, 3).unwrap();
+    assert_eq!(report.changed_voxels, 0);
+    assert!(report.touched_levels.is_empty());
+    assert!(octree.fill_shape(&Sphere::new([4.0, 4.0, 4.0], 0.1), 0, 1).unwrap().loaded_levels.is_empty());
+
+    octree.carve_shape(&Sphere::new([4.0, 4.0, 4.0], 1.0), 0).unwrap();
+    assert_eq!(material_at(&octree, 4, 4, 4), Some(MaterialComposition::Empty));
+    assert_eq!(material_at(&octree, 5, 4, 4), Some(one_hot_material(3)));
+
+    let report = octree.replace_material(&Aabb::new([0.0, 0.0, 0.0], [8.0, 8.0, 4.0]), 0, 3, 7).unwrap();
+    assert_eq!(report.touched_levels.len(), 4);
+    assert_eq!(material_at(&octree, 4, 4, 2), Some(one_hot_material(7)));
+    assert_eq!(material_at(&octree, 4, 4, 5), Some(one_hot_material(3)));
+
+    let report = octree.fill_shape(&Cylinder::new([0.5, 0.5, 0.0], [0.5, 0.5, 12.0], 0.4), 0, 9).unwrap();
+    assert_eq!(report.changed_voxels, 12);
+    assert_eq!(report.loaded_levels, vec![voxel(0, 0, 0, 8)]);
+    assert_eq!(material_at(&octree, 0, 0, 11), Some(one_hot_material(9)));
+
+    //an arbitrary SDF, here a half space cut down to a box by its bounds
+    let plane = SdfFn::new(|point:[f64;3]| point[1]-1.0, Aabb::new([8.0, 0.0, 0.0], [12.0, 4.0, 4.0]));
+    assert_eq!(octree.fill_shape(&plane, 0, 2).unwrap().changed_voxels, 4*4);
+
+    let report = octree.set_voxel_material(&voxel(1, 5, 0, 0), 4).unwrap();
+    assert_eq!(report.changed_voxels, 1);
+    assert_eq!(report.touched_levels, vec![voxel(1, 4, 0, 0)]);
+    assert_eq!(octree.query(voxel(1, 5, 0, 0)).unwrap().material, one_hot_material(4));
+    assert_eq!(octree.query(voxel(1, 4, 0, 0)).unwrap().material, MaterialComposition::Empty);
+
+    assert!(octree.fill_box(&Aabb::new([0.0; 3], [1.0; 3]), 0, 512).is_err(), "Filling with a material past MATERIAL_COUNT should fail");
+    assert!(octree.edit_shape(&sphere, 2, &Brush::Carve).is_err(), "Editing past the deepest LOD should fail");
+    assert!(octree.carve_shape(&Sphere::new([-10.0, 4.0, 4.0], 2.0), 0).unwrap().loaded_levels.is_empty());
+
+    //unbounded shapes are refused up front rather than walked level by level, and ranges stop at the addressable edge
+    let everywhere = SdfFn::new(|_point:[f64;3]| -1.0, Aabb::new([f64::NEG_INFINITY; 3], [f64::INFINITY; 3]));
+    assert!(octree.fill_shape(&everywhere, 0, 2).is_err(), "Editing an unbounded shape should fail");
+    let range = voxel_range(&Aabb::new([0.0; 3], [f64::INFINITY; 3]), 0, 4).unwrap();
+    assert_eq!(range.last.x, max_voxel_coord(4));
+    assert!(level_key(&voxel(0, range.last.x, 0, 0), 4).is_some());
+    assert_eq!(level_key(&voxel(0, range.last.x+1, 0, 0), 4), None);
+    assert!(voxel_range(&Aabb::new([1e30; 3], [2e30; 3]), 0, 4).is_none());
+    let range = voxel_range(&Aabb::new([1.0, 0.0, 0.0], [9.0, 2.0, 2.0]), 0, 4).unwrap();
+    assert_eq!(range.level_count(4), 3);
+    assert_eq!(range.level_origins(4).collect::<Vec<_>>(), vec![voxel(0, 0, 0, 0), voxel(0, 4, 0, 0), voxel(0, 8, 0, 0)]);
+    let part = range.within_level(&voxel(0, 4, 0, 0), 4).unwrap();
+    assert_eq!(part.voxels().count(), 4*2*2);
+    assert_eq!(part.voxels().next(), Some(voxel(0, 4, 0, 0)));
+    assert_eq!(range.within_level(&voxel(0, 12, 0, 0), 4), None);
+    println!("Voxel editing passed");
+}
+
+#[allow(dead_code)]
+pub fn raycasting() {
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    //lod 1 summarizes lod 0, so the solid voxel at lod 0 needs a solid parent for the walk to descend into it
+    octree.set_voxel_material(&voxel(0, 5, 2, 2), 3).unwrap();
+    octree.set_voxel_material(&voxel(1, 2, 1, 1), 3).unwrap();
+    //no level is loaded at lod 0 beneath this one, so it is the finest there is
+    octree.set_voxel_material(&voxel(1, 3, 3, 1), 5).unwrap();
+    let options = RaycastOptions::default();
+
+    let hit = octree.raycast(&Ray::new([-3.0, 2.5, 2.5], [2.0, 0.0, 0.0]), &options).unwrap();
+    assert_eq!(hit.voxel, voxel(0, 5, 2, 2));
+    assert_eq!(hit.normal, [-1, 0, 0]);
+    assert!((hit.distance-8.0).abs()<1e-6);
+    assert!((hit.position[0]-5.0).abs()<1e-6);
+    assert_eq!(hit.material, 3);
+
+    let hit = octree.raycast(&Ray::new([5.5, 10.0, 2.5], [0.0, -1.0, 0.0]), &options).unwrap();
+    assert_eq!(hit.voxel, voxel(0, 5, 2, 2));
+    assert_eq!(hit.normal, [0, 1, 0]);
+    assert!((hit.distance-7.0).abs()<1e-6);
+
+    let hit = octree.raycast(&Ray::new([-3.0, 6.5, 2.5], [1.0, 0.0, 0.0]), &options).unwrap();
+    assert_eq!(hit.voxel, voxel(1, 3, 3, 1));
+    assert_eq!(hit.material, 5);
+    assert!((hit.distance-9.0).abs()<1e-6);
+
+    //a diagonal ray passing above the solid voxels, then one clipping a corner
+    assert_eq!(octree.raycast(&Ray::new([0.5, 0.5, 6.5], [1.0, 1.0, 0.0]), &options), None);
+    let hit = octree.raycast(&Ray::new([4.6, 1.0, 2.5], [1.0, 1.0, 0.0]), &options).unwrap();
+    assert_eq!(hit.voxel, voxel(0, 5, 2, 2));
+    assert_eq!(hit.normal, [0, -1, 0]);
+
+    let ignore_stone = RaycastOptions {
+        ignored_materials: vec![3],
+        ..RaycastOptions::default()
+    };
+    assert_eq!(octree.raycast(&Ray::new([-3.0, 2.5, 2.5], [1.0, 0.0, 0.0]), &ignore_stone), None);
+    let short = RaycastOptions {
+        max_distance: 7.5,
+        ..RaycastOptions::default()
+    };
+    assert_eq!(octree.raycast(&Ray::new([-3.0, 2.5, 2.5], [1.0, 0.0, 0.0]), &short), None);
+
+    let inside = octree.raycast(&Ray::new([5.5, 2.5, 2.5], [0.0, 0.0, 1.0]), &options).unwrap();
+    assert_eq!(inside.distance, 0.0);
+    assert_eq!(inside.normal, [0, 0, 0]);
+    assert_eq!(octree.raycast(&Ray::new([5.5, 2.5, 2.5], [0.0, 0.0, 0.0]), &options), None);
+    assert_eq!(octree.raycast(&Ray::new([-3.0, 2.5, 2.5], [-1.0, 0.0, 0.0]), &options), None);
+    println!("Raycasting passed");
+}
+
+#[allow(dead_code)]
+pub fn range_queries() {
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    let loaded = [voxel(0, 0, 0, 0), voxel(0, 4, 0, 0), voxel(0, 0, 4, 0), voxel(0, 4, 4, 0)];
+    for location in &loaded {
+        octree.load_level(location.clone()).unwrap();
+    }
+    let far = voxel(0, 16, 16, 16);
+    octree.load_level(far.clone()).unwrap();
+    octree.load_level(voxel(1, 0, 0, 0)).unwrap();
+    octree.set_voxel_material(&voxel(0, 4, 4, 1), 6).unwrap();
+    let far_access = || octree.levels.levels().iter().find(|sorted_level| sorted_level.location==far).unwrap().level.last_access.load(std::sync::atomic::Ordering::Relaxed);
+    let far_before = far_access();
+
+    //every voxel of the loaded lod 0 levels with its center inside the shape
+    let expected = |shape:&dyn Sdf| {
+        let mut inside = vec![];
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..4 {
+                    if shape.distance(voxel_center(&voxel(0, x, y, z)))<=0.0 {
+                        inside.push(voxel(0, x, y, z));
+                    }
+                }
+            }
+        }
+        inside.sort_by_key(|location| (location.vec.x, location.vec.y, location.vec.z));
+        inside
+    };
+    let found = |results:Vec<ParticleRef>| {
+        let mut locations:Vec<VoxelLocation> = results.iter().map(|particle| {
+            assert_eq!(level_origin(&particle.location, 4), particle.level);
+            particle.location.clone()
+        }).collect();
+        locations.sort_by_key(|location| (location.vec.x, location.vec.y, location.vec.z));
+        locations
+    };
+
+    let bounds = Aabb::new([2.0, 2.0, 0.0], [6.0, 6.0, 2.0]);
+    let in_box:Vec<_> = octree.query_box(&bounds, 0).collect();
+    assert_eq!(in_box.len(), 4*4*2);
+    let mut levels:Vec<VoxelLocation> = in_box.iter().map(|particle| particle.level.clone()).collect();
+    levels.dedup();
+    assert_eq!(levels.len(), 4);
+    assert_eq!(found(in_box.clone()), expected(&bounds));
+    let edited = in_box.iter().find(|particle| particle.location==voxel(0, 4, 4, 1)).unwrap();
+    assert_eq!(edited.material, one_hot_material(6));
+
+    let sphere = Sphere::new([4.0, 4.0, 1.0], 1.5);
+    assert_eq!(found(octree.query_sphere(&sphere, 0).collect()), expected(&sphere));
+
+    let frustum = Frustum::from_corners(
+        [[3.0, 3.0, 0.75], [5.0, 3.0, 0.75], [5.0, 5.0, 0.75], [3.0, 5.0, 0.75]],
+        [[1.0, 1.0, 3.5], [7.0, 1.0, 3.5], [7.0, 7.0, 3.5], [1.0, 7.0, 3.5]],
+    );
+    let in_frustum = found(octree.query_frustum(&frustum, 0).collect());
+    assert_eq!(in_frustum, expected(&frustum));
+    assert!(in_frustum.contains(&voxel(0, 4, 4, 1)));
+    assert!(!in_frustum.contains(&voxel(0, 0, 0, 1)));
+    assert!(!in_frustum.contains(&voxel(0, 4, 4, 0)));
+
+    //the level far outside every shape was never looked at
+    assert_eq!(far_access(), far_before);
+    assert_eq!(octree.query_box(&Aabb::new([0.0; 3], [8.0; 3]), 1).count(), 4*4*4);
+    assert_eq!(octree.query_box(&Aabb::new([0.0; 3], [8.0; 3]), 2).count(), 0);
+    assert_eq!(octree.query_sphere(&Sphere::new([-5.0, 0.0, 0.0], 2.0), 0).count(), 0);
+    println!("Range queries passed");
+}
+
+#[allow(dead_code)]
+pub fn lod_downsampling() {
+    let empty = MaterialComposition::Empty;
+    let stone = one_hot_material(3);
+    let dirt = one_hot_material(4);
+    let mixed = MaterialComposition::from_entries([(3, 100), (4, 50)]);
+    let majority = DownsamplePolicy::Majority;
+    assert_eq!(majority.aggregate(&[stone.clone(), stone.clone(), dirt.clone(), empty.clone(), empty.clone()]), stone);
+    assert_eq!(majority.aggregate(&[stone.clone(), dirt.clone(), empty.clone(), empty.clone()]), empty);
+    assert_eq!(majority.aggregate(&[dirt.clone(), stone.clone()]), stone);
+    assert_eq!(majority.aggregate(&[mixed.clone(), empty.clone()]), stone);
+    let average = DownsamplePolicy::Average.aggregate(&[stone.clone(), mixed.clone(), empty.clone(), empty.clone()]);
+    assert_eq!((average.weight(3), average.weight(4)), (89, 13));
+    let densest = DownsamplePolicy::MaxDensity.aggregate(&[mixed.clone(), dirt.clone(), empty.clone()]);
+    assert_eq!((densest.weight(3), densest.weight(4)), (100, 255));
+    assert_eq!(DownsamplePolicy::MaxDensity.aggregate(&[]), empty);
+
+    let octree = HybridOctree::new(3, 4, Box::new(EmptyGenerator));
+    octree.fill_box(&Aabb::new([0.0; 3], [2.0; 3]), 0, 3).unwrap();
+    octree.set_voxel_material(&voxel(0, 2, 0, 0), 4).unwrap();
+    assert_eq!(octree.lod_dirty.lock().unwrap().len(), 1);
+    let report = octree.downsample_dirty(DownsamplePolicy::MaxDensity).unwrap();
+    assert_eq!(report.rebuilt_levels, vec![voxel(1, 0, 0, 0), voxel(2, 0, 0, 0)]);
+    assert_eq!(report.loaded_levels, report.rebuilt_levels);
+    assert!(octree.lod_dirty.lock().unwrap().is_empty());
+    assert_eq!(octree.query(voxel(1, 0, 0, 0)).unwrap().material, stone);
+    assert_eq!(octree.query(voxel(1, 1, 0, 0)).unwrap().material, dirt);
+    assert_eq!(octree.query(voxel(1, 1, 1, 0)).unwrap().material, empty);
+    let top = octree.query(voxel(2, 0, 0, 0)).unwrap().material;
+    assert_eq!((top.weight(3), top.weight(4)), (255, 255));
+    assert_eq!(octree.downsample_dirty(DownsamplePolicy::MaxDensity).unwrap().rebuilt_levels, vec![]);
+
+    //with every LOD consistent, a ray finds the fine voxels through the coarse ones
+    let hit = octree.raycast(&Ray::new([-3.0, 0.5, 0.5], [1.0, 0.0, 0.0]), &RaycastOptions::default()).unwrap();
+    assert_eq!(hit.voxel, voxel(0, 0, 0, 0));
+    assert_eq!(hit.material, 3);
+
+    //removing the lone dirt voxel clears it from every coarser LOD
+    octree.carve_shape(&Sphere::new([2.5, 0.5, 0.5], 0.5), 0).unwrap();
+    let report = octree.downsample_dirty(DownsamplePolicy::MaxDensity).unwrap();
+    assert_eq!(report.rebuilt_levels, vec![voxel(1, 0, 0, 0), voxel(2, 0, 0, 0)]);
+    assert!(report.loaded_levels.is_empty());
+    assert_eq!(octree.query(voxel(1, 1, 0, 0)).unwrap().material, empty);
+    assert_eq!(octree.query(voxel(2, 0, 0, 0)).unwrap().material, stone);
+
+    //a change that leaves the parent as it was stops before the coarsest LOD
+    octree.set_voxel_material(&voxel(0, 1, 1, 1), 4).unwrap();
+    let report = octree.downsample_dirty(DownsamplePolicy::Majority).unwrap();
+    assert_eq!(report.rebuilt_levels, vec![]);
+
+    let averaged = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    averaged.set_voxel_material(&voxel(0, 2, 0, 0), 4).unwrap();
+    averaged.downsample_dirty(DownsamplePolicy::Average).unwrap();
+    assert_eq!(averaged.query(voxel(1, 1, 0, 0)).unwrap().material.weight(4), 32);
+    println!("LOD downsampling passed");
+}
+
+#[allow(dead_code)]
+pub fn concurrent_level_map() {
+    //threads load, edit and read the same levels in different orders while a tight budget keeps evicting them
+    let octree = std::sync::Arc::new(HybridOctree::new(2, 4, Box::new(HashNoiseGenerator::new(7))));
+    octree.set_storage(Some(Box::new(MemoryLevelStorage::default())));
+    let level_usage = {
+        octree.load_level(voxel(0, 0, 0, 0)).unwrap();
+        octree.level_memory_usage(&voxel(0, 0, 0, 0)).unwrap()
+    };
+    octree.set_memory_budget(Some(6*level_usage)).unwrap();
+    let threads:Vec<_> = (0..4u64).map(|thread| {
+        let octree = octree.clone();
+        std::thread::spawn(move || {
+            for step in 0..32u64 {
+                let level = (step+thread*5)%16;
+                octree.load_level(voxel(0, level*4, 0, 0)).unwrap();
+                octree.set_voxel_material(&voxel(0, level*4+thread, 1, 1), thread as u16+1).unwrap();
+                octree.query(voxel(0, level*4, 2, 2));
+            }
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(octree.memory_usage()<=7*level_usage);
+
+    //no edit was lost to a level being loaded twice at once, or to an eviction racing an edit
+    octree.set_memory_budget(None).unwrap();
+    for level in 0..16u64 {
+        octree.load_level(voxel(0, level*4, 0, 0)).unwrap();
+        for thread in 0..4u64 {
+            assert_eq!(octree.query(voxel(0, level*4+thread, 1, 1)).unwrap().material, one_hot_material(thread as u16+1));
+        }
+    }
+    assert_eq!(octree.levels.len(), 16);
+    println!("Concurrent level map passed");
+}
+
+#[allow(dead_code)]
+pub fn level_streaming() {
+    //LOD 0 levels are 4 voxels wide and LOD 1 levels 8, so a radius of 2 around the middle of a level reaches its 6 face neighbours but no edge or corner
+    let objekt = HybridOctreeObjekt::new(String::from("world"), HybridOctree::new(2, 4, Box::new(HashNoiseGenerator::new(3))));
+    let octree = objekt.inner();
+    let objekt_list:ObjektList = Arc::new(Mutex::new(vec![Arc::new(RwLock::new(objekt))]));
+    let viewers = Arc::new(RwLock::new(vec![[6.0, 6.0, 6.0]]));
+    let mut streaming = LevelStreaming::new(String::from("streaming"), String::from("world"), viewers.clone(), vec![2.0, 0.0], 2);
+    streaming.unload_margin = 1.0;
+    streaming.init(objekt_list);
+
+    let wait_for = |streaming:&mut LevelStreaming, expected:&[VoxelLocation], loaded_count:usize| {
+        let deadline = Instant::now()+Duration::from_secs(10);
+        loop {
+            assert!(matches!(streaming.tick(), TaskControlFlow::Continue));
+            let all_loaded = expected.iter().all(|origin| octree.find_level(origin).is_some_and(|sorted_level| sorted_level.level.contents.read().unwrap().loaded));
+            if all_loaded && streaming.pending()==0 && octree.loaded_level_count()==loaded_count {
+                return;
+            }
+            assert!(Instant::now()<deadline, "Streaming did not settle, {} levels loaded", octree.loaded_level_count());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    };
+    let around = |x:u64, y:u64, z:u64| vec![
+        voxel(0, x, y, z),
+        voxel(0, x-4, y, z),
+        voxel(0, x+4, y, z),
+        voxel(0, x, y-4, z),
+        voxel(0, x, y+4, z),
+        voxel(0, x, y, z-4),
+        voxel(0, x, y, z+4),
+    ];
+
+    let mut expected = around(4, 4, 4);
+    expected.push(voxel(1, 0, 0, 0));
+    wait_for(&mut streaming, &expected, 8);
+    assert!(!octree.find_level(&voxel(0, 0, 0, 0)).is_some_and(|sorted_level| sorted_level.level.contents.read().unwrap().loaded));
+
+    //the viewer moves away: the old levels are dropped, except for the pinned one, and the ones around the viewer are loaded
+    octree.pin_level(&voxel(0, 4, 4, 4), true);
+    *viewers.write().unwrap() = vec![[102.0, 6.0, 6.0]];
+    let mut expected = around(100, 4, 4);
+    expected.push(voxel(1, 48, 0, 0));
+    wait_for(&mut streaming, &expected, 9);
+    assert!(octree.find_level(&voxel(0, 4, 4, 4)).unwrap().level.contents.read().unwrap().loaded);
+    assert!(!octree.find_level(&voxel(0, 8, 4, 4)).is_some_and(|sorted_level| sorted_level.level.contents.read().unwrap().loaded));
+
+    //a second viewer keeps its own surroundings loaded next to the first one's
+    octree.pin_level(&voxel(0, 4, 4, 4), false);
+    viewers.write().unwrap().push([6.0, 6.0, 6.0]);
+    let mut both = around(100, 4, 4);
+    both.extend(around(4, 4, 4));
+    wait_for(&mut streaming, &both, 16);
+
+    //levels with unsaved changes and nowhere to write them stay loaded
+    octree.with_level_mut(&voxel(0, 4, 4, 4), |contents| contents.dirty = true).unwrap();
+    *viewers.write().unwrap() = vec![[102.0, 6.0, 6.0]];
+    wait_for(&mut streaming, &around(100, 4, 4), 9);
+    assert!(octree.find_level(&voxel(0, 4, 4, 4)).unwrap().level.contents.read().unwrap().loaded);
+    assert!(streaming.failures().is_empty());
+
+    //levels that fail to load are reported instead of printed, and retried while they are wanted
+    let mut storage = MemoryLevelStorage::default();
+    storage.levels.insert(voxel(0, 200, 4, 4), vec![1, 2, 3]);
+    octree.set_storage(Some(Box::new(storage)));
+    *viewers.write().unwrap() = vec![[202.0, 6.0, 6.0]];
+    let deadline = Instant::now()+Duration::from_secs(10);
+    while streaming.failures().is_empty() {
+        assert!(matches!(streaming.tick(), TaskControlFlow::Continue));
+        assert!(Instant::now()<deadline, "Streaming reported no failure");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let failures = streaming.failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, voxel(0, 200, 4, 4));
+    assert!(failures[0].1.starts_with("Could not load level"), "{}", failures[0].1);
+    assert_eq!(streaming.panicked_workers(), 0);
+    assert_eq!(streaming.over_budget(), 0);
+
+    //a memory budget too small for every wanted level keeps the nearest ones loaded, rather than loading and evicting them in turn
+    let objekt = HybridOctreeObjekt::new(String::from("budget"), HybridOctree::new(2, 4, Box::new(HashNoiseGenerator::new(3))));
+    let octree = objekt.inner();
+    octree.load_level(voxel(0, 4, 4, 4)).unwrap();
+    let level_usage = octree.level_memory_usage(&voxel(0, 4, 4, 4)).unwrap();
+    octree.unload_level(voxel(0, 4, 4, 4)).unwrap();
+    octree.set_memory_budget(Some(3*level_usage)).unwrap();
+    let objekt_list:ObjektList = Arc::new(Mutex::new(vec![Arc::new(RwLock::new(objekt))]));
+    let viewers = Arc::new(RwLock::new(vec![[6.0, 6.0, 6.0]]));
+    let mut streaming = LevelStreaming::new(String::from("streaming"), String::from("budget"), viewers, vec![2.0, 0.0], 2);
+    streaming.init(objekt_list);
+    let deadline = Instant::now()+Duration::from_secs(10);
+    let mut idle_ticks = 0;
+    while idle_ticks<3 {
+        assert!(matches!(streaming.tick(), TaskControlFlow::Continue));
+        idle_ticks = if streaming.pending()==0 {idle_ticks+1} else {0};
+        assert!(Instant::now()<deadline, "Streaming kept loading levels over its memory budget");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(streaming.over_budget()>=5, "{} levels left out", streaming.over_budget());
+    assert!(octree.loaded_level_count()<=3);
+    assert!(octree.find_level(&voxel(0, 4, 4, 4)).unwrap().level.contents.read().unwrap().loaded);
+    println!("Level streaming passed");
+}
+
+#[allow(dead_code)]
+pub fn material_compositions() {
+    //the smallest form is picked, and equality ignores the form
+    assert!(matches!(MaterialComposition::from_weights(&[0; 512]), MaterialComposition::Empty));
+    let single = MaterialComposition::from_entries([(7, 0), (5, 20), (5, 90)]);
+    assert!(matches!(single, MaterialComposition::Single(5, 90)));
+    let mut weights = vec![0u8; 512];
+    weights[5] = 90;
+    assert_eq!(MaterialComposition::Dense(weights.clone().into_boxed_slice()), single);
+    assert_eq!(single.weights(), weights);
+    assert_eq!(MaterialComposition::Sparse(vec![(5, 90), (8, 0)].into_boxed_slice()), single);
+    assert!(MaterialComposition::Sparse(vec![(2, 0)].into_boxed_slice()).is_empty());
+
+    let mut composition = MaterialComposition::Empty;
+    for material in 0..40u16 {
+        composition.set_weight(material*3, material as u8+1);
+    }
+    assert!(matches!(composition, MaterialComposition::Dense(_)));
+    assert_eq!(composition.material_count(), 40);
+    assert_eq!((composition.weight(39*3), composition.weight(1), composition.weight(600)), (40, 0, 0));
+    assert_eq!(composition.dominant(&[117]), Some(114));
+    for material in 0..30u16 {
+        composition.set_weight(material*3, 0);
+    }
+    assert!(matches!(composition, MaterialComposition::Sparse(_)));
+    assert_eq!(composition.iter().collect::<Vec<_>>(), (30..40u16).map(|material| (material*3, material as u8+1)).collect::<Vec<_>>());
+    assert_eq!(composition.total_weight(&[]), (31..=40).sum::<u32>());
+    composition.set_weight(600, 1);
+    assert_eq!(composition.material_count(), 10);
+    composition.clear();
+    assert!(composition.is_empty());
+
+    //a Dense only ever holds more materials than a sparse list may
+    let mut composition = MaterialComposition::from_entries((0..33u16).map(|material| (material, 1)));
+    assert!(matches!(composition, MaterialComposition::Dense(_)));
+    assert!(composition.validate().is_ok());
+    composition.set_weight(0, 0);
+    assert!(matches!(composition, MaterialComposition::Sparse(_)));
+    assert!(matches!(MaterialComposition::from_entries((0..33u16).map(|material| (material%32, 1))), MaterialComposition::Sparse(_)));
+    assert!(MaterialComposition::Dense(vec![0; 512].into_boxed_slice()).validate().is_err());
+    let mut weights = vec![0u8; 512];
+    weights[3] = 9;
+    let few = MaterialComposition::Dense(weights.into_boxed_slice());
+    assert!(few.validate().is_err());
+    assert!(matches!(few.normalized(), MaterialComposition::Single(3, 9)));
+
+    //a level of one material costs a fraction of one holding every material
+    let octree = HybridOctree::new(1, 8, Box::new(FillGenerator::new(3, 255).unwrap()));
+    let noisy = HybridOctree::new(1, 8, Box::new(HashNoiseGenerator::new(0)));
+    octree.load_level(voxel(0, 0, 0, 0)).unwrap();
+    noisy.load_level(voxel(0, 0, 0, 0)).unwrap();
+    let report = octree.level_material_storage(&voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!((report.empty, report.single, report.sparse, report.dense), (0, 512, 0, 0));
+    let noisy_report = noisy.level_material_storage(&voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!(noisy_report.dense, 512);
+    assert!(noisy_report.bytes>=512*512);
+    assert!(report.bytes*10<noisy_report.bytes);
+    assert!(octree.level_memory_usage(&voxel(0, 0, 0, 0)).unwrap()*10<noisy.level_memory_usage(&voxel(0, 0, 0, 0)).unwrap());
+
+    //storage still holds every weight, so levels read back the same whatever form they were written in
+    let mixed = MaterialComposition::from_entries([(3, 100), (4, 50)]);
+    octree.with_level_mut(&voxel(0, 0, 0, 0), |contents| {
+        contents.data.material[1] = mixed.clone();
+        contents.data.material[2] = MaterialComposition::Empty;
+        contents.mark_changed();
+    }).unwrap();
+    octree.set_storage(Some(Box::new(MemoryLevelStorage::default())));
+    assert!(octree.save_level(&voxel(0, 0, 0, 0)).unwrap());
+    assert!(octree.unload_level(voxel(0, 0, 0, 0)).unwrap());
+    octree.load_level(voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!(octree.query(voxel(0, 0, 0, 1)).unwrap().material, mixed);
+    assert!(octree.query(voxel(0, 0, 0, 2)).unwrap().material.is_empty());
+    assert_eq!(octree.query(voxel(0, 0, 0, 3)).unwrap().material, one_hot_material(3));
+    let report = octree.level_material_storage(&voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!((report.empty, report.single, report.sparse, report.dense), (1, 510, 1, 0));
+    println!("Material compositions passed");
+}
+
+//flips every bit, which is enough to tell whether a compressor other than the built in one was used
+struct InvertingCompressor;
+
+impl ByteCompressor for InvertingCompressor {
+    fn id(&self) -> u8 {
+        7
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|byte| !byte).collect()
+    }
+
+    fn decompress(&self, data: &[u8], max_len: usize) -> Result<Vec<u8>, String> {
+        if data.len()>max_len {
+            return Err(String::from("Too long"));
+        }
+        Ok(self.compress(data))
+    }
+}
+
+#[allow(dead_code)]
+pub fn level_compression() {
+    let columns: [Vec<u64>; 5] = [
+        vec![],
+        vec![5; 100],
+        (0..300).map(|value| value*7).collect(),
+        vec![0, u64::MAX, 1, u64::MAX-1, 1 << 63, 3, 3, 3],
+        (0..1000).map(|value| mix64(value)%5).collect(),
+    ];
+    for values in &columns {
+        for codec in ColumnCodec::ALL {
+            let mut encoded = vec![];
+            codec.encode(values, &mut encoded);
+            let mut reader = ByteReader::new(&encoded);
+            assert_eq!(&codec.decode(&mut reader, values.len()).unwrap(), values, "{:?} should round trip", codec);
+            assert_eq!(reader.remaining(), 0);
+        }
+    }
+    let mut runs = vec![];
+    ColumnCodec::Rle.encode(&[9; 4], &mut runs);
+    assert!(ColumnCodec::Rle.decode(&mut ByteReader::new(&runs), 3).is_err(), "A run longer than the column should not decode");
+
+    let texts: [Vec<u8>; 4] = [
+        vec![],
+        b"abcabcabcabcabcabcabcabc, and then something else entirely".to_vec(),
+        vec![0; 10_000],
+        (0..5000).map(|value| mix64(value) as u8).collect(),
+    ];
+    for text in &texts {
+        let compressed = LzCompressor.compress(text);
+        assert_eq!(&LzCompressor.decompress(&compressed, text.len()).unwrap(), text);
+        if !text.is_empty() {
+            assert!(LzCompressor.decompress(&compressed, text.len()-1).is_err(), "Decompression past max_len should fail");
+        }
+    }
+    assert!(LzCompressor.compress(&texts[2]).len()<100);
+
+    //every combination of settings reads back exactly, and the usual levels shrink a lot
+    let noisy = HybridOctree::new(1, 4, Box::new(HashNoiseGenerator::new(3)));
+    let layered = HybridOctree::new(2, 4, Box::new(LayeredGenerator::default()
+        .with(Box::new(FillGenerator::new(2, 255).unwrap()))
+        .with(Box::new(FillGenerator::new(9, 40).unwrap()))));
+    noisy.load_level(voxel(0, 0, 0, 0)).unwrap();
+    layered.load_level(voxel(0, 0, 0, 0)).unwrap();
+    layered.carve_shape(&Sphere::new([1.0, 1.0, 1.0], 1.5), 0).unwrap();
+    layered.with_level_mut(&voxel(0, 0, 0, 0), |contents| {
+        contents.data.pos[5].x = 7;
+        contents.data._gpu_only_level_index_next[9] = u32::MAX;
+    }).unwrap();
+    let mut settings = vec![LevelCompression::default(), LevelCompression::smallest(), LevelCompression::new(None, Some(Arc::new(InvertingCompressor)))];
+    settings.extend(ColumnCodec::ALL.iter().map(|codec| LevelCompression::new(Some(*codec), None)));
+    for octree in [&noisy, &layered] {
+        let level = octree.get_level(voxel(0, 0, 0, 0));
+        let data = &level.level.contents.read().unwrap().data;
+        let mut uncompressed = vec![];
+        write_particle_vec(data, &mut uncompressed);
+        for compression in &settings {
+            let encoded = compression.encode(data);
+            assert_eq!(&compression.decode(&encoded, 64).unwrap(), data);
+            assert!(encoded.len()<=uncompressed.len());
+            assert!(compression.decode(&encoded, 63).is_err(), "A level of the wrong length should not decode");
+            assert!(compression.decode(&encoded[..encoded.len()-1], 64).is_err(), "A truncated level should not decode");
+        }
+        //the built in compressor needs no settings to read, others do
+        assert_eq!(&LevelCompression::default().decode(&LevelCompression::smallest().encode(data), 64).unwrap(), data);
+        assert!(LevelCompression::default().decode(&settings[2].encode(data), 64).is_err());
+    }
+    let level = layered.get_level(voxel(0, 0, 0, 0));
+    let data = &level.level.contents.read().unwrap().data;
+    let mut uncompressed = vec![];
+    write_particle_vec(data, &mut uncompressed);
+    assert!(LevelCompression::default().encode(data).len()*50<uncompressed.len());
+    assert!(LevelCompression::smallest().encode(data).len()<=LevelCompression::default().encode(data).len());
+
+    //region files keep levels in the storage's compression, and a storage using any other compression reads them back
+    let directory = std::env::temp_dir().join("molecule_engine_level_compression");
+    let _ = std::fs::remove_dir_all(&directory);
+    let mut storage = RegionStorage::new(&directory, 4).unwrap();
+    storage.compression = LevelCompression::smallest();
+    storage.write_level(&voxel(0, 0, 0, 0), data).unwrap();
+    let path = storage.region_path(&voxel(0, 0, 0, 0));
+    let region = RegionFile::read(&std::fs::read(&path).unwrap()).unwrap();
+    assert!(region.entries.values().all(|payload| payload.len()*50<uncompressed.len()));
+    let restored = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    restored.set_storage(Some(Box::new(RegionStorage::new(&directory, 4).unwrap())));
+    restored.load_level(voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!(&restored.get_level(voxel(0, 0, 0, 0)).level.contents.read().unwrap().data, data);
+    //a region file of any other format version is refused rather than misread
+    let mut other_version = std::fs::read(&path).unwrap();
+    other_version[8..12].copy_from_slice(&(REGION_FORMAT_VERSION+1).to_le_bytes());
+    assert!(RegionFile::read(&other_version).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //a level sent to another octree replaces what it had loaded there, and is kept through eviction
+    let compression = LevelCompression::smallest();
+    let payload = layered.level_payload(&voxel(0, 1, 2, 3), &compression).unwrap();
+    assert!(payload.len()*50<uncompressed.len());
+    let receiver = HybridOctree::new(2, 4, Box::new(FillGenerator::new(1, 255).unwrap()));
+    receiver.set_storage(Some(Box::new(MemoryLevelStorage::default())));
+    receiver.load_level(voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!(receiver.receive_level_payload(&payload, &compression).unwrap(), voxel(0, 0, 0, 0));
+    assert_eq!(&receiver.get_level(voxel(0, 0, 0, 0)).level.contents.read().unwrap().data, data);
+    assert!(receiver.get_level(voxel(0, 0, 0, 0)).level.contents.read().unwrap().dirty);
+    assert!(receiver.lod_dirty.lock().unwrap().contains(&level_key(&voxel(0, 0, 0, 0), 4).unwrap()));
+    receiver.unload_level(voxel(0, 0, 0, 0)).unwrap();
+    receiver.receive_level_payload(&write_level_payload(&voxel(1, 0, 0, 0), 4, data, &compression), &compression).unwrap();
+    assert_eq!(receiver.loaded_level_count(), 1);
+    receiver.load_level(voxel(0, 0, 0, 0)).unwrap();
+    assert_eq!(&receiver.get_level(voxel(0, 0, 0, 0)).level.contents.read().unwrap().data, data);
+    assert_eq!(&receiver.get_level(voxel(1, 0, 0, 0)).level.contents.read().unwrap().data, data);
+    assert!(receiver.level_payload(&voxel(0, 20, 0, 0), &compression).is_err());
+    assert!(receiver.receive_level_payload(&write_level_payload(&voxel(0, 1, 0, 0), 4, data, &compression), &compression).is_err(), "A payload away from a level origin should be refused");
+    assert!(receiver.receive_level_payload(&write_level_payload(&voxel(0, 0, 0, 0), 2, data, &compression), &compression).is_err(), "A payload of another level length should be refused");
+    assert!(receiver.receive_level_payload(&payload[..payload.len()-1], &compression).is_err());
+    println!("Level compression passed");
+}
+
+#[allow(dead_code)]
+pub fn gpu_packing() {
+    let octree = HybridOctree::new(2, 4, Box::new(FillGenerator::new(3, 200).unwrap()));
+    octree.set_voxels(0, &[
+        (Vector3U64 { x: 1, y: 0, z: 0 }, MaterialComposition::Empty),
+        (Vector3U64 { x: 2, y: 0, z: 0 }, MaterialComposition::from_entries(vec![(1, 10), (300, 20), (7, 30)])),
+    ]).unwrap();
+    let origins = [voxel(1, 0, 0, 0), voxel(0, 0, 0, 0), voxel(0, 4, 0, 0)];
+    let packed = octree.pack_levels(&origins).unwrap();
+    assert_eq!(packed.origins, origins.to_vec());
+    assert_eq!((packed.level_count(), packed.particle_count()), (3, 192));
+    //every Particle holds one material, except the emptied one and the one holding three
+    assert_eq!(packed.material_count(), 192-1+2);
+
+    //std430 puts the level table's counts after two uvec4s, and packs Particles tightly
+    let word = |data: &[u8], offset: usize| u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]);
+    let level = |index: usize, word_index: usize| word(&packed.level_table, index*GPU_LEVEL_SIZE+word_index*4);
+    assert_eq!((level(0, 3), level(1, 3), level(2, 0)), (1, 0, 4));
+    assert_eq!((level(2, 8), level(2, 9)), (128, 64));
+    assert_eq!((level(0, 10), level(1, 10), level(2, 10)), (NO_LEVEL, 0, 0));
+    let particle = |index: usize, word_index: usize| word(&packed.particles, index*GPU_PARTICLE_SIZE+word_index*4);
+    assert_eq!(particle(0, 4), 0x8000_8000);
+    assert_eq!(particle(64, 0), 1);
+    //the material entries of voxel (2, 0, 0) come lowest material first
+    let mixed = 64+index_in_level(&voxel(0, 2, 0, 0), 4);
+    assert_eq!(particle(mixed, 3), 3);
+    let first = particle(mixed, 2) as usize;
+    let entries: Vec<u32> = (first..first+3).map(|entry| word(&packed.materials, entry*4)).collect();
+    assert_eq!(entries, vec![1 | 10 << 16, 7 | 30 << 16, 300 | 20 << 16]);
+
+    //coarse Particles point at the finer level within their voxel, if it was packed
+    let unpacked = unpack_levels(&packed, 4).unwrap();
+    assert_eq!(unpacked.len(), 3);
+    for (index, next) in unpacked[0].1._gpu_only_level_index_next.iter().enumerate() {
+        let location = location_in_level(&voxel(1, 0, 0, 0), index, 4);
+        let expected = match (location.vec.x, location.vec.y, location.vec.z) {
+            (_, y, z) if y>=2 || z>=2 => NO_LEVEL,
+            (x, _, _) if x<2 => 1,
+            _ => 2,
+        };
+        assert_eq!(*next, expected, "{:?}", location);
+    }
+    for (level, (origin, particles)) in unpacked.iter().enumerate() {
+        assert!(particles._gpu_only_level_index_current.iter().all(|current| *current==level as u32));
+        let (expected, _loaded_now) = octree.with_level_mut(origin, |contents| (contents.data.material.clone(), contents.data.pos.clone())).unwrap();
+        assert_eq!((&particles.material, &particles.pos), (&expected.0, &expected.1));
+    }
+    assert!(unpacked[1].1._gpu_only_level_index_next.iter().all(|next| *next==NO_LEVEL));
+
+    //the packer takes each level once, and only at its origin
+    let mut packer = LevelPacker::new(4);
+    assert!(octree.with_level_mut(&voxel(0, 0, 0, 0), |contents| packer.push(&voxel(0, 1, 0, 0), &contents.data)).unwrap().0.is_err());
+    assert!(octree.pack_levels(&[voxel(0, 0, 0, 0), voxel(0, 1, 1, 1)]).is_err(), "Both are in the same level");
+
+    //the unpacker rejects layouts the packer would not write
+    let mut broken = packed.clone();
+    broken.materials[first*4+2] = 0;
+    assert!(unpack_levels(&broken, 4).is_err(), "A material entry without a weight should not unpack");
+    let mut broken = packed.clone();
+    broken.origins.swap(1, 2);
+    assert!(unpack_levels(&broken, 4).is_err());
+    let mut broken = packed.clone();
+    broken.particles.truncate(100*GPU_PARTICLE_SIZE);
+    assert!(unpack_levels(&broken, 4).is_err());
+    assert!(unpack_levels(&packed, 2).is_err());
+    println!("GPU packing passed");
+}
+
+#[allow(dead_code)]
+pub fn svo_traversal() {
+    let octree = HybridOctree::new(1, 8, Box::new(EmptyGenerator));
+    //a floor of stone with a ball of grass resting on it and one mixed voxel mostly of sand
+    let mut voxels = vec![];
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let offset = [x as f64+0.5-11.0, y as f64+0.5-8.0, z as f64+0.5-11.0];
+                if y<4 {
+                    voxels.push((Vector3U64 { x: x, y: y, z: z }, one_hot_material(1)));
+                } else if offset[0]*offset[0]+offset[1]*offset[1]+offset[2]*offset[2]<9.0 {
+                    voxels.push((Vector3U64 { x: x, y: y, z: z }, one_hot_material(2)));
+                }
+            }
+        }
+    }
+    voxels.push((Vector3U64 { x: 2, y: 6, z: 13 }, MaterialComposition::from_entries(vec![(5, 200), (1, 50)])));
+    octree.set_voxels(0, &voxels).unwrap();
+
+    let svo = octree.build_svo(&voxel(0, 0, 0, 0), 2, &[]).unwrap();
+    assert_eq!(svo.side(), 16);
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let location = voxel(0, x, y, z);
+                assert_eq!(svo.get(&location), Some(octree.query(location.clone()).unwrap().material.dominant(&[])), "{:?}", location);
+            }
+        }
+    }
+    assert_eq!((svo.get(&voxel(0, 16, 0, 0)), svo.get(&voxel(1, 0, 0, 0))), (None, None));
+
+    //empty and uniform space is collapsed, so only children the surface passes through get nodes or bricks
+    assert_eq!(SvoChild::decode(svo.root).unwrap(), SvoChild::Node(0));
+    assert_eq!(SvoChild::decode(svo.nodes[0][0]).unwrap(), SvoChild::Node(1));
+    assert_eq!(SvoChild::decode(svo.nodes[0][2]).unwrap(), SvoChild::Empty);
+    assert_eq!(SvoChild::decode(svo.nodes[1][0]).unwrap(), SvoChild::Uniform(1));
+    assert_eq!(SvoChild::decode(svo.nodes[1][2]).unwrap(), SvoChild::Empty);
+    assert!(svo.nodes.len()<1+8 && svo.brick_count()<64, "{} nodes and {} bricks", svo.nodes.len(), svo.brick_count());
+
+    //marching through the SVO finds what walking the octree finds
+    let unit = |seed: u64| (mix64(seed)%10_000) as f64/10_000.0;
+    let options = RaycastOptions {
+        max_distance: 100.0,
+        ..RaycastOptions::default()
+    };
+    let mut hits = 0;
+    for ray_index in 0..300 {
+        let seed = ray_index*6;
+        let origin = [unit(seed)*24.0-4.0, unit(seed+1)*24.0-4.0, unit(seed+2)*24.0-4.0];
+        let direction = [unit(seed+3)-0.5, unit(seed+4)-0.5, unit(seed+5)-0.5];
+        let ray = Ray::new(origin, direction);
+        let expected = octree.raycast(&ray, &options);
+        let found = svo.raycast(&ray, options.max_distance);
+        match (&expected, &found) {
+            (Some(expected), Some(found)) => {
+                assert_eq!((&found.voxel, found.normal, found.material), (&expected.voxel, expected.normal, expected.material), "{:?}", ray);
+                assert!((found.distance-expected.distance).abs()<1e-6);
+                hits+=1;
+            }
+            _ => assert_eq!(found, expected, "{:?}", ray),
+        }
+    }
+    assert!(hits>50, "Only {} rays hit", hits);
+
+    let without_stone = octree.build_svo(&voxel(0, 0, 0, 0), 2, &[1]).unwrap();
+    assert_eq!(without_stone.get(&voxel(0, 0, 0, 0)), Some(None));
+    assert_eq!(without_stone.get(&voxel(0, 2, 6, 13)), Some(Some(5)));
+    assert!(octree.build_svo(&voxel(0, u64::MAX-3, 0, 0), 1, &[]).is_err());
+
+    //the bricks of a deep, mostly empty cube are all that is kept
+    let mut brick = vec![SVO_EMPTY_VOXEL; 64];
+    brick[5] = 3;
+    let mut bricks = BTreeMap::new();
+    bricks.insert([1, (1<<SVO_MAX_DEPTH)-1, 0], brick.clone());
+    let deep = Svo::from_bricks(voxel(0, 0, 0, 0), SVO_MAX_DEPTH, bricks).unwrap();
+    assert_eq!((deep.nodes.len(), deep.brick_count()), (SVO_MAX_DEPTH as usize, 1));
+    assert_eq!(deep.get(&voxel(0, 4, (4<<SVO_MAX_DEPTH)-3, 1)), Some(Some(3)));
+    assert_eq!(deep.get(&voxel(0, 0, 0, 0)), Some(None));
+    let mut outside = BTreeMap::new();
+    outside.insert([4, 0, 0], brick);
+    assert!(Svo::from_bricks(voxel(0, 0, 0, 0), 2, outside).is_err());
+    assert!(Svo::from_grid(voxel(0, 0, 0, 0), SVO_MAX_DEPTH+1, &[]).is_err());
+    assert!(Svo::from_grid(voxel(0, 0, 0, 0), SVO_MAX_DEPTH, &[]).is_err());
+    let mut grid = vec![SVO_EMPTY_VOXEL; 16*16*16];
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                if let Some(Some(material)) = svo.get(&voxel(0, x, y, z)) {
+                    grid[((x*16+y)*16+z) as usize] = material;
+                }
+            }
+        }
+    }
+    assert_eq!(Svo::from_grid(voxel(0, 0, 0, 0), 2, &grid).unwrap(), svo);
+
+    //the format is versioned, and nothing that could send a traversal astray gets through
+    let data = svo.write();
+    assert_eq!(Svo::read(&data).unwrap(), svo);
+    let mut newer = data.clone();
+    newer[4..8].copy_from_slice(&(SVO_FORMAT_VERSION+1).to_le_bytes());
+    assert!(Svo::read(&newer).is_err(), "A newer format version should be refused");
+    assert!(Svo::read(&data[..data.len()-1]).is_err());
+    let mut trailing = data.clone();
+    trailing.push(0);
+    assert!(Svo::read(&trailing).is_err());
+    let mut cyclic = svo.clone();
+    cyclic.nodes[1][3] = SvoChild::Node(0).encode();
+    assert!(Svo::read(&cyclic.write()).is_err(), "A node reached twice should not read");
+    let mut shallow = svo.clone();
+    shallow.nodes[0][1] = SvoChild::Brick(0).encode();
+    assert!(shallow.validate().is_err(), "Bricks may only hang below the deepest nodes");
+    let mut missing = svo.clone();
+    missing.bricks.truncate(missing.bricks.len()-64);
+    assert!(missing.validate().is_err());
+    println!("SVO traversal passed");
+}
+
+//keeps levels in memory, but can not remove them again
+struct NoRemoveStorage(MemoryLevelStorage);
+
+impl LevelStorage for NoRemoveStorage {
+    fn read_level(&self, location: &VoxelLocation) -> Result<Option<ParticleVec>, String> {
+        self.0.read_level(location)
+    }
+
+    fn write_level(&mut self, location: &VoxelLocation, data: &ParticleVec) -> Result<(), String> {
+        self.0.write_level(location, data)
+    }
+
+    fn corrupt_levels(&mut self) -> Result<Vec<VoxelLocation>, String> {
+        self.0.corrupt_levels()
+    }
+}
+
+#[allow(dead_code)]
+pub fn level_fsck() {
+    let directory = std::env::temp_dir().join("molecule_engine_level_fsck");
+    let _ = std::fs::remove_dir_all(&directory);
+    let octree = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1)));
+    octree.set_storage(Some(Box::new(RegionStorage::new(&directory, 2).unwrap())));
+    let (first, second, parent) = (voxel(0, 0, 0, 0), voxel(0, 2, 0, 0), voxel(1, 0, 0, 0));
+    for location in [&parent, &first, &second] {
+        octree.load_level(location.clone()).unwrap();
+    }
+    assert!(octree.save_level(&first).unwrap() && octree.save_level(&second).unwrap());
+    let report = octree.fsck(&FsckOptions::new(false));
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.checked_levels, 3);
+
+    //flipping a bit in the last payload of the region fails that entry's checksum, rather than reading back garbage
+    let storage = RegionStorage::new(&directory, 2).unwrap();
+    let path = storage.region_path(&first);
+    let mut data = std::fs::read(&path).unwrap();
+    *data.last_mut().unwrap() ^= 1;
+    std::fs::write(&path, &data).unwrap();
+    let region = RegionFile::read(&data).unwrap();
+    let corrupt_key = *region.corrupt.keys().next().unwrap();
+    assert_eq!(region.corrupt.len(), 1);
+    assert_eq!(corrupt_key, *region.entries.keys().last().unwrap());
+    assert!(region.read_level(corrupt_key).unwrap_err().contains("checksum"));
+    //written back unchanged, the entry stays corrupt
+    assert_eq!(RegionFile::read(&region.write()).unwrap().corrupt, region.corrupt);
+    let corrupt_level = level_key_origin(corrupt_key, 2).unwrap();
+    assert_eq!(corrupt_level, second);
+    assert!(octree.unload_level(second.clone()).unwrap());
+    assert!(octree.load_level(second.clone()).unwrap_err().contains("checksum"));
+
+    octree.with_level_mut(&first, |contents| {
+        contents.data.pos.pop();
+        contents.data.material[0] = MaterialComposition::Sparse(vec![(5, 3), (2, 4)].into_boxed_slice());
+        contents.data.material[1] = MaterialComposition::Single(7, 0);
+    }).unwrap();
+    //a level whose parent LOD is not loaded is no issue, downsampling loads the parent when it is needed
+    let orphan = voxel(0, 8, 0, 0);
+    octree.load_level(orphan.clone()).unwrap();
+
+    let report = octree.fsck(&FsckOptions::new(false));
+    assert_eq!(report.findings.len(), 4, "{:?}", report);
+    assert_eq!(report.unrepaired().len(), 4);
+    assert!(report.unrepaired().contains(&&FsckIssue::CorruptStored { level: second.clone() }));
+    assert!(report.unrepaired().contains(&&FsckIssue::ColumnLengthMismatch { level: first.clone(), lengths: [8, 8, 8, 7], expected: 8 }));
+    let invalid:Vec<usize> = report.unrepaired().iter().filter_map(|issue| match issue {
+        FsckIssue::InvalidMaterial { level, index, .. } if *level==first => Some(*index),
+        _ => None,
+    }).collect();
+    assert_eq!(invalid, vec![0, 1]);
+    assert_eq!(octree.get_level(first.clone()).level.contents.read().unwrap().data.pos.len(), 7, "Checking alone should change nothing");
+
+    let report = octree.fsck(&FsckOptions::new(true));
+    assert_eq!(report.findings.len(), 4);
+    assert!(report.unrepaired().is_empty(), "{:?}", report);
+    assert!(report.findings.iter().all(|finding| finding.repair_error.is_none()));
+    {
+        let sorted_level = octree.get_level(first.clone());
+        let contents = sorted_level.level.contents.read().unwrap();
+        assert_eq!(contents.data.pos.len(), 8);
+        assert_eq!(contents.data.material[0], MaterialComposition::from_entries([(2, 4), (5, 3)]));
+        assert!(contents.data.material[0].validate().is_ok());
+        assert!(contents.data.material[1].is_empty());
+        assert!(contents.dirty);
+    }
+    //the corrupt entry is gone, so the level is generated afresh
+    octree.load_level(second.clone()).unwrap();
+    let fresh = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1)));
+    fresh.load_level(second.clone()).unwrap();
+    assert_eq!(
+        octree.get_level(second.clone()).level.contents.read().unwrap().data,
+        fresh.get_level(second.clone()).level.contents.read().unwrap().data
+    );
+    assert!(octree.fsck(&FsckOptions::new(false)).is_clean());
+
+    //a level filed under another level's key moves to its own, and leftovers in unloaded entries are cleared
+    let (misplaced, wrong_key) = (voxel(0, 2, 2, 0), level_key(&voxel(0, 4, 4, 0), 2).unwrap());
+    octree.levels.insert(SortedLevel {
+        ordinal: wrong_key,
+        location: misplaced.clone(),
+        level: Level::new(empty_particle_vec(8)),
+    }, None);
+    //an entry someone holds on to stays behind when its level is unloaded
+    let held = octree.find_level(&orphan).unwrap();
+    assert!(octree.unload_level(orphan.clone()).unwrap());
+    held.level.contents.write().unwrap().dirty = true;
+    let report = octree.fsck(&FsckOptions::new(true));
+    assert_eq!(report.findings.len(), 2, "{:?}", report);
+    assert!(report.unrepaired().is_empty());
+    assert!(matches!(&report.findings[0].issue, FsckIssue::UnloadedWithData { level } if *level==orphan));
+    assert!(matches!(&report.findings[1].issue, FsckIssue::MisplacedLevel { location, key, .. } if *location==misplaced && *key==wrong_key));
+    assert_eq!(octree.get_level(misplaced.clone()).location, misplaced);
+    assert!(!octree.levels.get(wrong_key).unwrap().level.contents.read().unwrap().loaded);
+    assert!(octree.fsck(&FsckOptions::new(false)).is_clean());
+    assert!(octree.try_get_level(&orphan).is_err());
+    assert_eq!(octree.try_get_level(&misplaced).unwrap().location, misplaced);
+
+    //a repair that fails says why in the report
+    let broken = HybridOctree::new(2, 2, Box::new(HashNoiseGenerator::new(1)));
+    let mut memory = MemoryLevelStorage::default();
+    memory.levels.insert(voxel(1, 0, 0, 0), vec![1, 2, 3]);
+    broken.set_storage(Some(Box::new(NoRemoveStorage(memory))));
+    let report = broken.fsck(&FsckOptions::new(true));
+    assert_eq!(report.findings.len(), 1, "{:?}", report);
+    assert_eq!(report.findings[0].issue, FsckIssue::CorruptStored { level: voxel(1, 0, 0, 0) });
+    assert!(!report.findings[0].repaired);
+    assert!(report.findings[0].repair_error.as_ref().unwrap().starts_with("Could not remove level"));
+    assert!(!broken.fsck(&FsckOptions::new(false)).is_clean());
+
+    //memory storage has no checksums, but still notices levels that no longer decode
+    let mut memory = MemoryLevelStorage::default();
+    memory.write_level(&first, &empty_particle_vec(8)).unwrap();
+    memory.levels.insert(second.clone(), vec![1, 2, 3]);
+    assert_eq!(memory.corrupt_levels().unwrap(), vec![second.clone()]);
+    assert!(memory.remove_level(&second).unwrap());
+    assert!(memory.corrupt_levels().unwrap().is_empty());
+    std::fs::remove_dir_all(&directory).unwrap();
+    println!("Level fsck passed");
 }
\ No newline at end of file
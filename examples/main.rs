@@ -1,9 +1,39 @@
 mod concurrency;
+mod formats;
 mod levels;
 mod macros;
+mod materials;
+mod math;
+mod meshing;
 mod vulkan;
 
 fn main() {
+    concurrency::objekt_snapshot::snapshot_round_trip();
+    levels::storage_order();
+    levels::octree_index_math();
+    levels::octree_point_queries();
+    levels::level_eviction();
+    levels::region_storage_round_trip();
+    levels::seeded_generation();
+    levels::voxel_editing();
+    levels::raycasting();
+    levels::range_queries();
+    levels::lod_downsampling();
+    levels::concurrent_level_map();
+    levels::level_streaming();
+    levels::material_compositions();
+    levels::level_compression();
+    levels::gpu_packing();
+    levels::svo_traversal();
+    levels::level_fsck();
+    formats::vox_round_trip();
+    formats::heightmap_terrain();
+    formats::ply_point_cloud();
+    formats::mesh_export();
+    materials::material_registry();
+    math::noise_properties();
+    meshing::surface_meshing();
+    meshing::greedy_meshing();
     vulkan::how_to_use_vulkan::make_vulkan_test();
     // concurrency::synchronization_graph_tasks_basics::sync_test();
 }
\ No newline at end of file
@@ -0,0 +1,95 @@
+use molecule_engine::{
+    concurrency::objekt_snapshot::SerializableObjekt,
+    objekt_impl::materials::{
+        MaterialDefinition,
+        MaterialRegistry,
+        MaterialRegistryObjekt,
+        Phase,
+        UNDEFINED_COLOR,
+    },
+    utils::binary::ByteReader,
+};
+
+const MATERIALS: &'static str = "
+# terrain
+[1]
+name = stone
+density = 2600
+color = 7f7f7f
+phase = solid
+hardness = 6
+thermal_conductivity = 2.5
+flammability = 0
+
+[2]
+name = water
+density = 1000
+color = #2050c080
+phase = liquid
+thermal_conductivity = 0.6
+
+[511]
+name = dry grass
+phase = solid
+flammability = 0.9
+";
+
+#[allow(dead_code)]
+pub fn material_registry() {
+    let registry = MaterialRegistry::parse(MATERIALS).unwrap();
+    assert_eq!(registry.len(), 3);
+    let stone = registry.get(1).unwrap();
+    assert_eq!((stone.density, stone.color, stone.phase, stone.hardness), (2600.0, [127, 127, 127, 255], Phase::Solid, 6.0));
+    let water = registry.get(registry.index_of("water").unwrap()).unwrap();
+    assert_eq!((water.color, water.phase, water.thermal_conductivity), ([0x20, 0x50, 0xc0, 0x80], Phase::Liquid, 0.6));
+    //keys that are left out fall back to the defaults
+    let grass = registry.get(511).unwrap();
+    assert_eq!((grass.density, grass.hardness, grass.flammability), (1000.0, 0.0, 0.9));
+    assert_eq!(registry.index_of("dry grass"), Some(511));
+    assert!(registry.get(0).is_none() && registry.get(512).is_none());
+    let colors = registry.colors();
+    assert_eq!((colors.len(), colors[0], colors[2]), (512, UNDEFINED_COLOR, [0x20, 0x50, 0xc0, 0x80]));
+    assert_eq!(registry.iter().map(|(index, _definition)| index).collect::<Vec<_>>(), vec![1, 2, 511]);
+
+    //written out and read back, nothing changes
+    assert_eq!(MaterialRegistry::parse(&registry.to_text()).unwrap(), registry);
+    let path = std::env::temp_dir().join(format!("molecule_materials_{}.txt", std::process::id()));
+    registry.save(&path).unwrap();
+    assert_eq!(MaterialRegistry::load(&path).unwrap(), registry);
+    std::fs::remove_file(&path).unwrap();
+    assert!(MaterialRegistry::load(&path).is_err());
+
+    //as part of a snapshot
+    let objekt = MaterialRegistryObjekt::new(String::from("materials"), registry.clone());
+    let mut out = vec![];
+    objekt.serialize(&mut out).unwrap();
+    let restored = MaterialRegistryObjekt::deserialize(String::from("materials"), &mut ByteReader::new(&out)).unwrap();
+    assert_eq!(*restored.inner(), registry);
+
+    //mistakes are reported with the line they are on
+    let errors = [
+        ("name = orphan", "Line 1"),
+        ("[1]\nname = a\n[1]\nname = b", "Line 3"),
+        ("[1]\ndensity = 3", "Line 1"),
+        ("[512]\nname = beyond", "Line 1"),
+        ("[x]", "Line 1"),
+        ("[1]\nname = a\ncolor = 12345", "Line 3"),
+        ("[1]\nname = a\nphase = plasma", "Line 3"),
+        ("[1]\nname = a\nhardness = soft", "Line 3"),
+        ("[1]\nname = a\nviscosity = 1", "Line 3"),
+        ("[1]\nname = a\n\n[2]\nname = a", "Line 4"),
+        ("[1]\nname", "Line 2"),
+    ];
+    for (text, line) in errors.iter() {
+        let msg = MaterialRegistry::parse(text).unwrap_err();
+        assert!(msg.starts_with(line), "{} for {:?}", msg, text);
+    }
+
+    let mut registry = MaterialRegistry::new();
+    registry.define(3, MaterialDefinition::new(String::from("sand"))).unwrap();
+    assert!(registry.define(4, MaterialDefinition::new(String::from("sand"))).is_err());
+    assert!(registry.define(4, MaterialDefinition::new(String::from(" sand"))).is_err());
+    assert!(registry.define(512, MaterialDefinition::new(String::from("void"))).is_err());
+    registry.define(3, MaterialDefinition::new(String::from("sand"))).unwrap();
+    println!("Material registry passed");
+}
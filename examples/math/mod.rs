@@ -0,0 +1,103 @@
+use molecule_engine::math::{
+    noise::{
+        Fbm,
+        GradientNoise,
+        Noise2,
+        Noise3,
+        Noise4,
+        Octaves,
+        Ridged,
+        SimplexNoise,
+        WorleyNoise,
+        WorleyReturn,
+    },
+    octree_math::children,
+    vectors::{
+        Vector3U64,
+        VoxelLocation,
+    },
+};
+
+//a fixed spread of sample points, so failures are reproducible
+fn sample_points(count: usize) -> Vec<[f64; 4]> {
+    (0..count).map(|i| {
+        let i = i as f64;
+        [i*0.618_034*7.3-40.0, i*0.414_214*5.1-20.0, i*0.732_051*3.7, i*0.236_068*9.9-10.0]
+    }).collect()
+}
+
+#[allow(dead_code)]
+pub fn noise_properties() {
+    let gradient = GradientNoise::new(7);
+    let simplex = SimplexNoise::new(7);
+    let f1 = WorleyNoise::new(7, WorleyReturn::F1);
+    let f2 = WorleyNoise::new(7, WorleyReturn::F2);
+
+    //gradient noise is zero on its lattice
+    for lattice in &[[0.0, 0.0, 0.0, 0.0], [3.0, -2.0, 5.0, 1.0], [-7.0, 11.0, -1.0, 4.0]] {
+        assert_eq!(gradient.sample2([lattice[0], lattice[1]]), 0.0);
+        assert_eq!(gradient.sample3([lattice[0], lattice[1], lattice[2]]), 0.0);
+        assert_eq!(gradient.sample4(*lattice), 0.0);
+    }
+
+    let mut simplex_extreme: f64 = 0.0;
+    let mut simplex4_extreme: f64 = 0.0;
+    for p in sample_points(4000) {
+        let p2 = [p[0], p[1]];
+        let p3 = [p[0], p[1], p[2]];
+        for value in &[gradient.sample2(p2), gradient.sample3(p3), gradient.sample4(p), simplex.sample2(p2), simplex.sample3(p3), simplex.sample4(p)] {
+            assert!(value.abs()<=1.05, "noise value {} out of range at {:?}", value, p);
+        }
+        simplex_extreme = simplex_extreme.max(simplex.sample3(p3).abs());
+        simplex4_extreme = simplex4_extreme.max(simplex.sample4(p).abs());
+        assert!(f1.sample3(p3)>=0.0 && f1.sample3(p3)<=f2.sample3(p3));
+        assert!(f1.sample2(p2)<=f2.sample2(p2));
+        assert!(f1.sample3(p3)<3f64.sqrt());
+        assert!(f1.sample4(p)>=0.0 && f1.sample4(p)<=f2.sample4(p) && f1.sample4(p)<2.0);
+
+        //continuous, so tiny steps give tiny changes
+        let nudged = [p[0]+1e-4, p[1], p[2]];
+        assert!((gradient.sample3(p3)-gradient.sample3(nudged)).abs()<1e-2);
+        assert!((simplex.sample3(p3)-simplex.sample3(nudged)).abs()<1e-2);
+        let nudged4 = [p[0]+1e-4, p[1], p[2], p[3]];
+        assert!((simplex.sample4(p)-simplex.sample4(nudged4)).abs()<1e-2);
+        assert!((f1.sample4(p)-f1.sample4(nudged4)).abs()<1e-3);
+    }
+    assert!(simplex_extreme>0.3, "simplex noise should use most of its range");
+    assert!(simplex4_extreme>0.3, "4D simplex noise should use most of its range");
+
+    //the seed alone decides the output
+    assert_eq!(GradientNoise::new(7).sample3([1.5, 2.25, -3.75]), gradient.sample3([1.5, 2.25, -3.75]));
+    assert!(GradientNoise::new(8).sample3([1.5, 2.25, -3.75])!=gradient.sample3([1.5, 2.25, -3.75]));
+    assert_eq!(SimplexNoise::new(7).sample2([0.3, 9.1]), simplex.sample2([0.3, 9.1]));
+
+    let fbm = Fbm::new(GradientNoise::new(3), Octaves::default());
+    let ridged = Ridged::new(SimplexNoise::new(3), Octaves::default());
+    for p in sample_points(500) {
+        assert!(fbm.sample3([p[0], p[1], p[2]]).abs()<=1.05);
+        assert!(fbm.sample4(p).abs()<=1.05);
+        let ridge = ridged.sample2([p[0], p[1]]);
+        assert!((0.0..=1.0).contains(&ridge));
+    }
+
+    //a coarse voxel takes the average of its children, exactly while it still samples every LOD 0 voxel
+    let frequency = 1.0/64.0;
+    for x in 0..6 {
+        let coarse = VoxelLocation { lod: 2, vec: Vector3U64 { x: x*5, y: 17, z: x+2 } };
+        let fine = children(&coarse).unwrap();
+        let average = fine.iter().map(|child| fbm.sample_voxel(child, frequency)).sum::<f64>()/8.0;
+        assert!((fbm.sample_voxel(&coarse, frequency)-average).abs()<1e-9);
+        let average = fine.iter().map(|child| ridged.sample_voxel(child, frequency)).sum::<f64>()/8.0;
+        assert!((ridged.sample_voxel(&coarse, frequency)-average).abs()<1e-9);
+    }
+    for x in 0..6 {
+        let coarse = VoxelLocation { lod: 3, vec: Vector3U64 { x: x*5, y: 17, z: x+2 } };
+        let fine = children(&coarse).unwrap();
+        let average = fine.iter().map(|child| fbm.sample_voxel(child, frequency)).sum::<f64>()/8.0;
+        assert!((fbm.sample_voxel(&coarse, frequency)-average).abs()<0.05);
+    }
+    //at lod 0 nothing is filtered out, so voxel sampling agrees with point sampling
+    let voxel = VoxelLocation { lod: 0, vec: Vector3U64 { x: 3, y: 4, z: 5 } };
+    assert_eq!(fbm.sample_voxel(&voxel, 0.01), fbm.sample3([3.5*0.01, 4.5*0.01, 5.5*0.01]));
+    println!("Noise properties passed");
+}
\ No newline at end of file
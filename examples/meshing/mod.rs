@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use molecule_engine::{
+    math::{
+        shapes::{
+            Aabb,
+            Sphere,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    meshing::{
+        blocky::blocky_mesh,
+        greedy::greedy_mesh,
+        mesh::{
+            Mesh,
+            MeshMode,
+            MeshOptions,
+        },
+        mesh_cache::MeshCache,
+        padded_grid::PaddedGrid,
+        surface_nets::surface_nets_mesh,
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        level_generator::EmptyGenerator,
+        material_composition::MaterialComposition,
+        particle::one_hot_material,
+    },
+};
+
+fn voxel(lod: u64, x: u64, y: u64, z: u64) -> VoxelLocation {
+    VoxelLocation {
+        lod: lod,
+        vec: Vector3U64 { x: x, y: y, z: z },
+    }
+}
+
+fn blocky() -> MeshOptions {
+    MeshOptions {
+        mode: MeshMode::Blocky,
+        ignored_materials: vec![],
+    }
+}
+
+fn greedy() -> MeshOptions {
+    MeshOptions {
+        mode: MeshMode::Greedy,
+        ignored_materials: vec![],
+    }
+}
+
+//face area per material and normal direction, which merging faces must not change
+fn face_areas(mesh: &Mesh) -> HashMap<(u16, [i8;3]), f32> {
+    let mut areas = HashMap::new();
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [mesh.positions[triangle[0] as usize], mesh.positions[triangle[1] as usize], mesh.positions[triangle[2] as usize]];
+        let (ab, ac) = ([b[0]-a[0], b[1]-a[1], b[2]-a[2]], [c[0]-a[0], c[1]-a[1], c[2]-a[2]]);
+        let cross = [ab[1]*ac[2]-ab[2]*ac[1], ab[2]*ac[0]-ab[0]*ac[2], ab[0]*ac[1]-ab[1]*ac[0]];
+        let area = (cross[0]*cross[0]+cross[1]*cross[1]+cross[2]*cross[2]).sqrt()*0.5;
+        let normal = mesh.normals[triangle[0] as usize];
+        let key = (mesh.materials[triangle[0] as usize], [normal[0] as i8, normal[1] as i8, normal[2] as i8]);
+        *areas.entry(key).or_insert(0.0) += area;
+    }
+    areas
+}
+
+fn world_positions(mesh: &Mesh) -> Vec<[i64;3]> {
+    //quantized, so vertices from meshes of neighbouring levels can be matched up
+    mesh.positions.iter().map(|position| {
+        let mut quantized = [0;3];
+        for axis in 0..3 {
+            quantized[axis] = ((mesh.origin[axis]+position[axis] as f64)*1024.0).round() as i64;
+        }
+        quantized
+    }).collect()
+}
+
+//every triangle faces the same way as the normals of its vertices
+fn assert_winding(mesh: &Mesh) {
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [mesh.positions[triangle[0] as usize], mesh.positions[triangle[1] as usize], mesh.positions[triangle[2] as usize]];
+        let (ab, ac) = ([b[0]-a[0], b[1]-a[1], b[2]-a[2]], [c[0]-a[0], c[1]-a[1], c[2]-a[2]]);
+        let cross = [ab[1]*ac[2]-ab[2]*ac[1], ab[2]*ac[0]-ab[0]*ac[2], ab[0]*ac[1]-ab[1]*ac[0]];
+        for index in triangle {
+            let normal = mesh.normals[*index as usize];
+            assert!(cross[0]*normal[0]+cross[1]*normal[1]+cross[2]*normal[2]>0.0);
+        }
+    }
+}
+
+//welded by position, every edge is used once in each direction, so the surface is closed and consistently wound
+fn assert_closed(meshes: &[&Mesh]) {
+    let mut edges = HashMap::new();
+    for mesh in meshes {
+        let positions = world_positions(mesh);
+        for triangle in mesh.indices.chunks(3) {
+            for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+                *edges.entry((positions[triangle[from] as usize], positions[triangle[to] as usize])).or_insert(0) += 1;
+            }
+        }
+    }
+    for ((from, to), count) in &edges {
+        assert_eq!(*count, 1);
+        assert_eq!(edges.get(&(*to, *from)), Some(&1));
+    }
+}
+
+fn fill(octree: &HybridOctree, min: [f64;3], max: [f64;3], material: u16) {
+    octree.fill_box(&Aabb::new(min, max), 0, material).unwrap();
+}
+
+#[allow(dead_code)]
+pub fn surface_meshing() {
+    //a single voxel in the middle of a level, meshed without an octree
+    let mut grid = PaddedGrid::new(voxel(0, 0, 0, 0), 4);
+    grid.set(2, 2, 2, &one_hot_material(3), &[]);
+    let cube = blocky_mesh(&grid);
+    assert_eq!((cube.vertex_count(), cube.triangle_count()), (24, 12));
+    assert!(cube.materials.iter().all(|material| *material==3));
+    assert_winding(&cube);
+    assert_closed(&[&cube]);
+    for (position, normal) in cube.positions.iter().zip(cube.normals.iter()) {
+        //voxel (1, 1, 1) spans [1, 2] on every axis, and faces point away from its center
+        assert!(position.iter().all(|coord| *coord==1.0 || *coord==2.0));
+        let outward:f32 = (0..3).map(|axis| (position[axis]-1.5)*normal[axis]).sum();
+        assert!(outward>0.0);
+    }
+
+    //surface nets turn the same voxel into a smaller cube around its center, one vertex in each of the eight cells touching it
+    let smooth = surface_nets_mesh(&grid);
+    assert_eq!((smooth.vertex_count(), smooth.triangle_count()), (8, 12));
+    assert_winding(&smooth);
+    assert_closed(&[&smooth]);
+    for position in &smooth.positions {
+        assert!(position.iter().all(|coord| ((coord-1.5).abs()-1.0/6.0).abs()<1e-6));
+    }
+
+    //half a weight pulls the surface in towards the voxel center
+    grid.set(2, 2, 2, &MaterialComposition::Single(0, 128), &[]);
+    let shrunk = surface_nets_mesh(&grid);
+    for position in &shrunk.positions {
+        assert!(position.iter().all(|coord| (coord-1.5).abs()<0.1));
+    }
+
+    //levels must be loaded to be meshed
+    let octree = HybridOctree::new(2, 4, Box::new(EmptyGenerator));
+    assert!(octree.mesh_level(&voxel(0, 0, 0, 0), &blocky()).is_err());
+
+    //a 4x2x2 box straddling two levels: neither level builds faces on the shared border, and together they cover the box exactly
+    fill(&octree, [2.0, 1.0, 1.0], [6.0, 3.0, 3.0], 1);
+    let left = octree.mesh_level(&voxel(0, 0, 0, 0), &blocky()).unwrap();
+    let right = octree.mesh_level(&voxel(0, 4, 0, 0), &blocky()).unwrap();
+    assert_eq!(left.triangle_count()+right.triangle_count(), 2*2*(4*2+4*2+2*2));
+    assert_eq!(right.origin, [4.0, 0.0, 0.0]);
+    assert!(left.positions.iter().chain(right.positions.iter()).all(|position| position.iter().all(|coord| (0.0..=4.0).contains(coord))));
+    assert_closed(&[&left, &right]);
+    let smooth_left = octree.mesh_level(&voxel(0, 0, 0, 0), &MeshOptions::default()).unwrap();
+    let smooth_right = octree.mesh_level(&voxel(0, 4, 0, 0), &MeshOptions::default()).unwrap();
+    assert!(!smooth_left.is_empty() && !smooth_right.is_empty());
+    assert_winding(&smooth_left);
+    assert_closed(&[&smooth_left, &smooth_right]);
+
+    //material ids follow the voxels, and ignored materials are meshed as empty
+    fill(&octree, [2.0, 1.0, 1.0], [4.0, 3.0, 3.0], 2);
+    let left = octree.mesh_level(&voxel(0, 0, 0, 0), &blocky()).unwrap();
+    assert!(left.materials.iter().all(|material| *material==2));
+    let see_through = MeshOptions {
+        mode: MeshMode::Blocky,
+        ignored_materials: vec![2],
+    };
+    let left = octree.mesh_level(&voxel(0, 0, 0, 0), &see_through).unwrap();
+    let right = octree.mesh_level(&voxel(0, 4, 0, 0), &see_through).unwrap();
+    assert!(left.is_empty());
+    assert_eq!(right.triangle_count(), 2*6*2*2);
+    assert!(octree.mesh_level(&voxel(0, 0, 0, 0), &MeshOptions { mode: MeshMode::Blocky, ignored_materials: vec![512] }).is_err());
+
+    //where the neighbour is not loaded the level's own border is repeated, so a full level gets no walls towards it
+    let octree = HybridOctree::new(1, 4, Box::new(EmptyGenerator));
+    fill(&octree, [4.0, 0.0, 0.0], [8.0, 4.0, 4.0], 1);
+    assert!(octree.mesh_level(&voxel(0, 4, 0, 0), &blocky()).unwrap().is_empty());
+    octree.load_level(voxel(0, 8, 0, 0)).unwrap();
+    let full = octree.mesh_level(&voxel(0, 4, 0, 0), &blocky()).unwrap();
+    assert_eq!(full.triangle_count(), 2*4*4);
+    assert!(full.normals.iter().all(|normal| *normal==[1.0, 0.0, 0.0]));
+    println!("Surface meshing passed");
+}
+
+#[allow(dead_code)]
+pub fn greedy_meshing() {
+    //a 4x1x4 slab with nothing around it: six rectangles instead of 48 voxel faces
+    let mut grid = PaddedGrid::new(voxel(0, 0, 0, 0), 4);
+    for x in 1..=4 {
+        for z in 1..=4 {
+            grid.set(x, 1, z, &one_hot_material(1), &[]);
+        }
+    }
+    assert_eq!(blocky_mesh(&grid).triangle_count(), 2*48);
+    let slab = greedy_mesh(&grid);
+    assert_eq!(slab.triangle_count(), 2*6);
+    assert_winding(&slab);
+    assert_closed(&[&slab]);
+    assert_eq!(face_areas(&slab), face_areas(&blocky_mesh(&grid)));
+
+    //faces only merge within a material
+    for z in 1..=4 {
+        grid.set(1, 1, z, &one_hot_material(2), &[]);
+    }
+    let split = greedy_mesh(&grid);
+    assert_eq!(split.triangle_count(), 2*10);
+    assert_eq!(face_areas(&split), face_areas(&blocky_mesh(&grid)));
+
+    //a sphere spread over eight levels covers the same area per material and direction either way, with far fewer triangles
+    let octree = HybridOctree::new(1, 8, Box::new(EmptyGenerator));
+    let sphere = Sphere {
+        center: [8.0, 8.0, 8.0],
+        radius: 5.5,
+    };
+    octree.fill_shape(&sphere, 0, 3).unwrap();
+    fill(&octree, [8.0, 0.0, 0.0], [16.0, 16.0, 8.0], 4);
+    let mut blocky_triangles = 0;
+    let mut greedy_triangles = 0;
+    for origin in [0, 8] {
+        let blocky_level = octree.mesh_level(&voxel(0, origin, 0, 0), &blocky()).unwrap();
+        let greedy_level = octree.mesh_level(&voxel(0, origin, 0, 0), &greedy()).unwrap();
+        assert_winding(&greedy_level);
+        assert_eq!(face_areas(&greedy_level), face_areas(&blocky_level));
+        blocky_triangles += blocky_level.triangle_count();
+        greedy_triangles += greedy_level.triangle_count();
+    }
+    assert!(greedy_triangles*3<blocky_triangles);
+
+    //nothing is built on the border between two loaded levels
+    let octree = HybridOctree::new(1, 4, Box::new(EmptyGenerator));
+    fill(&octree, [2.0, 1.0, 1.0], [6.0, 3.0, 3.0], 1);
+    let left = octree.mesh_level(&voxel(0, 0, 0, 0), &greedy()).unwrap();
+    let right = octree.mesh_level(&voxel(0, 4, 0, 0), &greedy()).unwrap();
+    assert_eq!((left.triangle_count(), right.triangle_count()), (2*5, 2*5));
+    assert_closed(&[&left, &right]);
+
+    //the cache only rebuilds meshes whose level or neighbours changed
+    let octree = HybridOctree::new(1, 4, Box::new(EmptyGenerator));
+    for x in [0, 4, 8] {
+        octree.load_level(voxel(0, x, 0, 0)).unwrap();
+    }
+    let mut cache = MeshCache::new(greedy());
+    let report = cache.update_lod(&octree, 0).unwrap();
+    assert_eq!(report.rebuilt.len(), 3);
+    assert!(cache.get(&octree, &voxel(0, 4, 0, 0)).unwrap().is_empty());
+    assert!(cache.update_lod(&octree, 0).unwrap().rebuilt.is_empty());
+
+    octree.set_voxel_material(&voxel(0, 10, 1, 1), 5).unwrap();
+    let report = cache.update_lod(&octree, 0).unwrap();
+    assert_eq!(report.rebuilt, vec![voxel(0, 4, 0, 0), voxel(0, 8, 0, 0)]);
+    assert_eq!(cache.get(&octree, &voxel(0, 8, 0, 0)).unwrap().triangle_count(), 2*6);
+    assert!(!cache.is_stale(&octree, &voxel(0, 9, 2, 3)));
+
+    //unloading drops the level's mesh and rebuilds its neighbours, and loading it again brings it back
+    octree.unload_level(voxel(0, 8, 0, 0)).unwrap_err();
+    octree.with_level_mut(&voxel(0, 8, 0, 0), |contents| contents.dirty = false).unwrap();
+    octree.unload_level(voxel(0, 8, 0, 0)).unwrap();
+    assert_eq!(octree.levels.len(), 2, "The cache should not keep the entry of an unloaded level alive");
+    let report = cache.update_lod(&octree, 0).unwrap();
+    assert_eq!((report.rebuilt, report.removed), (vec![voxel(0, 4, 0, 0)], vec![voxel(0, 8, 0, 0)]));
+    assert_eq!(cache.len(), 2);
+    octree.load_level(voxel(0, 8, 0, 0)).unwrap();
+    let report = cache.update(&octree, &[voxel(0, 4, 0, 0), voxel(0, 8, 0, 0)]).unwrap();
+    assert_eq!(report.rebuilt, vec![voxel(0, 4, 0, 0), voxel(0, 8, 0, 0)]);
+    assert!(cache.get(&octree, &voxel(0, 8, 0, 0)).unwrap().is_empty());
+    println!("Greedy meshing passed");
+}
\ No newline at end of file
@@ -1,4 +1,5 @@
 pub mod molecule_objekt;
+pub mod objekt_snapshot;
 pub mod synchronization_graph;
 pub mod tasks;
 pub mod util;
\ No newline at end of file
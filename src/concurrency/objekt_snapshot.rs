@@ -0,0 +1,188 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        Arc,
+        Mutex,
+        RwLock,
+    },
+};
+
+use crate::{
+    concurrency::molecule_objekt::{
+        InnerObjektList,
+        MoleculeObjekt,
+        ObjektList,
+    },
+    get_engine_version,
+    metadata::versions::MoleculeVersion,
+    utils::binary::{
+        ByteReader,
+        write_bytes,
+        write_str,
+        write_u32,
+    },
+};
+
+pub const SNAPSHOT_MAGIC:&'static [u8;8] = b"MOLSNAP\0";
+pub const SNAPSHOT_FORMAT_VERSION:u32 = 1;
+
+//opt-in persistence for objekts. Types that do not implement this are left out when a snapshot is written, see WrittenSnapshot.
+pub trait SerializableObjekt: MoleculeObjekt + Sized {
+    fn type_tag() -> &'static str;//must be unique among the types registered with an ObjektTypeRegistry, and stable across releases
+    fn serialize(&self, out:&mut Vec<u8>) -> Result<(), String>;
+    fn deserialize(name:String, reader:&mut ByteReader) -> Result<Self, String>;
+}
+
+type SaveFn = fn(&dyn MoleculeObjekt, &mut Vec<u8>) -> Option<Result<(), String>>;
+type LoadFn = fn(String, &mut ByteReader) -> Result<Arc<RwLock<dyn MoleculeObjekt>>, String>;
+
+struct ObjektTypeEntry {
+    type_tag:&'static str,
+    save:SaveFn,
+    load:LoadFn,
+}
+
+fn save_objekt<T: SerializableObjekt>(objekt:&dyn MoleculeObjekt, out:&mut Vec<u8>) -> Option<Result<(), String>> {
+    objekt.downcast_ref::<T>().map(|objekt| objekt.serialize(out))
+}
+
+fn load_objekt<T: SerializableObjekt>(name:String, reader:&mut ByteReader) -> Result<Arc<RwLock<dyn MoleculeObjekt>>, String> {
+    Ok(Arc::new(RwLock::new(T::deserialize(name, reader)?)))
+}
+
+#[derive(Default)]
+pub struct ObjektTypeRegistry {
+    entries:Vec<ObjektTypeEntry>,
+}
+
+impl ObjektTypeRegistry {
+    pub fn register<T: SerializableObjekt>(&mut self) -> Result<(), String> {
+        if self.entries.iter().any(|entry| entry.type_tag==T::type_tag()) {
+            return Err(format!("Objekt type tag {} is registered more than once.", T::type_tag()));
+        }
+        self.entries.push(ObjektTypeEntry {
+            type_tag:T::type_tag(),
+            save:save_objekt::<T>,
+            load:load_objekt::<T>,
+        });
+        Ok(())
+    }
+
+    pub fn contains(&self, type_tag:&str) -> bool {
+        self.entries.iter().any(|entry| entry.type_tag==type_tag)
+    }
+}
+
+#[derive(Debug)]
+pub struct SnapshotHeader {
+    pub format_version:u32,
+    pub engine_version:MoleculeVersion,
+    pub objekt_count:u32,
+}
+
+fn read_header(reader:&mut ByteReader) -> Result<SnapshotHeader, String> {
+    if reader.read_slice(SNAPSHOT_MAGIC.len())? != &SNAPSHOT_MAGIC[..] {
+        return Err(String::from("Data is not an objekt snapshot."));
+    }
+    let format_version = reader.read_u32()?;
+    if format_version>SNAPSHOT_FORMAT_VERSION {
+        return Err(format!("Snapshot format version {} is newer than the supported version {}.", format_version, SNAPSHOT_FORMAT_VERSION));
+    }
+    Ok(SnapshotHeader {
+        format_version:format_version,
+        engine_version:MoleculeVersion::read(reader)?,
+        objekt_count:reader.read_u32()?,
+    })
+}
+
+pub fn read_snapshot_header(data:&[u8]) -> Result<SnapshotHeader, String> {
+    read_header(&mut ByteReader::new(data))
+}
+
+#[derive(Debug)]
+pub struct WrittenSnapshot {
+    pub data:Vec<u8>,
+    //names of the objekts left out because their type is not registered, in list order. Reading the snapshot back
+    //only rebuilds the list exactly when this is empty.
+    pub skipped:Vec<String>,
+}
+
+//writes every objekt whose type is registered, in list order
+pub fn write_snapshot(objekt_list:&InnerObjektList, registry:&ObjektTypeRegistry) -> Result<WrittenSnapshot, String> {
+    let mut entries = vec![];
+    let mut skipped = vec![];
+    for objekt_lock in objekt_list {
+        let objekt = objekt_lock.read().unwrap();
+        let mut saved = false;
+        for entry in &registry.entries {
+            let mut payload = vec![];
+            if let Some(result) = (entry.save)(&*objekt, &mut payload) {
+                result.map_err(|msg| format!("Could not serialize objekt {}: {}", objekt.name(), msg))?;
+                entries.push((objekt.name(), entry.type_tag, payload));
+                saved = true;
+                break;
+            }
+        }
+        if !saved {
+            skipped.push(objekt.name());
+        }
+    }
+
+    let mut out = vec![];
+    out.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u32(&mut out, SNAPSHOT_FORMAT_VERSION);
+    get_engine_version().write(&mut out);
+    write_u32(&mut out, entries.len() as u32);
+    for (name, type_tag, payload) in entries {
+        write_str(&mut out, &name);
+        write_str(&mut out, type_tag);
+        write_bytes(&mut out, &payload);
+    }
+    Ok(WrittenSnapshot {
+        data:out,
+        skipped:skipped,
+    })
+}
+
+pub fn read_snapshot(data:&[u8], registry:&ObjektTypeRegistry) -> Result<InnerObjektList, String> {
+    let mut reader = ByteReader::new(data);
+    let header = read_header(&mut reader)?;
+    let mut objekt_list:InnerObjektList = vec![];
+    for _i in 0..header.objekt_count {
+        let name = reader.read_str()?;
+        let type_tag = reader.read_str()?;
+        let payload = reader.read_bytes()?;
+        let entry = match registry.entries.iter().find(|entry| entry.type_tag==type_tag) {
+            Some(entry) => entry,
+            None => return Err(format!("Objekt {} has type tag {}, which is not registered.", name, type_tag)),
+        };
+        let mut payload_reader = ByteReader::new(payload);
+        let objekt = (entry.load)(name.clone(), &mut payload_reader).map_err(|msg| format!("Could not deserialize objekt {}: {}", name, msg))?;
+        if payload_reader.remaining()!=0 {
+            return Err(format!("Objekt {} left {} unread bytes in its payload.", name, payload_reader.remaining()));
+        }
+        objekt_list.push(objekt);
+    }
+    Ok(objekt_list)
+}
+
+//returns the names of the objekts that were left out, see WrittenSnapshot::skipped
+pub fn save_snapshot<P: AsRef<Path>>(objekt_list:&ObjektList, registry:&ObjektTypeRegistry, path:P) -> Result<Vec<String>, String> {
+    let snapshot = {
+        let objekt_list = objekt_list.lock().unwrap();
+        write_snapshot(&objekt_list, registry)?
+    };
+    //write beside the target and rename over it, so a crash mid-save never leaves a truncated snapshot behind
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, snapshot.data).map_err(|e| format!("Could not write snapshot to {}: {}", temp_path.display(), e))?;
+    fs::rename(&temp_path, path).map_err(|e| format!("Could not move snapshot into place at {}: {}", path.display(), e))?;
+    Ok(snapshot.skipped)
+}
+
+pub fn load_snapshot<P: AsRef<Path>>(registry:&ObjektTypeRegistry, path:P) -> Result<ObjektList, String> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| format!("Could not read snapshot from {}: {}", path.display(), e))?;
+    Ok(Arc::new(Mutex::new(read_snapshot(&data, registry)?)))
+}
\ No newline at end of file
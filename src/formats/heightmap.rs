@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    math::{
+        octree_math::{
+            index_in_level,
+            voxel_size,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        level_generator::LevelGenerator,
+        material_composition::MaterialComposition,
+        particle::ParticleVec,
+    },
+};
+
+//Coarse voxels look at up to this many columns per axis of their footprint. Up to LOD 3 that is every LOD 0 column, so
+//those levels come out exactly as downsampling the finer ones with DownsamplePolicy::Majority would make them, and
+//coarser ones come close.
+pub const MAX_COLUMN_SAMPLES:u64 = 8;
+
+//A grid of 16-bit heights, row by row: x runs along a row and z from one row to the next. The fields are only set by
+//new, so every Heightmap holds at least one sample and exactly width*depth of them, which sample relies on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width:usize,
+    depth:usize,
+    heights:Vec<u16>,
+}
+
+impl Heightmap {
+    pub fn new(width:usize, depth:usize, heights:Vec<u16>) -> Result<Self, String> {
+        if width==0 || depth==0 {
+            return Err(format!("Heightmap of {}x{} samples is empty", width, depth));
+        }
+        if heights.len()!=width*depth {
+            return Err(format!("Heightmap of {}x{} samples was given {} heights", width, depth, heights.len()));
+        }
+        Ok(Self {
+            width:width,
+            depth:depth,
+            heights:heights,
+        })
+    }
+
+    //.r16 files are bare little-endian heights without a header, so the width has to come from elsewhere
+    pub fn read_r16(data:&[u8], width:usize) -> Result<Self, String> {
+        if !data.len().is_multiple_of(2) {
+            return Err(format!("Raw 16-bit heightmap has an odd length of {} bytes", data.len()));
+        }
+        let count = data.len()/2;
+        if width==0 || !count.is_multiple_of(width) {
+            return Err(format!("Raw 16-bit heightmap of {} samples does not split into rows of {}", count, width));
+        }
+        let heights = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        Self::new(width, count/width, heights)
+    }
+
+    pub fn write_r16(&self) -> Vec<u8> {
+        self.heights.iter().flat_map(|height| height.to_le_bytes()).collect()
+    }
+
+    //takes 16-bit grayscale images, and 8-bit ones stretched to the full 16-bit range
+    pub fn read_png(data:&[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().map_err(|e| format!("Could not read PNG header: {}", e))?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| format!("Could not read PNG image: {}", e))?;
+        let (width, depth) = (info.width as usize, info.height as usize);
+        let heights = match (info.color_type, info.bit_depth) {
+            (png::ColorType::Grayscale, png::BitDepth::Sixteen) => buffer.chunks_exact(info.line_size)
+                .flat_map(|row| row[..width*2].chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])))
+                .collect(),
+            (png::ColorType::Grayscale, png::BitDepth::Eight) => buffer.chunks_exact(info.line_size)
+                .flat_map(|row| row[..width].iter().map(|value| *value as u16*257))
+                .collect(),
+            (color_type, bit_depth) => return Err(format!("PNG heightmaps must be 8 or 16-bit grayscale, not {:?} at {:?}", color_type, bit_depth)),
+        };
+        Self::new(width, depth, heights)
+    }
+
+    pub fn write_png(&self) -> Result<Vec<u8>, String> {
+        let mut out = vec![];
+        let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.depth as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().map_err(|e| format!("Could not write PNG header: {}", e))?;
+        let bytes:Vec<u8> = self.heights.iter().flat_map(|height| height.to_be_bytes()).collect();
+        writer.write_image_data(&bytes).map_err(|e| format!("Could not write PNG image: {}", e))?;
+        writer.finish().map_err(|e| format!("Could not finish PNG image: {}", e))?;
+        Ok(out)
+    }
+
+    pub fn load_png(path:&Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read heightmap {}: {}", path.display(), e))?;
+        Self::read_png(&data).map_err(|msg| format!("Could not parse heightmap {}: {}", path.display(), msg))
+    }
+
+    //the width is needed for the same reason as in read_r16
+    pub fn load_r16(path:&Path, width:usize) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read heightmap {}: {}", path.display(), e))?;
+        Self::read_r16(&data, width).map_err(|msg| format!("Could not parse heightmap {}: {}", path.display(), msg))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn heights(&self) -> &[u16] {
+        &self.heights
+    }
+
+    pub fn get(&self, x:usize, z:usize) -> u16 {
+        self.heights[z*self.width+x]
+    }
+
+    //bilinear between the samples, holding the edge samples beyond the borders
+    pub fn sample(&self, x:f64, z:f64) -> f64 {
+        let x = x.max(0.0).min((self.width-1) as f64);
+        let z = z.max(0.0).min((self.depth-1) as f64);
+        let (x0, z0) = (x.floor() as usize, z.floor() as usize);
+        let (x1, z1) = ((x0+1).min(self.width-1), (z0+1).min(self.depth-1));
+        let (fx, fz) = (x-x0 as f64, z-z0 as f64);
+        let near = self.get(x0, z0) as f64*(1.0-fx)+self.get(x1, z0) as f64*fx;
+        let far = self.get(x0, z1) as f64*(1.0-fx)+self.get(x1, z1) as f64*fx;
+        near*(1.0-fz)+far*fz
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainLayer {
+    pub material:u16,
+    pub thickness:f64,//in LOD 0 voxels; the last layer reaches down forever
+}
+
+//Terrain from a heightmap, with y up. Sample (i, j) covers the square from offset + (i, j)*horizontal_scale to one
+//horizontal_scale further on x and z, and a height of h puts the surface at offset[1] + h*vertical_scale.
+//Below the surface the layers follow each other from the top down.
+pub struct HeightmapGenerator {
+    pub heightmap:Heightmap,
+    pub layers:Vec<TerrainLayer>,
+    pub horizontal_scale:f64,//LOD 0 voxels per sample
+    pub vertical_scale:f64,//LOD 0 voxels per height step
+    pub offset:[f64;3],
+}
+
+impl HeightmapGenerator {
+    pub fn new(heightmap:Heightmap, layers:Vec<TerrainLayer>) -> Self {
+        Self {
+            heightmap:heightmap,
+            layers:layers,
+            horizontal_scale:1.0,
+            vertical_scale:1.0,
+            offset:[0.0; 3],
+        }
+    }
+
+    pub fn surface_height(&self, x:f64, z:f64) -> f64 {
+        let sample_x = (x-self.offset[0])/self.horizontal_scale-0.5;
+        let sample_z = (z-self.offset[2])/self.horizontal_scale-0.5;
+        self.offset[1]+self.heightmap.sample(sample_x, sample_z)*self.vertical_scale
+    }
+
+    //The material of size LOD 0 voxels stacked up from min_y in one column, whose layers start at tops, each taking the
+    //layer at the depth of its center, and the most common among them standing for all. Stays empty if more of them are empty.
+    fn column_material(&self, tops:&[f64], min_y:f64, size:u64) -> Option<u16> {
+        let mut votes = BTreeMap::new();
+        let mut solid = 0;
+        for (index, layer) in self.layers.iter().enumerate() {
+            let bottom = tops.get(index+1).cloned().unwrap_or(f64::NEG_INFINITY);
+            let count = centers_below(tops[index], min_y, size)-centers_below(bottom, min_y, size);
+            if count>0 {
+                *votes.entry(Some(layer.material)).or_insert(0)+=count;
+                solid+=count;
+            }
+        }
+        votes.insert(None, size-solid);
+        majority(votes)
+    }
+
+    //Splits a block of cells, cells_per_axis a power of two, into eighths until single cells remain, then votes the way
+    //DownsamplePolicy::Majority does at each step, so a coarse voxel comes out as downsampling its children would make it.
+    //Cells are cell_size LOD 0 voxels on a side and take their material from the column through their center, whose layer
+    //tops follow each other in tops.
+    fn block_material(&self, tops:&[f64], samples:u64, cell_size:u64, min_y:f64, cell:[u64;3], cells_per_axis:u64) -> Option<u16> {
+        if cells_per_axis==1 {
+            let column = (cell[0]*samples+cell[2]) as usize*self.layers.len();
+            return self.column_material(&tops[column..column+self.layers.len()], min_y+(cell[1]*cell_size) as f64, cell_size);
+        }
+        let half = cells_per_axis/2;
+        let mut votes = BTreeMap::new();
+        for child in 0..8 {
+            let child_cell = [cell[0]+(child>>2&1)*half, cell[1]+(child>>1&1)*half, cell[2]+(child&1)*half];
+            *votes.entry(self.block_material(tops, samples, cell_size, min_y, child_cell, half)).or_insert(0)+=1;
+        }
+        majority(votes)
+    }
+}
+
+//the material with the most votes, the lowest of any tied, unless at least as many voted for nothing
+fn majority(votes:BTreeMap<Option<u16>, u64>) -> Option<u16> {
+    let empty = votes.get(&None).cloned().unwrap_or(0);
+    let mut winner = None;
+    let mut winner_votes = 0;
+    for (material, count) in votes {
+        if material.is_some() && count>winner_votes {
+            winner = material;
+            winner_votes = count;
+        }
+    }
+    winner.filter(|_| winner_votes>=empty)
+}
+
+//how many of the size LOD 0 voxel centers stacked up from min_y lie below height
+fn centers_below(height:f64, min_y:f64, size:u64) -> u64 {
+    (height-min_y-0.5).ceil().max(0.0).min(size as f64) as u64
+}
+
+impl LevelGenerator for HeightmapGenerator {
+    fn generate(&self, origin:&VoxelLocation, level_length:u64, particles:&mut ParticleVec) {
+        if self.layers.is_empty() {
+            return;
+        }
+        let size = voxel_size(origin.lod);
+        let samples = size.min(MAX_COLUMN_SAMPLES);
+        let step = size/samples;
+        let layer_count = self.layers.len();
+        let mut tops = Vec::with_capacity((samples*samples) as usize*layer_count);
+        let mut spans = vec![(0.0, 0.0); layer_count];
+        for x in origin.vec.x..origin.vec.x+level_length {
+            for z in origin.vec.z..origin.vec.z+level_length {
+                //Worked out once per column of the level: the layer tops of evenly spread LOD 0 columns within the footprint,
+                //and the span of heights over which each layer covers all of those columns
+                tops.clear();
+                for column_x in 0..samples {
+                    for column_z in 0..samples {
+                        let world_x = (x*size+column_x*step+step/2) as f64+0.5;
+                        let world_z = (z*size+column_z*step+step/2) as f64+0.5;
+                        let mut top = self.surface_height(world_x, world_z);
+                        for layer in &self.layers {
+                            tops.push(top);
+                            top-=layer.thickness;
+                        }
+                    }
+                }
+                for (index, span) in spans.iter_mut().enumerate() {
+                    let bottom = if index+1==layer_count {
+                        f64::NEG_INFINITY
+                    } else {
+                        tops[index+1..].iter().step_by(layer_count).cloned().fold(f64::NEG_INFINITY, f64::max)
+                    };
+                    *span = (bottom, tops[index..].iter().step_by(layer_count).cloned().fold(f64::INFINITY, f64::min));
+                }
+                let highest = tops.iter().step_by(layer_count).cloned().fold(f64::NEG_INFINITY, f64::max);
+                for y in origin.vec.y..origin.vec.y+level_length {
+                    let min_y = (y*size) as f64;
+                    if min_y+0.5>=highest {
+                        break;
+                    }
+                    //only voxels on a layer boundary in some of the columns need a vote
+                    let material = match spans.iter().position(|(bottom, top)| min_y+0.5>=*bottom && min_y+size as f64-0.5<*top) {
+                        Some(index) => Some(self.layers[index].material),
+                        None => self.block_material(&tops, samples, step, min_y, [0; 3], samples),
+                    };
+                    if let Some(material) = material {
+                        let location = VoxelLocation {
+                            lod:origin.lod,
+                            vec:Vector3U64 {
+                                x:x,
+                                y:y,
+                                z:z,
+                            },
+                        };
+                        particles.material[index_in_level(&location, level_length)] = MaterialComposition::one_hot(material);
+                    }
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
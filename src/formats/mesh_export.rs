@@ -0,0 +1,242 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    math::vectors::VoxelLocation,
+    meshing::mesh::{
+        Mesh,
+        MeshOptions,
+    },
+    objekt_impl::{
+        materials::MaterialRegistry,
+        storage::hybrid_octree::HybridOctree,
+    },
+    utils::binary::write_u32,
+};
+
+pub const GLB_MAGIC:&'static [u8;4] = b"glTF";
+pub const GLB_VERSION:u32 = 2;
+const GLB_JSON_CHUNK:u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK:u32 = 0x004E4942;
+const GLTF_FLOAT:u32 = 5126;
+const GLTF_UNSIGNED_INT:u32 = 5125;
+const GLTF_ARRAY_BUFFER:u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER:u32 = 34963;
+
+//The meshes of a chosen set of levels, written out as one node each so they can be told apart in other tools.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelMeshes {
+    pub levels:Vec<(VoxelLocation, Mesh)>,
+}
+
+impl HybridOctree {
+    //loads the levels if needed; neighbours are not loaded, so borders with unloaded levels are meshed as open
+    pub fn level_meshes(&self, origins:&[VoxelLocation], options:&MeshOptions) -> Result<LevelMeshes, String> {
+        let mut meshes = LevelMeshes::default();
+        for origin in origins {
+            self.load_level(origin.clone())?;
+            meshes.levels.push((origin.clone(), self.mesh_level(origin, options)?));
+        }
+        Ok(meshes)
+    }
+}
+
+pub fn level_node_name(origin:&VoxelLocation) -> String {
+    format!("level_{}_{}_{}_{}", origin.lod, origin.vec.x, origin.vec.y, origin.vec.z)
+}
+
+//registry names with whitespace replaced, as OBJ and MTL split on it, or material_<index> for undefined materials
+pub fn export_material_name(registry:&MaterialRegistry, material:u16) -> String {
+    match registry.get(material) {
+        Some(definition) => definition.name.split_whitespace().collect::<Vec<_>>().join("_"),
+        None => format!("material_{}", material),
+    }
+}
+
+//Triangles grouped by the material of their first vertex. Surface nets can give the corners of a triangle different materials,
+//and a triangle can only be drawn with one.
+fn triangles_by_material(mesh:&Mesh) -> BTreeMap<u16, Vec<u32>> {
+    let mut groups:BTreeMap<u16, Vec<u32>> = BTreeMap::new();
+    for triangle in mesh.indices.chunks_exact(3) {
+        groups.entry(mesh.materials[triangle[0] as usize]).or_default().extend_from_slice(triangle);
+    }
+    groups
+}
+
+fn json_string(value:&str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32)<0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+//glTF colors are linear, material colors sRGB
+fn srgb_to_linear(value:u8) -> f32 {
+    let value = value as f32/255.0;
+    if value<=0.04045 {
+        value/12.92
+    } else {
+        ((value+0.055)/1.055).powf(2.4)
+    }
+}
+
+impl LevelMeshes {
+    //materials of any triangle, lowest index first
+    pub fn materials(&self) -> Vec<u16> {
+        let mut materials:Vec<u16> = self.levels.iter().flat_map(|(_origin, mesh)| triangles_by_material(mesh).into_keys()).collect();
+        materials.sort_unstable();
+        materials.dedup();
+        materials
+    }
+
+    //Binary glTF 2.0 with one node per level, placed at the level's origin, holding a mesh with a primitive per material.
+    //Levels without triangles still get their node, without a mesh.
+    pub fn write_glb(&self, registry:&MaterialRegistry) -> Vec<u8> {
+        let materials = self.materials();
+        let mut buffer = vec![];
+        let mut buffer_views = vec![];
+        let mut accessors = vec![];
+        let mut meshes = vec![];
+        let mut nodes = vec![];
+        //every chunk of the buffer gets its own view, all of them multiples of four bytes long
+        let mut push_view = |buffer:&mut Vec<u8>, data:&[u8], target:u32| {
+            buffer_views.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}", buffer.len(), data.len(), target));
+            buffer.extend_from_slice(data);
+            buffer_views.len()-1
+        };
+        for (origin, mesh) in &self.levels {
+            let name = json_string(&level_node_name(origin));
+            let translation = format!("[{},{},{}]", mesh.origin[0] as f32, mesh.origin[1] as f32, mesh.origin[2] as f32);
+            if mesh.is_empty() {
+                nodes.push(format!("{{\"name\":{},\"translation\":{}}}", name, translation));
+                continue;
+            }
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            let mut positions = vec![];
+            for position in &mesh.positions {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(position[axis]);
+                    max[axis] = max[axis].max(position[axis]);
+                    positions.extend_from_slice(&position[axis].to_le_bytes());
+                }
+            }
+            let normals:Vec<u8> = mesh.normals.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+            let view = push_view(&mut buffer, &positions, GLTF_ARRAY_BUFFER);
+            accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+                view, GLTF_FLOAT, mesh.vertex_count(), min[0], min[1], min[2], max[0], max[1], max[2]));
+            let position_accessor = accessors.len()-1;
+            let view = push_view(&mut buffer, &normals, GLTF_ARRAY_BUFFER);
+            accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}", view, GLTF_FLOAT, mesh.vertex_count()));
+            let normal_accessor = accessors.len()-1;
+            let mut primitives = vec![];
+            for (material, indices) in triangles_by_material(mesh) {
+                let data:Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+                let view = push_view(&mut buffer, &data, GLTF_ELEMENT_ARRAY_BUFFER);
+                accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}", view, GLTF_UNSIGNED_INT, indices.len()));
+                let material_index = materials.binary_search(&material).expect("Materials are gathered from the same meshes");
+                primitives.push(format!("{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{}}},\"indices\":{},\"material\":{}}}",
+                    position_accessor, normal_accessor, accessors.len()-1, material_index));
+            }
+            meshes.push(format!("{{\"name\":{},\"primitives\":[{}]}}", name, primitives.join(",")));
+            nodes.push(format!("{{\"name\":{},\"mesh\":{},\"translation\":{}}}", name, meshes.len()-1, translation));
+        }
+        let gltf_materials:Vec<String> = materials.iter().map(|material| {
+            let color = registry.color(*material);
+            let alpha_mode = if color[3]<255 {
+                ",\"alphaMode\":\"BLEND\""
+            } else {
+                ""
+            };
+            format!("{{\"name\":{},\"pbrMetallicRoughness\":{{\"baseColorFactor\":[{},{},{},{}],\"metallicFactor\":0,\"roughnessFactor\":1}}{}}}",
+                json_string(&export_material_name(registry, *material)),
+                srgb_to_linear(color[0]), srgb_to_linear(color[1]), srgb_to_linear(color[2]), color[3] as f32/255.0, alpha_mode)
+        }).collect();
+        let scene_nodes:Vec<String> = (0..nodes.len()).map(|node| node.to_string()).collect();
+        let mut json = format!("{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"molecule-engine\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[{}]}}],\"nodes\":[{}]",
+            scene_nodes.join(","), nodes.join(","));
+        //glTF does not allow empty arrays, so arrays with nothing in them are left out
+        if !meshes.is_empty() {
+            json.push_str(&format!(",\"meshes\":[{}],\"materials\":[{}],\"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{}}}]",
+                meshes.join(","), gltf_materials.join(","), accessors.join(","), buffer_views.join(","), buffer.len()));
+        }
+        json.push('}');
+
+        let mut json = json.into_bytes();
+        while json.len()%4!=0 {
+            json.push(b' ');
+        }
+        let mut out = vec![];
+        out.extend_from_slice(GLB_MAGIC);
+        write_u32(&mut out, GLB_VERSION);
+        let bin_chunk_len = if buffer.is_empty() {
+            0
+        } else {
+            8+buffer.len()
+        };
+        write_u32(&mut out, (12+8+json.len()+bin_chunk_len) as u32);
+        write_u32(&mut out, json.len() as u32);
+        write_u32(&mut out, GLB_JSON_CHUNK);
+        out.extend_from_slice(&json);
+        if !buffer.is_empty() {
+            write_u32(&mut out, buffer.len() as u32);
+            write_u32(&mut out, GLB_BIN_CHUNK);
+            out.extend_from_slice(&buffer);
+        }
+        out
+    }
+
+    pub fn save_glb(&self, path:&Path, registry:&MaterialRegistry) -> Result<(), String> {
+        fs::write(path, self.write_glb(registry)).map_err(|e| format!("Could not write glTF file {}: {}", path.display(), e))
+    }
+
+    //Wavefront OBJ text referring to mtl_file_name, and the MTL text to go in that file. Vertices are in world units, as OBJ
+    //has no node transforms, and each level becomes an object with a group per material.
+    pub fn write_obj(&self, registry:&MaterialRegistry, mtl_file_name:&str) -> (String, String) {
+        let mut obj = format!("# exported by molecule-engine\nmtllib {}\n", mtl_file_name);
+        let mut first_vertex = 1;
+        for (origin, mesh) in &self.levels {
+            obj.push_str(&format!("o {}\n", level_node_name(origin)));
+            for position in &mesh.positions {
+                obj.push_str(&format!("v {} {} {}\n", mesh.origin[0]+position[0] as f64, mesh.origin[1]+position[1] as f64, mesh.origin[2]+position[2] as f64));
+            }
+            for normal in &mesh.normals {
+                obj.push_str(&format!("vn {} {} {}\n", normal[0], normal[1], normal[2]));
+            }
+            for (material, indices) in triangles_by_material(mesh) {
+                obj.push_str(&format!("usemtl {}\n", export_material_name(registry, material)));
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize+first_vertex);
+                    obj.push_str(&format!("f {}//{} {}//{} {}//{}\n", a, a, b, b, c, c));
+                }
+            }
+            first_vertex+=mesh.vertex_count();
+        }
+        let mut mtl = String::from("# exported by molecule-engine\n");
+        for material in self.materials() {
+            let color = registry.color(material);
+            mtl.push_str(&format!("newmtl {}\nKd {:.6} {:.6} {:.6}\nd {:.6}\nillum 1\n",
+                export_material_name(registry, material), color[0] as f32/255.0, color[1] as f32/255.0, color[2] as f32/255.0, color[3] as f32/255.0));
+        }
+        (obj, mtl)
+    }
+
+    //writes the MTL file next to the OBJ file, with the same name
+    pub fn save_obj(&self, path:&Path, registry:&MaterialRegistry) -> Result<(), String> {
+        let mtl_path = path.with_extension("mtl");
+        let mtl_file_name = mtl_path.file_name().ok_or_else(|| format!("{} is not a file path", path.display()))?.to_string_lossy().into_owned();
+        let (obj, mtl) = self.write_obj(registry, &mtl_file_name);
+        fs::write(path, obj).map_err(|e| format!("Could not write OBJ file {}: {}", path.display(), e))?;
+        fs::write(&mtl_path, mtl).map_err(|e| format!("Could not write MTL file {}: {}", mtl_path.display(), e))
+    }
+}
\ No newline at end of file
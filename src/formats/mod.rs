@@ -0,0 +1,4 @@
+pub mod heightmap;
+pub mod mesh_export;
+pub mod ply;
+pub mod vox;
\ No newline at end of file
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    math::{
+        octree_math::voxel_range,
+        shapes::Aabb,
+    },
+    objekt_impl::{
+        materials::MaterialRegistry,
+        storage::{
+            hybrid_octree::HybridOctree,
+            material_composition::MaterialComposition,
+            particle::particle_position,
+        },
+    },
+    utils::binary::{
+        write_u16,
+        write_u64,
+        write_u8,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+impl PlyFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlyOptions {
+    pub format:PlyFormat,
+    //adds a uchar weight_<material index> property for every material held by any point, named in header comments
+    pub material_weights:bool,
+}
+
+impl PlyOptions {
+    pub fn new(format:PlyFormat) -> Self {
+        Self {
+            format:format,
+            material_weights:false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CloudPoint {
+    pub position:[f64;3],//world units, see particle_position
+    pub material:MaterialComposition,
+}
+
+//Particles that hold any material, as points for looking at in external tools
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointCloud {
+    pub points:Vec<CloudPoint>,
+}
+
+impl PointCloud {
+    //materials with a weight in any point, lowest index first
+    pub fn materials(&self) -> Vec<u16> {
+        let materials:BTreeSet<u16> = self.points.iter().flat_map(|point| point.material.iter().map(|(material, _)| material)).collect();
+        materials.into_iter().collect()
+    }
+
+    //Each point has double x, y and z, the uchar color of its dominant material from the registry, and that material's
+    //index as a ushort, followed by the weights if asked for.
+    pub fn write_ply(&self, registry:&MaterialRegistry, options:&PlyOptions) -> Vec<u8> {
+        let weighted = if options.material_weights {
+            self.materials()
+        } else {
+            vec![]
+        };
+        let mut header = format!("ply\nformat {} 1.0\ncomment exported by molecule-engine\n", options.format.name());
+        for material in &weighted {
+            let name = registry.get(*material).map_or("undefined", |definition| definition.name.as_str());
+            header.push_str(&format!("comment weight_{} is {}\n", material, name));
+        }
+        header.push_str(&format!("element vertex {}\n", self.points.len()));
+        header.push_str("property double x\nproperty double y\nproperty double z\n");
+        header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha\n");
+        header.push_str("property ushort material\n");
+        for material in &weighted {
+            header.push_str(&format!("property uchar weight_{}\n", material));
+        }
+        header.push_str("end_header\n");
+
+        let mut out = header.into_bytes();
+        for point in &self.points {
+            //points are never empty, but an empty one shows up in the undefined color rather than failing the export
+            let material = point.material.dominant(&[]).unwrap_or(0);
+            let color = registry.color(material);
+            match options.format {
+                PlyFormat::Ascii => {
+                    let mut line = format!("{} {} {} {} {} {} {} {}", point.position[0], point.position[1], point.position[2],
+                        color[0], color[1], color[2], color[3], material);
+                    for weighted_material in &weighted {
+                        line.push_str(&format!(" {}", point.material.weight(*weighted_material)));
+                    }
+                    line.push('\n');
+                    out.extend_from_slice(line.as_bytes());
+                }
+                PlyFormat::BinaryLittleEndian => {
+                    for coordinate in point.position {
+                        write_u64(&mut out, coordinate.to_bits());
+                    }
+                    out.extend_from_slice(&color);
+                    write_u16(&mut out, material);
+                    for weighted_material in &weighted {
+                        write_u8(&mut out, point.material.weight(*weighted_material));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn save_ply(&self, path:&Path, registry:&MaterialRegistry, options:&PlyOptions) -> Result<(), String> {
+        fs::write(path, self.write_ply(registry, options)).map_err(|e| format!("Could not write PLY file {}: {}", path.display(), e))
+    }
+}
+
+impl HybridOctree {
+    //Every Particle at lod with its voxel center inside bounds that holds any material, loading levels as needed.
+    pub fn point_cloud(&self, bounds:&Aabb, lod:u64) -> Result<PointCloud, String> {
+        let mut cloud = PointCloud::default();
+        if !bounds.is_finite() {
+            return Err(format!("Exports need finite bounds, not {:?}", bounds));
+        }
+        let range = match voxel_range(bounds, lod, self.level_length) {
+            Some(range) => range,
+            None => return Ok(cloud),
+        };
+        self.visit_range(&range, |location, index, contents| {
+            let material = &contents.data.material[index];
+            if !material.is_empty() {
+                cloud.points.push(CloudPoint {
+                    position:particle_position(location, &contents.data.pos[index]),
+                    material:material.clone(),
+                });
+            }
+        })?;
+        Ok(cloud)
+    }
+}
\ No newline at end of file
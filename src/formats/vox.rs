@@ -0,0 +1,688 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    convert::TryInto,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    math::{
+        octree_math::voxel_range,
+        shapes::Aabb,
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::{
+        materials::MaterialRegistry,
+        storage::{
+            hybrid_octree::HybridOctree,
+            material_composition::MaterialComposition,
+            voxel_editing::EditReport,
+        },
+    },
+    utils::binary::{
+        ByteReader,
+        write_u32,
+    },
+};
+
+pub const VOX_MAGIC:&'static [u8;4] = b"VOX ";
+pub const VOX_VERSION:u32 = 150;
+pub const VOX_MAX_MODEL_SIZE:u32 = 256;//voxels per axis of one model
+const MAX_NODE_DEPTH:usize = 64;//deeper scene graphs are taken to be cycles
+
+//The palette MagicaVoxel uses for files without an RGBA chunk, indexed by color index with 0 unused: a 6x6x6 color cube
+//from white down to (but not including) black, then ramps of red, green, blue and grey.
+pub fn default_vox_palette() -> [[u8;4];256] {
+    let mut palette = [[0u8;4];256];
+    let steps = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let mut index = 1;
+    for r in steps {
+        for g in steps {
+            for b in steps {
+                if index<216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index+=1;
+                }
+            }
+        }
+    }
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channels in [[true, false, false], [false, true, false], [false, false, true], [true, true, true]] {
+        for value in ramp {
+            palette[index] = [
+                if channels[0] {value} else {0},
+                if channels[1] {value} else {0},
+                if channels[2] {value} else {0},
+                0xff,
+            ];
+            index+=1;
+        }
+    }
+    palette
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VoxModel {
+    pub size:[u32;3],
+    pub voxels:Vec<([u8;3], u8)>,//position within the model and color index, which is never 0
+}
+
+//one placement of a model in the scene. A voxel v of the model ends up at rotation*(v-size/2)+translation, with size/2 rounded down.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxInstance {
+    pub model:usize,
+    pub rotation:[[i32;3];3],//a signed permutation matrix
+    pub translation:[i32;3],
+}
+
+const IDENTITY:[[i32;3];3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+impl VoxInstance {
+    //places a model with its lowest corner at min
+    pub fn at(model:usize, size:[u32;3], min:[i32;3]) -> Self {
+        Self {
+            model:model,
+            rotation:IDENTITY,
+            translation:[min[0]+(size[0]/2) as i32, min[1]+(size[1]/2) as i32, min[2]+(size[2]/2) as i32],
+        }
+    }
+}
+
+enum SceneNode {
+    Transform {
+        child:i32,
+        rotation:[[i32;3];3],
+        translation:[i32;3],
+    },
+    Group {
+        children:Vec<i32>,
+    },
+    Shape {
+        models:Vec<i32>,
+    },
+}
+
+//A MagicaVoxel file: models, the scene placing them, and a palette of 255 colors.
+//Only the parts that matter for voxel data are kept, materials, layers, cameras and animation frames past the first are skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxFile {
+    pub models:Vec<VoxModel>,
+    pub instances:Vec<VoxInstance>,//empty when the file has no scene graph, in which case every model sits with its lowest corner at 0
+    pub palette:[[u8;4];256],//RGBA by color index, with 0 unused
+}
+
+impl Default for VoxFile {
+    fn default() -> Self {
+        Self {
+            models:vec![],
+            instances:vec![],
+            palette:default_vox_palette(),
+        }
+    }
+}
+
+fn read_i32(reader:&mut ByteReader) -> Result<i32, String> {
+    Ok(reader.read_u32()? as i32)
+}
+
+fn read_count(reader:&mut ByteReader, item_size:usize) -> Result<usize, String> {
+    let count = reader.read_u32()? as usize;
+    //items take at least item_size bytes each, which stops absurd counts before anything is allocated
+    if count.saturating_mul(item_size)>reader.remaining() {
+        return Err(format!("Count {} at offset {} is larger than the remaining data", count, reader.offset()));
+    }
+    Ok(count)
+}
+
+fn read_string(reader:&mut ByteReader) -> Result<String, String> {
+    let len = read_count(reader, 1)?;
+    String::from_utf8(reader.read_slice(len)?.to_vec()).map_err(|e| format!("Invalid UTF-8 string: {}", e))
+}
+
+fn read_dict(reader:&mut ByteReader) -> Result<HashMap<String, String>, String> {
+    let count = read_count(reader, 8)?;
+    let mut dict = HashMap::new();
+    for _i in 0..count {
+        dict.insert(read_string(reader)?, read_string(reader)?);
+    }
+    Ok(dict)
+}
+
+fn write_string(out:&mut Vec<u8>, value:&str) {
+    write_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_dict(out:&mut Vec<u8>, entries:&[(&str, String)]) {
+    write_u32(out, entries.len() as u32);
+    for (key, value) in entries {
+        write_string(out, key);
+        write_string(out, value);
+    }
+}
+
+fn write_chunk(out:&mut Vec<u8>, id:&[u8;4], content:&[u8]) {
+    out.extend_from_slice(id);
+    write_u32(out, content.len() as u32);
+    write_u32(out, 0);
+    out.extend_from_slice(content);
+}
+
+//bits 0-1 and 2-3 give the column of the nonzero entry in the first two rows, bits 4-6 make rows negative
+fn decode_rotation(bits:u8) -> Result<[[i32;3];3], String> {
+    let first = (bits&3) as usize;
+    let second = ((bits>>2)&3) as usize;
+    if first>2 || second>2 || first==second {
+        return Err(format!("Rotation {} is not a valid rotation", bits));
+    }
+    let columns = [first, second, 3-first-second];
+    let mut rotation = [[0;3];3];
+    for row in 0..3 {
+        rotation[row][columns[row]] = if bits>>(4+row)&1==1 {-1} else {1};
+    }
+    Ok(rotation)
+}
+
+fn parse_translation(text:&str) -> Result<[i32;3], String> {
+    let values:Vec<i32> = text.split_whitespace().map(|value| value.parse::<i32>()).collect::<Result<_, _>>()
+        .map_err(|e| format!("Translation {} is not three integers: {}", text, e))?;
+    match values[..] {
+        [x, y, z] => Ok([x, y, z]),
+        _ => Err(format!("Translation {} is not three integers", text)),
+    }
+}
+
+impl VoxFile {
+    pub fn read(data:&[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(data);
+        if reader.read_slice(VOX_MAGIC.len())? != &VOX_MAGIC[..] {
+            return Err(String::from("Data is not a MagicaVoxel file."));
+        }
+        let _version = reader.read_u32()?;
+        if reader.read_slice(4)? != b"MAIN" {
+            return Err(String::from("MagicaVoxel file does not start with a MAIN chunk."));
+        }
+        let main_content = read_count(&mut reader, 1)?;
+        let _main_children = reader.read_u32()?;
+        reader.read_slice(main_content)?;
+
+        let mut file = Self::default();
+        let mut size = None;
+        let mut nodes = HashMap::new();
+        while reader.remaining()>0 {
+            let id:[u8;4] = reader.read_slice(4)?.try_into().expect("Four bytes were read");
+            let content_len = read_count(&mut reader, 1)?;
+            let children_len = reader.read_u32()? as usize;
+            let mut content = ByteReader::new(reader.read_slice(content_len)?);
+            let chunk_name = String::from_utf8_lossy(&id).to_string();
+            let parsed = match &id {
+                b"SIZE" => {
+                    size = Some([content.read_u32()?, content.read_u32()?, content.read_u32()?]);
+                    Ok(())
+                }
+                b"XYZI" => file.read_voxels(&mut content, size.take()),
+                b"RGBA" => {
+                    for index in 1..256 {
+                        file.palette[index] = content.read_slice(4)?.try_into().expect("Four bytes were read");
+                    }
+                    Ok(())
+                }
+                b"nTRN" => {
+                    let node = read_i32(&mut content)?;
+                    let _attributes = read_dict(&mut content)?;
+                    let child = read_i32(&mut content)?;
+                    let _reserved = read_i32(&mut content)?;
+                    let _layer = read_i32(&mut content)?;
+                    let frame_count = read_count(&mut content, 4)?;
+                    let mut rotation = IDENTITY;
+                    let mut translation = [0;3];
+                    //only the first frame is used, later ones animate the model
+                    if frame_count>0 {
+                        let frame = read_dict(&mut content)?;
+                        if let Some(bits) = frame.get("_r") {
+                            rotation = decode_rotation(bits.parse::<u8>().map_err(|e| format!("Rotation {} is not a number: {}", bits, e))?)?;
+                        }
+                        if let Some(text) = frame.get("_t") {
+                            translation = parse_translation(text)?;
+                        }
+                    }
+                    nodes.insert(node, SceneNode::Transform {
+                        child:child,
+                        rotation:rotation,
+                        translation:translation,
+                    });
+                    Ok(())
+                }
+                b"nGRP" => {
+                    let node = read_i32(&mut content)?;
+                    let _attributes = read_dict(&mut content)?;
+                    let count = read_count(&mut content, 4)?;
+                    let mut children = vec![];
+                    for _i in 0..count {
+                        children.push(read_i32(&mut content)?);
+                    }
+                    nodes.insert(node, SceneNode::Group {
+                        children:children,
+                    });
+                    Ok(())
+                }
+                b"nSHP" => {
+                    let node = read_i32(&mut content)?;
+                    let _attributes = read_dict(&mut content)?;
+                    let count = read_count(&mut content, 8)?;
+                    let mut models = vec![];
+                    for _i in 0..count {
+                        models.push(read_i32(&mut content)?);
+                        let _model_attributes = read_dict(&mut content)?;
+                    }
+                    nodes.insert(node, SceneNode::Shape {
+                        models:models,
+                    });
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            parsed.map_err(|msg| format!("{} chunk at offset {}: {}", chunk_name, reader.offset(), msg))?;
+            reader.read_slice(children_len)?;
+        }
+        if nodes.contains_key(&0) {
+            file.place(&nodes, 0, IDENTITY, [0;3], 0)?;
+        }
+        Ok(file)
+    }
+
+    fn read_voxels(&mut self, content:&mut ByteReader, size:Option<[u32;3]>) -> Result<(), String> {
+        let size = size.ok_or_else(|| String::from("voxels come without a SIZE chunk before them"))?;
+        let count = read_count(content, 4)?;
+        let mut voxels = vec![];
+        for _i in 0..count {
+            let voxel = content.read_slice(4)?;
+            let pos = [voxel[0], voxel[1], voxel[2]];
+            if (0..3).any(|axis| pos[axis] as u32>=size[axis]) {
+                return Err(format!("voxel at {:?} lies outside of a model of size {:?}", pos, size));
+            }
+            if voxel[3]!=0 {
+                voxels.push((pos, voxel[3]));
+            }
+        }
+        self.models.push(VoxModel {
+            size:size,
+            voxels:voxels,
+        });
+        Ok(())
+    }
+
+    fn place(&mut self, nodes:&HashMap<i32, SceneNode>, node:i32, rotation:[[i32;3];3], translation:[i32;3], depth:usize) -> Result<(), String> {
+        if depth>MAX_NODE_DEPTH {
+            return Err(format!("Scene graph is deeper than {} nodes, it probably holds a cycle", MAX_NODE_DEPTH));
+        }
+        match nodes.get(&node) {
+            Some(SceneNode::Transform { child, rotation:local_rotation, translation:local_translation }) => {
+                let rotated = rotate(&rotation, *local_translation);
+                let combined = [translation[0]+rotated[0], translation[1]+rotated[1], translation[2]+rotated[2]];
+                self.place(nodes, *child, multiply(&rotation, local_rotation), combined, depth+1)
+            }
+            Some(SceneNode::Group { children }) => {
+                for child in children {
+                    self.place(nodes, *child, rotation, translation, depth+1)?;
+                }
+                Ok(())
+            }
+            Some(SceneNode::Shape { models }) => {
+                for model in models {
+                    if *model<0 || *model as usize>=self.models.len() {
+                        return Err(format!("Scene graph places model {}, but there are only {} models", model, self.models.len()));
+                    }
+                    self.instances.push(VoxInstance {
+                        model:*model as usize,
+                        rotation:rotation,
+                        translation:translation,
+                    });
+                }
+                Ok(())
+            }
+            None => Err(format!("Scene graph refers to missing node {}", node)),
+        }
+    }
+
+    //every placed voxel with its color index, in scene coordinates
+    pub fn placed_voxels(&self) -> Vec<([i32;3], u8)> {
+        let default_instances:Vec<VoxInstance>;
+        let instances = if self.instances.is_empty() {
+            default_instances = self.models.iter().enumerate().map(|(index, model)| VoxInstance::at(index, model.size, [0;3])).collect();
+            &default_instances
+        } else {
+            &self.instances
+        };
+        let mut placed = vec![];
+        for instance in instances {
+            let model = &self.models[instance.model];
+            for (pos, color) in &model.voxels {
+                let centered = [0, 1, 2].map(|axis| pos[axis] as i32-(model.size[axis]/2) as i32);
+                let rotated = rotate(&instance.rotation, centered);
+                placed.push(([0, 1, 2].map(|axis| rotated[axis]+instance.translation[axis]), *color));
+            }
+        }
+        placed
+    }
+
+    //Layout: magic, version, then a MAIN chunk holding a SIZE and XYZI chunk per model, a scene graph when there is more
+    //than one model or any model is placed elsewhere than at 0, and the palette as an RGBA chunk.
+    pub fn write(&self) -> Vec<u8> {
+        let mut children = vec![];
+        for model in &self.models {
+            let mut size = vec![];
+            for axis in 0..3 {
+                write_u32(&mut size, model.size[axis]);
+            }
+            write_chunk(&mut children, b"SIZE", &size);
+            let mut voxels = vec![];
+            write_u32(&mut voxels, model.voxels.len() as u32);
+            for (pos, color) in &model.voxels {
+                voxels.extend_from_slice(&[pos[0], pos[1], pos[2], *color]);
+            }
+            write_chunk(&mut children, b"XYZI", &voxels);
+        }
+
+        //without instances, or with only the ones a reader assumes when there is no scene graph, none is written
+        let default_instances:Vec<VoxInstance> = self.models.iter().enumerate().map(|(index, model)| VoxInstance::at(index, model.size, [0;3])).collect();
+        if !self.instances.is_empty() && self.instances!=default_instances {
+            //a root transform holding a group of one transform and shape per instance, numbered in that order
+            let mut root = vec![];
+            write_u32(&mut root, 0);
+            write_dict(&mut root, &[]);
+            write_u32(&mut root, 1);
+            write_u32(&mut root, u32::MAX);
+            write_u32(&mut root, u32::MAX);
+            write_u32(&mut root, 1);
+            write_dict(&mut root, &[]);
+            write_chunk(&mut children, b"nTRN", &root);
+            let mut group = vec![];
+            write_u32(&mut group, 1);
+            write_dict(&mut group, &[]);
+            write_u32(&mut group, self.instances.len() as u32);
+            for index in 0..self.instances.len() {
+                write_u32(&mut group, 2+2*index as u32);
+            }
+            write_chunk(&mut children, b"nGRP", &group);
+            for (index, instance) in self.instances.iter().enumerate() {
+                let mut transform = vec![];
+                write_u32(&mut transform, 2+2*index as u32);
+                write_dict(&mut transform, &[]);
+                write_u32(&mut transform, 3+2*index as u32);
+                write_u32(&mut transform, u32::MAX);
+                write_u32(&mut transform, 0);
+                write_u32(&mut transform, 1);
+                let translation = instance.translation;
+                write_dict(&mut transform, &[
+                    ("_r", encode_rotation(&instance.rotation).to_string()),
+                    ("_t", format!("{} {} {}", translation[0], translation[1], translation[2])),
+                ]);
+                write_chunk(&mut children, b"nTRN", &transform);
+                let mut shape = vec![];
+                write_u32(&mut shape, 3+2*index as u32);
+                write_dict(&mut shape, &[]);
+                write_u32(&mut shape, 1);
+                write_u32(&mut shape, instance.model as u32);
+                write_dict(&mut shape, &[]);
+                write_chunk(&mut children, b"nSHP", &shape);
+            }
+        }
+
+        let mut palette = vec![];
+        for index in 1..256 {
+            palette.extend_from_slice(&self.palette[index]);
+        }
+        palette.extend_from_slice(&[0;4]);
+        write_chunk(&mut children, b"RGBA", &palette);
+
+        let mut out = vec![];
+        out.extend_from_slice(VOX_MAGIC);
+        write_u32(&mut out, VOX_VERSION);
+        out.extend_from_slice(b"MAIN");
+        write_u32(&mut out, 0);
+        write_u32(&mut out, children.len() as u32);
+        out.extend_from_slice(&children);
+        out
+    }
+
+    pub fn load(path:&Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read MagicaVoxel file {}: {}", path.display(), e))?;
+        Self::read(&data).map_err(|msg| format!("Could not parse MagicaVoxel file {}: {}", path.display(), msg))
+    }
+
+    pub fn save(&self, path:&Path) -> Result<(), String> {
+        fs::write(path, self.write()).map_err(|e| format!("Could not write MagicaVoxel file {}: {}", path.display(), e))
+    }
+}
+
+fn rotate(rotation:&[[i32;3];3], vector:[i32;3]) -> [i32;3] {
+    [0, 1, 2].map(|row| (0..3).map(|column| rotation[row][column]*vector[column]).sum())
+}
+
+fn multiply(a:&[[i32;3];3], b:&[[i32;3];3]) -> [[i32;3];3] {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|k| a[row][k]*b[k][column]).sum()))
+}
+
+fn encode_rotation(rotation:&[[i32;3];3]) -> u8 {
+    let mut bits = 0;
+    for (row, values) in rotation.iter().enumerate() {
+        let column = (0..3).find(|column| values[*column]!=0).unwrap_or(row);
+        if row<2 {
+            bits|=(column as u8)<<(2*row);
+        }
+        if values[column]<0 {
+            bits|=1<<(4+row);
+        }
+    }
+    bits
+}
+
+//Which material each of the 255 colors of a .vox palette stands for. Kept in a text file of `color index = material` lines,
+//where the material is a name from the MaterialRegistry or its index, and `#` starts a comment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxPaletteMapping {
+    materials:Vec<Option<u16>>,//indexed by color index
+}
+
+impl Default for VoxPaletteMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VoxPaletteMapping {
+    pub fn new() -> Self {
+        Self {
+            materials:vec![None; 256],
+        }
+    }
+
+    pub fn set(&mut self, color:u8, material:Option<u16>) -> Result<(), String> {
+        if color==0 {
+            return Err(String::from("Color index 0 marks empty voxels and cannot be mapped"));
+        }
+        self.materials[color as usize] = material;
+        Ok(())
+    }
+
+    pub fn material(&self, color:u8) -> Option<u16> {
+        self.materials[color as usize]
+    }
+
+    //the lowest color index standing for material
+    pub fn color(&self, material:u16) -> Option<u8> {
+        (1..=255u8).find(|color| self.materials[*color as usize]==Some(material))
+    }
+
+    //maps every color to the defined material of the closest color, for when nobody wrote a mapping yet
+    pub fn nearest_colors(palette:&[[u8;4];256], registry:&MaterialRegistry) -> Self {
+        let mut mapping = Self::new();
+        for (material, color) in mapping.materials.iter_mut().zip(palette.iter()).skip(1) {
+            let distance = |other:[u8;4]| (0..3).map(|channel| (color[channel] as i32-other[channel] as i32).pow(2)).sum::<i32>();
+            *material = registry.iter()
+                .min_by_key(|(_index, definition)| distance(definition.color))
+                .map(|(index, _definition)| index);
+        }
+        mapping
+    }
+
+    pub fn parse(text:&str, registry:&MaterialRegistry) -> Result<Self, String> {
+        let mut mapping = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (color, material) = line.split_once('=')
+                .map(|(color, material)| (color.trim(), material.trim()))
+                .ok_or_else(|| format!("Line {}: expected color index = material, found {}", number+1, line))?;
+            let color = color.parse::<u8>().map_err(|_| format!("Line {}: color index {} should be a number from 1 to 255", number+1, color))?;
+            let material = match registry.index_of(material) {
+                Some(index) => index,
+                None => material.parse::<u16>().ok().filter(|index| registry.get(*index).is_some())
+                    .ok_or_else(|| format!("Line {}: {} is neither the name nor the index of a defined material", number+1, material))?,
+            };
+            mapping.set(color, Some(material)).map_err(|msg| format!("Line {}: {}", number+1, msg))?;
+        }
+        Ok(mapping)
+    }
+
+    //the text format read by parse, using material names where the registry has them
+    pub fn to_text(&self, registry:&MaterialRegistry) -> String {
+        let mut text = String::new();
+        for color in 1..256 {
+            if let Some(material) = self.materials[color] {
+                match registry.get(material) {
+                    Some(definition) => text.push_str(&format!("{} = {}\n", color, definition.name)),
+                    None => text.push_str(&format!("{} = {}\n", color, material)),
+                }
+            }
+        }
+        text
+    }
+}
+
+//how .vox axes line up with the octree's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoxAxes {
+    Unchanged,
+    YUp,//MagicaVoxel's z up becomes y up, keeping the model right handed: (x, y, z) in the file is (x, z, -y) in the octree
+}
+
+impl VoxAxes {
+    fn to_octree(self, pos:[i32;3]) -> [i64;3] {
+        match self {
+            VoxAxes::Unchanged => [pos[0] as i64, pos[1] as i64, pos[2] as i64],
+            VoxAxes::YUp => [pos[0] as i64, pos[2] as i64, -(pos[1] as i64)],
+        }
+    }
+
+    fn to_vox(self, pos:[i64;3]) -> [i64;3] {
+        match self {
+            VoxAxes::Unchanged => pos,
+            VoxAxes::YUp => [pos[0], -pos[2], pos[1]],
+        }
+    }
+}
+
+impl HybridOctree {
+    //Writes every voxel of the file's scene at one LOD as its mapped material at full weight, with the lowest corner of the
+    //scene's bounds at origin. Voxels the scene leaves empty keep what they held. Fails before changing anything if a color is not mapped.
+    pub fn import_vox(&self, vox:&VoxFile, mapping:&VoxPaletteMapping, origin:&VoxelLocation, axes:VoxAxes) -> Result<EditReport, String> {
+        let placed:Vec<([i64;3], u8)> = vox.placed_voxels().into_iter().map(|(pos, color)| (axes.to_octree(pos), color)).collect();
+        let min = [0, 1, 2].map(|axis| placed.iter().map(|(pos, _color)| pos[axis]).min().unwrap_or(0));
+        let mut voxels = vec![];
+        for (pos, color) in placed {
+            let material = mapping.material(color).ok_or_else(|| format!("Color index {} is not mapped to a material", color))?;
+            let offset = [0, 1, 2].map(|axis| (pos[axis]-min[axis]) as u64);
+            voxels.push((
+                Vector3U64 {
+                    x:origin.vec.x+offset[0],
+                    y:origin.vec.y+offset[1],
+                    z:origin.vec.z+offset[2],
+                },
+                MaterialComposition::one_hot(material),
+            ));
+        }
+        self.set_voxels(origin.lod, &voxels)
+    }
+
+    //Exports the voxels at one LOD whose centers lie within bounds, each as the color its dominant material is mapped to.
+    //Materials without a color get an unmapped one, colored as in the registry. Regions larger than VOX_MAX_MODEL_SIZE
+    //along an axis are split into several models. Levels that are not loaded are loaded first.
+    pub fn export_vox(&self, bounds:&Aabb, lod:u64, mapping:&VoxPaletteMapping, registry:&MaterialRegistry, axes:VoxAxes) -> Result<VoxFile, String> {
+        let mut vox = VoxFile::default();
+        if !bounds.is_finite() {
+            return Err(format!("Exports need finite bounds, not {:?}", bounds));
+        }
+        let range = match voxel_range(bounds, lod, self.level_length) {
+            Some(range) => range,
+            None => return Ok(vox),
+        };
+        let first = range.first.clone();
+        let mut materials = vec![];
+        self.visit_range(&range, |location, index, contents| {
+            if let Some(material) = contents.data.material[index].dominant(&[]) {
+                materials.push(([location.vec.x-first.x, location.vec.y-first.y, location.vec.z-first.z], material));
+            }
+        })?;
+        let mut colors:HashMap<u16, u8> = HashMap::new();
+        let mut next_free = 1u16;
+        let mut placed = vec![];
+        for (offset, material) in materials {
+            let color = match colors.get(&material) {
+                Some(color) => *color,
+                None => {
+                    let color = match mapping.color(material) {
+                        Some(color) => color,
+                        None => {
+                            while next_free<256 && (mapping.material(next_free as u8).is_some() || colors.values().any(|color| *color as u16==next_free)) {
+                                next_free+=1;
+                            }
+                            if next_free==256 {
+                                return Err(String::from("Region holds more materials than a .vox palette has colors"));
+                            }
+                            vox.palette[next_free as usize] = registry.color(material);
+                            next_free as u8
+                        }
+                    };
+                    colors.insert(material, color);
+                    color
+                }
+            };
+            placed.push((axes.to_vox([offset[0] as i64, offset[1] as i64, offset[2] as i64]), color));
+        }
+        for (material, color) in &colors {
+            if mapping.color(*material).is_some() {
+                vox.palette[*color as usize] = registry.color(*material);
+            }
+        }
+
+        //split into models of at most VOX_MAX_MODEL_SIZE voxels per axis, each with its lowest corner at a multiple of that
+        let min = [0, 1, 2].map(|axis| placed.iter().map(|(pos, _color)| pos[axis]).min().unwrap_or(0));
+        let max_size = VOX_MAX_MODEL_SIZE as i64;
+        let mut models:BTreeMap<[i64;3], Vec<([u8;3], u8)>> = BTreeMap::new();
+        for (pos, color) in placed {
+            let relative = [0, 1, 2].map(|axis| pos[axis]-min[axis]);
+            let model = relative.map(|value| value/max_size);
+            models.entry(model).or_default().push((relative.map(|value| (value%max_size) as u8), color));
+        }
+        for (model, voxels) in models {
+            let size = [0, 1, 2].map(|axis| voxels.iter().map(|(pos, _color)| pos[axis] as u32+1).max().unwrap_or(1));
+            let corner = model.map(|value| (value*max_size) as i32);
+            vox.instances.push(VoxInstance::at(vox.models.len(), size, corner));
+            vox.models.push(VoxModel {
+                size:size,
+                voxels:voxels,
+            });
+        }
+        Ok(vox)
+    }
+}
\ No newline at end of file
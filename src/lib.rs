@@ -2,7 +2,9 @@
 extern crate mopa;
 
 pub mod concurrency;
+pub mod formats;
 pub mod math;
+pub mod meshing;
 pub mod metadata;
 pub mod objekt_impl;
 pub mod task_impl;
@@ -0,0 +1,28 @@
+//Seeded hashing for anything that must come out the same on every machine and every Rust release, such as world generation.
+//std's DefaultHasher makes no such promise, and Hash impls feed platform-sized integers, so neither is used here.
+//
+//The mixing function is the SplitMix64 finalizer (Steele, Lea & Flood, "Fast splittable pseudorandom number generators", 2014).
+//Its constants, and the way values are chained below, are part of the world format: changing them changes every generated world.
+
+pub const GOLDEN_GAMMA:u64 = 0x9e37_79b9_7f4a_7c15;
+
+pub fn mix64(value:u64) -> u64 {
+    let mut z = value;
+    z = (z^(z>>30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z^(z>>27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z^(z>>31)
+}
+
+//h_0 = mix64(seed + GOLDEN_GAMMA), h_i+1 = mix64(h_i ^ mix64(value_i + GOLDEN_GAMMA))
+pub fn hash_u64s(seed:u64, values:&[u64]) -> u64 {
+    let mut hash = mix64(seed.wrapping_add(GOLDEN_GAMMA));
+    for value in values {
+        hash = mix64(hash^mix64(value.wrapping_add(GOLDEN_GAMMA)));
+    }
+    hash
+}
+
+//maps a hash onto [0, 1)
+pub fn hash_to_unit(hash:u64) -> f64 {
+    (hash>>11) as f64/(1u64<<53) as f64
+}
\ No newline at end of file
@@ -1,3 +1,6 @@
 pub mod constants;
+pub mod hashing;
+pub mod noise;
 pub mod octree_math;
+pub mod shapes;
 pub mod vectors;
\ No newline at end of file
@@ -0,0 +1,520 @@
+use crate::math::{
+    hashing::{
+        hash_to_unit,
+        hash_u64s,
+    },
+    octree_math::{
+        voxel_min_corner,
+        voxel_size,
+    },
+    vectors::VoxelLocation,
+};
+
+//Coherent noise for world generation. Lattice gradients and feature points come straight from hashing::hash_u64s
+//instead of a shuffled permutation table, so every generator is fully described by its seed and settings.
+//Gradient and simplex noise return values in roughly [-1, 1].
+
+//Coarse voxels average up to this many samples per axis of their footprint. Up to LOD 2 that is every LOD 0 voxel, so a
+//voxel's value is exactly the average of its children's, and coarser voxels come close.
+pub const MAX_VOXEL_SAMPLES:u64 = 4;
+
+//averages sample over the centers of up to MAX_VOXEL_SAMPLES^3 equal parts of a voxel, passing each the LOD of the parts
+fn footprint_average(location:&VoxelLocation, frequency:f64, sample:impl Fn([f64;3], u64) -> f64) -> f64 {
+    let size = voxel_size(location.lod);
+    let samples = size.min(MAX_VOXEL_SAMPLES);
+    let part_lod = location.lod-samples.trailing_zeros() as u64;
+    let part_size = (size/samples) as f64;
+    let corner = voxel_min_corner(location);
+    let mut sum = 0.0;
+    for x in 0..samples {
+        for y in 0..samples {
+            for z in 0..samples {
+                let point = [
+                    (corner[0]+(x as f64+0.5)*part_size)*frequency,
+                    (corner[1]+(y as f64+0.5)*part_size)*frequency,
+                    (corner[2]+(z as f64+0.5)*part_size)*frequency,
+                ];
+                sum+=sample(point, part_lod);
+            }
+        }
+    }
+    sum/(samples*samples*samples) as f64
+}
+
+pub trait Noise2: Send + Sync {
+    fn sample2(&self, point:[f64;2]) -> f64;
+}
+
+pub trait Noise3: Send + Sync {
+    fn sample3(&self, point:[f64;3]) -> f64;
+
+    //averages the field over a voxel, see MAX_VOXEL_SAMPLES, frequency being noise units per LOD 0 voxel
+    fn sample_voxel(&self, location:&VoxelLocation, frequency:f64) -> f64 {
+        footprint_average(location, frequency, |point, _lod| self.sample3(point))
+    }
+}
+
+pub trait Noise4: Send + Sync {
+    fn sample4(&self, point:[f64;4]) -> f64;
+}
+
+fn fade(t:f64) -> f64 {
+    t*t*t*(t*(t*6.0-15.0)+10.0)
+}
+
+fn lerp(a:f64, b:f64, t:f64) -> f64 {
+    a+(b-a)*t
+}
+
+//the 12 edge midpoints of a cube, as used by improved Perlin noise and Gustavson's simplex noise
+const GRAD3:[[f64;3];12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+];
+
+fn dot2(hash:u64, offset:&[f64;2]) -> f64 {
+    let gradient = GRAD3[(hash%12) as usize];
+    gradient[0]*offset[0]+gradient[1]*offset[1]
+}
+
+fn dot3(hash:u64, offset:&[f64;3]) -> f64 {
+    let gradient = GRAD3[(hash%12) as usize];
+    gradient[0]*offset[0]+gradient[1]*offset[1]+gradient[2]*offset[2]
+}
+
+//one of the 32 vectors with a single zero component and the rest +-1
+fn dot4(hash:u64, offset:&[f64;4]) -> f64 {
+    let zero_axis = (hash%4) as usize;
+    let signs = hash/4%8;
+    let mut sum = 0.0;
+    let mut sign_bit = 0;
+    for (axis, value) in offset.iter().enumerate() {
+        if axis==zero_axis {
+            continue;
+        }
+        sum+=if signs>>sign_bit&1==0 {*value} else {-*value};
+        sign_bit+=1;
+    }
+    sum
+}
+
+fn lattice_coords<const N:usize>(cell:&[i64;N], corner:usize) -> [u64;N] {
+    let mut coords = [0u64;N];
+    for axis in 0..N {
+        coords[axis] = (cell[axis]+(corner>>axis&1) as i64) as u64;
+    }
+    coords
+}
+
+fn gradient_noise<const N:usize>(seed:u64, point:[f64;N], dot:fn(u64, &[f64;N]) -> f64) -> f64 {
+    let mut cell = [0i64;N];
+    let mut frac = [0f64;N];
+    for axis in 0..N {
+        let floor = point[axis].floor();
+        cell[axis] = floor as i64;
+        frac[axis] = point[axis]-floor;
+    }
+    let mut values = [0f64;16];
+    for (corner, value) in values.iter_mut().enumerate().take(1<<N) {
+        let mut offset = [0f64;N];
+        for axis in 0..N {
+            offset[axis] = frac[axis]-(corner>>axis&1) as f64;
+        }
+        *value = dot(hash_u64s(seed, &lattice_coords(&cell, corner)), &offset);
+    }
+    //collapse one axis at a time, x first, since neighbouring corners differ in their lowest bit
+    let mut count = 1<<N;
+    for t in frac.iter().map(|f| fade(*f)) {
+        count/=2;
+        for j in 0..count {
+            values[j] = lerp(values[2*j], values[2*j+1], t);
+        }
+    }
+    values[0]
+}
+
+//Perlin's improved gradient noise
+pub struct GradientNoise {
+    pub seed:u64,
+}
+
+impl GradientNoise {
+    pub fn new(seed:u64) -> Self {
+        Self {
+            seed:seed,
+        }
+    }
+}
+
+impl Noise2 for GradientNoise {
+    fn sample2(&self, point:[f64;2]) -> f64 {
+        gradient_noise(self.seed, point, dot2)
+    }
+}
+
+impl Noise3 for GradientNoise {
+    fn sample3(&self, point:[f64;3]) -> f64 {
+        gradient_noise(self.seed, point, dot3)
+    }
+}
+
+impl Noise4 for GradientNoise {
+    fn sample4(&self, point:[f64;4]) -> f64 {
+        gradient_noise(self.seed, point, dot4)*0.8
+    }
+}
+
+//Gustavson's simplex noise, with gradients hashed from the seed
+pub struct SimplexNoise {
+    pub seed:u64,
+}
+
+impl SimplexNoise {
+    pub fn new(seed:u64) -> Self {
+        Self {
+            seed:seed,
+        }
+    }
+}
+
+impl Noise2 for SimplexNoise {
+    fn sample2(&self, point:[f64;2]) -> f64 {
+        let f2 = 0.5*(3f64.sqrt()-1.0);
+        let g2 = (3.0-3f64.sqrt())/6.0;
+        let skew = (point[0]+point[1])*f2;
+        let i = (point[0]+skew).floor();
+        let j = (point[1]+skew).floor();
+        let unskew = (i+j)*g2;
+        let x0 = point[0]-(i-unskew);
+        let y0 = point[1]-(j-unskew);
+        let (i1, j1) = if x0>y0 {(1.0, 0.0)} else {(0.0, 1.0)};
+        let corners = [
+            (0.0, 0.0, x0, y0),
+            (i1, j1, x0-i1+g2, y0-j1+g2),
+            (1.0, 1.0, x0-1.0+2.0*g2, y0-1.0+2.0*g2),
+        ];
+        let mut sum = 0.0;
+        for (di, dj, x, y) in corners.iter() {
+            let t = 0.5-x*x-y*y;
+            if t>0.0 {
+                let hash = hash_u64s(self.seed, &[(i+di) as i64 as u64, (j+dj) as i64 as u64]);
+                sum+=t*t*t*t*dot2(hash, &[*x, *y]);
+            }
+        }
+        70.0*sum
+    }
+}
+
+impl Noise3 for SimplexNoise {
+    fn sample3(&self, point:[f64;3]) -> f64 {
+        let f3 = 1.0/3.0;
+        let g3 = 1.0/6.0;
+        let skew = (point[0]+point[1]+point[2])*f3;
+        let i = (point[0]+skew).floor();
+        let j = (point[1]+skew).floor();
+        let k = (point[2]+skew).floor();
+        let unskew = (i+j+k)*g3;
+        let x0 = point[0]-(i-unskew);
+        let y0 = point[1]-(j-unskew);
+        let z0 = point[2]-(k-unskew);
+        let (first, second) = if x0>=y0 {
+            if y0>=z0 {
+                ([1.0, 0.0, 0.0], [1.0, 1.0, 0.0])
+            } else if x0>=z0 {
+                ([1.0, 0.0, 0.0], [1.0, 0.0, 1.0])
+            } else {
+                ([0.0, 0.0, 1.0], [1.0, 0.0, 1.0])
+            }
+        } else if y0<z0 {
+            ([0.0, 0.0, 1.0], [0.0, 1.0, 1.0])
+        } else if x0<z0 {
+            ([0.0, 1.0, 0.0], [0.0, 1.0, 1.0])
+        } else {
+            ([0.0, 1.0, 0.0], [1.0, 1.0, 0.0])
+        };
+        let corners = [[0.0, 0.0, 0.0], first, second, [1.0, 1.0, 1.0]];
+        let mut sum = 0.0;
+        for (n, corner) in corners.iter().enumerate() {
+            let x = x0-corner[0]+n as f64*g3;
+            let y = y0-corner[1]+n as f64*g3;
+            let z = z0-corner[2]+n as f64*g3;
+            let t = 0.6-x*x-y*y-z*z;
+            if t>0.0 {
+                let hash = hash_u64s(self.seed, &[(i+corner[0]) as i64 as u64, (j+corner[1]) as i64 as u64, (k+corner[2]) as i64 as u64]);
+                sum+=t*t*t*t*dot3(hash, &[x, y, z]);
+            }
+        }
+        32.0*sum
+    }
+}
+
+impl Noise4 for SimplexNoise {
+    fn sample4(&self, point:[f64;4]) -> f64 {
+        let f4 = (5f64.sqrt()-1.0)/4.0;
+        let g4 = (5.0-5f64.sqrt())/20.0;
+        let skew = point.iter().sum::<f64>()*f4;
+        let cell = point.map(|value| (value+skew).floor());
+        let unskew = cell.iter().sum::<f64>()*g4;
+        let mut offset = [0f64;4];
+        for axis in 0..4 {
+            offset[axis] = point[axis]-(cell[axis]-unskew);
+        }
+        //the simplex is found by ranking the axes by their offset, larger offsets stepping first
+        let mut rank = [0usize;4];
+        for a in 0..4 {
+            for b in a+1..4 {
+                if offset[a]>offset[b] {
+                    rank[a]+=1;
+                } else {
+                    rank[b]+=1;
+                }
+            }
+        }
+        let mut sum = 0.0;
+        for n in 0..5 {
+            let mut corner = [0f64;4];
+            let mut relative = [0f64;4];
+            for axis in 0..4 {
+                corner[axis] = if rank[axis]+n>=4 {1.0} else {0.0};
+                relative[axis] = offset[axis]-corner[axis]+n as f64*g4;
+            }
+            let t = 0.6-relative.iter().map(|value| value*value).sum::<f64>();
+            if t>0.0 {
+                let coords = [0, 1, 2, 3].map(|axis| (cell[axis]+corner[axis]) as i64 as u64);
+                sum+=t*t*t*t*dot4(hash_u64s(self.seed, &coords), &relative);
+            }
+        }
+        27.0*sum
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorleyReturn {
+    F1,//distance to the nearest feature point
+    F2,//distance to the second nearest feature point
+    F2MinusF1,//zero along cell borders, which gives cracks and cell walls
+}
+
+//Worley's cellular noise, with one feature point per unit cell. Returns distances, so values are at least 0.
+pub struct WorleyNoise {
+    pub seed:u64,
+    pub output:WorleyReturn,
+}
+
+impl WorleyNoise {
+    pub fn new(seed:u64, output:WorleyReturn) -> Self {
+        Self {
+            seed:seed,
+            output:output,
+        }
+    }
+
+    fn select(&self, f1:f64, f2:f64) -> f64 {
+        match self.output {
+            WorleyReturn::F1 => f1,
+            WorleyReturn::F2 => f2,
+            WorleyReturn::F2MinusF1 => f2-f1,
+        }
+    }
+
+    fn worley<const N:usize>(&self, point:[f64;N]) -> f64 {
+        let mut cell = [0i64;N];
+        for axis in 0..N {
+            cell[axis] = point[axis].floor() as i64-1;
+        }
+        let mut f1 = f64::INFINITY;
+        let mut f2 = f64::INFINITY;
+        //every cell within one step holds a candidate
+        for neighbour in 0..3usize.pow(N as u32) {
+            let mut coords = [0u64;N];
+            let mut distance = 0.0;
+            let mut rest = neighbour;
+            for axis in 0..N {
+                coords[axis] = (cell[axis]+(rest%3) as i64) as u64;
+                rest/=3;
+            }
+            for axis in 0..N {
+                let mut values = [0u64;5];
+                values[..N].copy_from_slice(&coords);
+                values[N] = axis as u64;
+                let feature = coords[axis] as i64 as f64+hash_to_unit(hash_u64s(self.seed, &values[..N+1]));
+                distance+=(feature-point[axis])*(feature-point[axis]);
+            }
+            let distance = distance.sqrt();
+            if distance<f1 {
+                f2 = f1;
+                f1 = distance;
+            } else if distance<f2 {
+                f2 = distance;
+            }
+        }
+        self.select(f1, f2)
+    }
+}
+
+impl Noise2 for WorleyNoise {
+    fn sample2(&self, point:[f64;2]) -> f64 {
+        self.worley(point)
+    }
+}
+
+impl Noise3 for WorleyNoise {
+    fn sample3(&self, point:[f64;3]) -> f64 {
+        self.worley(point)
+    }
+}
+
+impl Noise4 for WorleyNoise {
+    fn sample4(&self, point:[f64;4]) -> f64 {
+        self.worley(point)
+    }
+}
+
+//settings shared by the fractal combinators
+#[derive(Clone, Copy, Debug)]
+pub struct Octaves {
+    pub count:u32,
+    pub lacunarity:f64,//frequency multiplier between octaves
+    pub gain:f64,//amplitude multiplier between octaves
+}
+
+impl Default for Octaves {
+    fn default() -> Self {
+        Self {
+            count:6,
+            lacunarity:2.0,
+            gain:0.5,
+        }
+    }
+}
+
+impl Octaves {
+    //octaves that fit within the voxel are skipped, since they would only add aliasing, but the first octave is always kept
+    fn count_for_lod(&self, lod:u64, frequency:f64) -> u32 {
+        let size = voxel_size(lod) as f64;
+        let mut octave_frequency = frequency;
+        let mut count = 0;
+        while count<self.count && (count==0 || octave_frequency*size<=0.5) {
+            count+=1;
+            octave_frequency*=self.lacunarity;
+        }
+        count
+    }
+
+    fn accumulate(&self, count:u32, shape:impl Fn(f64) -> f64, sample:impl Fn(u32, f64) -> f64) -> f64 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for octave in 0..count {
+            sum+=amplitude*shape(sample(octave, frequency));
+            total_amplitude+=amplitude;
+            amplitude*=self.gain;
+            frequency*=self.lacunarity;
+        }
+        if total_amplitude==0.0 {
+            0.0
+        } else {
+            sum/total_amplitude
+        }
+    }
+}
+
+//each octave is shifted by a different amount so their lattices do not line up at the origin
+fn octave_shift(octave:u32, axis:usize) -> f64 {
+    octave as f64*[31.416, 27.183, 14.142, 17.321][axis]
+}
+
+fn octave_point<const N:usize>(point:[f64;N], octave:u32, frequency:f64) -> [f64;N] {
+    let mut shifted = [0f64;N];
+    for axis in 0..N {
+        shifted[axis] = point[axis]*frequency+octave_shift(octave, axis);
+    }
+    shifted
+}
+
+fn ridge(value:f64) -> f64 {
+    let ridge = 1.0-value.abs();
+    ridge*ridge
+}
+
+//fractional Brownian motion, normalized so it keeps the range of its source
+pub struct Fbm<N> {
+    pub source:N,
+    pub octaves:Octaves,
+}
+
+impl<N> Fbm<N> {
+    pub fn new(source:N, octaves:Octaves) -> Self {
+        Self {
+            source:source,
+            octaves:octaves,
+        }
+    }
+}
+
+impl<N: Noise2> Noise2 for Fbm<N> {
+    fn sample2(&self, point:[f64;2]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, |value| value, |octave, frequency| self.source.sample2(octave_point(point, octave, frequency)))
+    }
+}
+
+impl<N: Noise3> Noise3 for Fbm<N> {
+    fn sample3(&self, point:[f64;3]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, |value| value, |octave, frequency| self.source.sample3(octave_point(point, octave, frequency)))
+    }
+
+    fn sample_voxel(&self, location:&VoxelLocation, frequency:f64) -> f64 {
+        footprint_average(location, frequency, |point, lod| {
+            let count = self.octaves.count_for_lod(lod, frequency);
+            self.octaves.accumulate(count, |value| value, |octave, octave_frequency| self.source.sample3(octave_point(point, octave, octave_frequency)))
+        })
+    }
+}
+
+impl<N: Noise4> Noise4 for Fbm<N> {
+    fn sample4(&self, point:[f64;4]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, |value| value, |octave, frequency| self.source.sample4(octave_point(point, octave, frequency)))
+    }
+}
+
+//ridged multifractal: sharp crests where the source crosses zero, in [0, 1] for sources in [-1, 1]
+pub struct Ridged<N> {
+    pub source:N,
+    pub octaves:Octaves,
+}
+
+impl<N> Ridged<N> {
+    pub fn new(source:N, octaves:Octaves) -> Self {
+        Self {
+            source:source,
+            octaves:octaves,
+        }
+    }
+}
+
+impl<N: Noise2> Noise2 for Ridged<N> {
+    fn sample2(&self, point:[f64;2]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, ridge, |octave, frequency| self.source.sample2(octave_point(point, octave, frequency)))
+    }
+}
+
+impl<N: Noise3> Noise3 for Ridged<N> {
+    fn sample3(&self, point:[f64;3]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, ridge, |octave, frequency| self.source.sample3(octave_point(point, octave, frequency)))
+    }
+
+    fn sample_voxel(&self, location:&VoxelLocation, frequency:f64) -> f64 {
+        footprint_average(location, frequency, |point, lod| {
+            let count = self.octaves.count_for_lod(lod, frequency);
+            self.octaves.accumulate(count, ridge, |octave, octave_frequency| self.source.sample3(octave_point(point, octave, octave_frequency)))
+        })
+    }
+}
+
+impl<N: Noise4> Noise4 for Ridged<N> {
+    fn sample4(&self, point:[f64;4]) -> f64 {
+        self.octaves.accumulate(self.octaves.count, ridge, |octave, frequency| self.source.sample4(octave_point(point, octave, frequency)))
+    }
+}
\ No newline at end of file
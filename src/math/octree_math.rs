@@ -0,0 +1,345 @@
+use crate::math::{
+    shapes::{
+        Aabb,
+        Sdf,
+    },
+    vectors::{
+        Vector3U64,
+        VoxelLocation,
+    },
+};
+
+pub type LevelKey = u128;
+
+pub const MAX_LOD:u64 = 255;
+pub const MORTON_AXIS_BITS:u32 = 40;
+pub const MAX_LEVEL_COORD:u64 = (1<<MORTON_AXIS_BITS)-1;
+
+//LOD 0 is the finest resolution. Each LOD step doubles the edge length of a voxel, so every voxel has eight children one LOD below it.
+//Voxel coordinates are always global and counted in voxels of the location's own LOD.
+
+//lod has to be below 64, which every LOD of an octree is, see max_level_depth
+pub fn voxel_size(lod:u64) -> u64 {
+    1<<lod
+}
+
+//world positions are measured in LOD 0 voxels, with the origin on the lowest corner of voxel (0, 0, 0)
+pub fn voxel_min_corner(location:&VoxelLocation) -> [f64;3] {
+    let size = voxel_size(location.lod) as f64;
+    [location.vec.x as f64*size, location.vec.y as f64*size, location.vec.z as f64*size]
+}
+
+pub fn voxel_center(location:&VoxelLocation) -> [f64;3] {
+    let size = voxel_size(location.lod) as f64;
+    [(location.vec.x as f64+0.5)*size, (location.vec.y as f64+0.5)*size, (location.vec.z as f64+0.5)*size]
+}
+
+pub fn parent(location:&VoxelLocation) -> VoxelLocation {
+    VoxelLocation {
+        lod:location.lod+1,
+        vec:Vector3U64 {
+            x:location.vec.x>>1,
+            y:location.vec.y>>1,
+            z:location.vec.z>>1,
+        },
+    }
+}
+
+//which of its parent's eight children this voxel is: bit 0 is x, bit 1 is y, bit 2 is z
+pub fn octant(location:&VoxelLocation) -> u8 {
+    ((location.vec.x&1) | (location.vec.y&1)<<1 | (location.vec.z&1)<<2) as u8
+}
+
+pub fn child(location:&VoxelLocation, octant:u8) -> Option<VoxelLocation> {
+    if location.lod==0 || octant>=8 {
+        return None;
+    }
+    let octant = octant as u64;
+    Some(VoxelLocation {
+        lod:location.lod-1,
+        vec:Vector3U64 {
+            x:(location.vec.x<<1) | (octant&1),
+            y:(location.vec.y<<1) | (octant>>1&1),
+            z:(location.vec.z<<1) | (octant>>2&1),
+        },
+    })
+}
+
+pub fn children(location:&VoxelLocation) -> Option<Vec<VoxelLocation>> {
+    if location.lod==0 {
+        return None;
+    }
+    Some((0..8).map(|octant| child(location, octant).unwrap()).collect())
+}
+
+pub fn siblings(location:&VoxelLocation) -> Vec<VoxelLocation> {
+    let own_octant = octant(location);
+    let parent = parent(location);
+    (0..8).filter(|octant| *octant!=own_octant).map(|octant| child(&parent, octant).unwrap()).collect()
+}
+
+//moving to a coarser LOD gives the voxel containing this one, moving to a finer LOD gives the child on the lowest corner
+pub fn to_lod(location:&VoxelLocation, lod:u64) -> VoxelLocation {
+    let vec = if lod>=location.lod {
+        let shift = lod-location.lod;
+        if shift>=64 {
+            Vector3U64 {
+                x:0,
+                y:0,
+                z:0,
+            }
+        } else {
+            Vector3U64 {
+                x:location.vec.x>>shift,
+                y:location.vec.y>>shift,
+                z:location.vec.z>>shift,
+            }
+        }
+    } else {
+        let shift = location.lod-lod;
+        Vector3U64 {
+            x:location.vec.x<<shift,
+            y:location.vec.y<<shift,
+            z:location.vec.z<<shift,
+        }
+    };
+    VoxelLocation {
+        lod:lod,
+        vec:vec,
+    }
+}
+
+//coordinates of the level containing this voxel, counted in levels of the same LOD
+pub fn level_coords(location:&VoxelLocation, level_length:u64) -> Vector3U64 {
+    Vector3U64 {
+        x:location.vec.x/level_length,
+        y:location.vec.y/level_length,
+        z:location.vec.z/level_length,
+    }
+}
+
+//the voxel on the lowest position on each axis that is still within the level containing this voxel
+pub fn level_origin(location:&VoxelLocation, level_length:u64) -> VoxelLocation {
+    let coords = level_coords(location, level_length);
+    VoxelLocation {
+        lod:location.lod,
+        vec:Vector3U64 {
+            x:coords.x*level_length,
+            y:coords.y*level_length,
+            z:coords.z*level_length,
+        },
+    }
+}
+
+//Particles within a level are stored x-major, then y, then z
+pub fn index_in_level(location:&VoxelLocation, level_length:u64) -> usize {
+    let x = location.vec.x%level_length;
+    let y = location.vec.y%level_length;
+    let z = location.vec.z%level_length;
+    ((x*level_length+y)*level_length+z) as usize
+}
+
+pub fn location_in_level(origin:&VoxelLocation, index:usize, level_length:u64) -> VoxelLocation {
+    let index = index as u64;
+    VoxelLocation {
+        lod:origin.lod,
+        vec:Vector3U64 {
+            x:origin.vec.x+index/(level_length*level_length),
+            y:origin.vec.y+index/level_length%level_length,
+            z:origin.vec.z+index%level_length,
+        },
+    }
+}
+
+fn spread_bits(value:u64) -> u128 {
+    let mut spread = 0u128;
+    for bit in 0..MORTON_AXIS_BITS {
+        spread |= ((value>>bit&1) as u128)<<(3*bit);
+    }
+    spread
+}
+
+fn compact_bits(spread:u128) -> u64 {
+    let mut value = 0u64;
+    for bit in 0..MORTON_AXIS_BITS {
+        value |= ((spread>>(3*bit)&1) as u64)<<bit;
+    }
+    value
+}
+
+//interleaves the low 40 bits of each axis into a Z-order curve, x in the lowest bit
+pub fn morton_encode(coords:&Vector3U64) -> Option<u128> {
+    if coords.x>MAX_LEVEL_COORD || coords.y>MAX_LEVEL_COORD || coords.z>MAX_LEVEL_COORD {
+        return None;
+    }
+    Some(spread_bits(coords.x) | spread_bits(coords.y)<<1 | spread_bits(coords.z)<<2)
+}
+
+pub fn morton_decode(code:u128) -> Vector3U64 {
+    Vector3U64 {
+        x:compact_bits(code),
+        y:compact_bits(code>>1),
+        z:compact_bits(code>>2),
+    }
+}
+
+//the top 8 bits hold MAX_LOD-lod so coarser LODs always sort before finer ones, the low 120 bits hold the Morton code of the level coordinates
+pub fn level_key(location:&VoxelLocation, level_length:u64) -> Option<LevelKey> {
+    if location.lod>MAX_LOD {
+        return None;
+    }
+    let morton = morton_encode(&level_coords(location, level_length))?;
+    Some(((MAX_LOD-location.lod) as u128)<<(3*MORTON_AXIS_BITS) | morton)
+}
+
+//gives back the origin of the level a key was made from, or None if that origin does not fit in 64 bits for this level_length
+pub fn level_key_origin(key:LevelKey, level_length:u64) -> Option<VoxelLocation> {
+    let lod = MAX_LOD-(key>>(3*MORTON_AXIS_BITS)) as u64;
+    let coords = morton_decode(key&((1<<(3*MORTON_AXIS_BITS))-1));
+    Some(VoxelLocation {
+        lod:lod,
+        vec:Vector3U64 {
+            x:coords.x.checked_mul(level_length)?,
+            y:coords.y.checked_mul(level_length)?,
+            z:coords.z.checked_mul(level_length)?,
+        },
+    })
+}
+
+//the last voxel coordinate on an axis whose level still has a level key
+pub fn max_voxel_coord(level_length:u64) -> u64 {
+    (MAX_LEVEL_COORD+1).saturating_mul(level_length)-1
+}
+
+//The most LODs an octree can have such that every voxel with a level key, at any of its LODs, still has its LOD 0
+//coordinates within a u64. That keeps voxel_size and moving to finer LODs with to_lod from overflowing.
+pub fn max_level_depth(level_length:u64) -> u64 {
+    let coord_bits = 64-max_voxel_coord(level_length).leading_zeros() as u64;
+    (64-coord_bits+1).min(MAX_LOD+1)
+}
+
+//A box of voxels at one LOD, first and last included on each axis. Visiting it level by level, as
+//for origin in range.level_origins(level_length) { for location in range.within_level(&origin, level_length)?.voxels() {...} }
+//locks each level only once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelRange {
+    pub lod:u64,
+    pub first:Vector3U64,
+    pub last:Vector3U64,
+}
+
+impl VoxelRange {
+    //origins of the levels the range overlaps, x-major like the voxels within a level
+    pub fn level_origins(&self, level_length:u64) -> impl Iterator<Item = VoxelLocation> {
+        let lod = self.lod;
+        let first = level_coords(&self.location(&self.first), level_length);
+        let last = level_coords(&self.location(&self.last), level_length);
+        grid_points([first.x, first.y, first.z], [last.x, last.y, last.z]).map(move |[x, y, z]| VoxelLocation {
+            lod:lod,
+            vec:Vector3U64 {
+                x:x*level_length,
+                y:y*level_length,
+                z:z*level_length,
+            },
+        })
+    }
+
+    //how many levels level_origins goes through
+    pub fn level_count(&self, level_length:u64) -> u128 {
+        let first = level_coords(&self.location(&self.first), level_length);
+        let last = level_coords(&self.location(&self.last), level_length);
+        let span = |first:u64, last:u64| (last-first) as u128+1;
+        span(first.x, last.x)*span(first.y, last.y)*span(first.z, last.z)
+    }
+
+    //the part of the range inside the level at origin, or None if they do not overlap
+    pub fn within_level(&self, origin:&VoxelLocation, level_length:u64) -> Option<VoxelRange> {
+        if origin.lod!=self.lod {
+            return None;
+        }
+        let end = |first:u64, last:u64, origin:u64| (first.max(origin), last.min(origin.saturating_add(level_length-1)));
+        let (x_first, x_last) = end(self.first.x, self.last.x, origin.vec.x);
+        let (y_first, y_last) = end(self.first.y, self.last.y, origin.vec.y);
+        let (z_first, z_last) = end(self.first.z, self.last.z, origin.vec.z);
+        if x_first>x_last || y_first>y_last || z_first>z_last {
+            return None;
+        }
+        Some(VoxelRange {
+            lod:self.lod,
+            first:Vector3U64 {
+                x:x_first,
+                y:y_first,
+                z:z_first,
+            },
+            last:Vector3U64 {
+                x:x_last,
+                y:y_last,
+                z:z_last,
+            },
+        })
+    }
+
+    //every voxel of the range, x-major
+    pub fn voxels(&self) -> impl Iterator<Item = VoxelLocation> {
+        let lod = self.lod;
+        grid_points([self.first.x, self.first.y, self.first.z], [self.last.x, self.last.y, self.last.z]).map(move |[x, y, z]| VoxelLocation {
+            lod:lod,
+            vec:Vector3U64 {
+                x:x,
+                y:y,
+                z:z,
+            },
+        })
+    }
+
+    fn location(&self, vec:&Vector3U64) -> VoxelLocation {
+        VoxelLocation {
+            lod:self.lod,
+            vec:vec.clone(),
+        }
+    }
+}
+
+//every point from first to last on each axis, x-major
+fn grid_points(first:[u64;3], last:[u64;3]) -> impl Iterator<Item = [u64;3]> {
+    (first[0]..=last[0]).flat_map(move |x| (first[1]..=last[1]).flat_map(move |y| (first[2]..=last[2]).map(move |z| [x, y, z])))
+}
+
+//The voxels at this LOD with a center inside the bounds, or None if there are none. The range is clipped to the
+//addressable space, see max_voxel_coord, but that still spans 2^40 levels per axis: check bounds with Aabb::is_finite
+//before walking the levels of a shape that may be unbounded.
+pub fn voxel_range(bounds:&Aabb, lod:u64, level_length:u64) -> Option<VoxelRange> {
+    let size = voxel_size(lod) as f64;
+    let max_coord = max_voxel_coord(level_length) as f64;
+    //voxel i has its center at (i+0.5)*size
+    let first_center = |axis:usize| (bounds.min[axis]/size-0.5).ceil().max(0.0);
+    let last_center = |axis:usize| (bounds.max[axis]/size-0.5).floor().min(max_coord);
+    if (0..3).any(|axis| last_center(axis)<first_center(axis) || first_center(axis)>max_coord) {
+        return None;
+    }
+    //float to int casts saturate, and max_coord may round up past u64::MAX-1, so clamp again once converted
+    let max_coord = max_voxel_coord(level_length);
+    let first = |axis:usize| first_center(axis) as u64;
+    let last = |axis:usize| (last_center(axis) as u64).min(max_coord);
+    Some(VoxelRange {
+        lod:lod,
+        first:Vector3U64 {
+            x:first(0),
+            y:first(1),
+            z:first(2),
+        },
+        last:Vector3U64 {
+            x:last(0),
+            y:last(1),
+            z:last(2),
+        },
+    })
+}
+
+//false only if no voxel center of the level can lie inside the shape, so the level can be skipped without looking at it
+pub fn level_may_overlap(shape:&dyn Sdf, origin:&VoxelLocation, level_length:u64) -> bool {
+    let first_center = voxel_center(origin);
+    let extent = ((level_length-1)*voxel_size(origin.lod)) as f64;
+    let centers = Aabb::new(first_center, [first_center[0]+extent, first_center[1]+extent, first_center[2]+extent]);
+    shape.distance(centers.center())<=centers.half_diagonal()
+}
\ No newline at end of file
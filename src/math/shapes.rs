@@ -0,0 +1,265 @@
+//Shapes described by signed distance functions, in world units (LOD 0 voxels, see octree_math::voxel_min_corner).
+//Distances are negative inside a shape. They only need to be exact near the surface, but must never overestimate,
+//so that a point farther than its distance from the surface can be skipped safely.
+
+pub trait Sdf: Send + Sync {
+    fn distance(&self, point:[f64;3]) -> f64;
+    fn bounds(&self) -> Aabb;//everything with a negative distance lies within these bounds
+}
+
+fn length(v:[f64;3]) -> f64 {
+    (v[0]*v[0]+v[1]*v[1]+v[2]*v[2]).sqrt()
+}
+
+fn sub(a:[f64;3], b:[f64;3]) -> [f64;3] {
+    [a[0]-b[0], a[1]-b[1], a[2]-b[2]]
+}
+
+fn dot(a:[f64;3], b:[f64;3]) -> f64 {
+    a[0]*b[0]+a[1]*b[1]+a[2]*b[2]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min:[f64;3],
+    pub max:[f64;3],
+}
+
+impl Aabb {
+    pub fn new(min:[f64;3], max:[f64;3]) -> Self {
+        Self {
+            min:min,
+            max:max,
+        }
+    }
+
+    pub fn center(&self) -> [f64;3] {
+        [(self.min[0]+self.max[0])*0.5, (self.min[1]+self.max[1])*0.5, (self.min[2]+self.max[2])*0.5]
+    }
+
+    pub fn contains(&self, point:[f64;3]) -> bool {
+        (0..3).all(|axis| point[axis]>=self.min[axis] && point[axis]<=self.max[axis])
+    }
+
+    pub fn intersects(&self, other:&Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis]<=other.max[axis] && self.max[axis]>=other.min[axis])
+    }
+
+    pub fn intersection(&self, other:&Aabb) -> Option<Aabb> {
+        if !self.intersects(other) {
+            return None;
+        }
+        Some(Aabb {
+            min:[self.min[0].max(other.min[0]), self.min[1].max(other.min[1]), self.min[2].max(other.min[2])],
+            max:[self.max[0].min(other.max[0]), self.max[1].min(other.max[1]), self.max[2].min(other.max[2])],
+        })
+    }
+
+    //radius of the smallest sphere around the center holding the whole box
+    pub fn half_diagonal(&self) -> f64 {
+        length(sub(self.max, self.min))*0.5
+    }
+
+    //false for the bounds of unbounded shapes, and for boxes with a NaN corner
+    pub fn is_finite(&self) -> bool {
+        self.min.iter().chain(self.max.iter()).all(|value| value.is_finite())
+    }
+}
+
+impl Sdf for Aabb {
+    fn distance(&self, point:[f64;3]) -> f64 {
+        let center = self.center();
+        let mut outside = [0f64;3];
+        let mut inside = f64::NEG_INFINITY;
+        for axis in 0..3 {
+            let q = (point[axis]-center[axis]).abs()-(self.max[axis]-self.min[axis])*0.5;
+            outside[axis] = q.max(0.0);
+            inside = inside.max(q);
+        }
+        length(outside)+inside.min(0.0)
+    }
+
+    fn bounds(&self) -> Aabb {
+        *self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center:[f64;3],
+    pub radius:f64,
+}
+
+impl Sphere {
+    pub fn new(center:[f64;3], radius:f64) -> Self {
+        Self {
+            center:center,
+            radius:radius,
+        }
+    }
+}
+
+impl Sdf for Sphere {
+    fn distance(&self, point:[f64;3]) -> f64 {
+        length(sub(point, self.center))-self.radius
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb {
+            min:[self.center[0]-self.radius, self.center[1]-self.radius, self.center[2]-self.radius],
+            max:[self.center[0]+self.radius, self.center[1]+self.radius, self.center[2]+self.radius],
+        }
+    }
+}
+
+//a capped cylinder running from one end cap to the other
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cylinder {
+    pub start:[f64;3],
+    pub end:[f64;3],
+    pub radius:f64,
+}
+
+impl Cylinder {
+    pub fn new(start:[f64;3], end:[f64;3], radius:f64) -> Self {
+        Self {
+            start:start,
+            end:end,
+            radius:radius,
+        }
+    }
+}
+
+impl Sdf for Cylinder {
+    fn distance(&self, point:[f64;3]) -> f64 {
+        let axis = sub(self.end, self.start);
+        let axis_length = length(axis);
+        if axis_length==0.0 {
+            return f64::INFINITY;
+        }
+        let direction = [axis[0]/axis_length, axis[1]/axis_length, axis[2]/axis_length];
+        let relative = sub(point, self.start);
+        let along = dot(relative, direction);
+        let across = length(sub(relative, [direction[0]*along, direction[1]*along, direction[2]*along]));
+        let radial = across-self.radius;
+        let axial = (along-axis_length*0.5).abs()-axis_length*0.5;
+        let outside = (radial.max(0.0).powi(2)+axial.max(0.0).powi(2)).sqrt();
+        outside+radial.max(axial).min(0.0)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let mut min = [0f64;3];
+        let mut max = [0f64;3];
+        for axis in 0..3 {
+            min[axis] = self.start[axis].min(self.end[axis])-self.radius;
+            max[axis] = self.start[axis].max(self.end[axis])+self.radius;
+        }
+        Aabb {
+            min:min,
+            max:max,
+        }
+    }
+}
+
+//any signed distance function, given bounds that hold everything inside it
+pub struct SdfFn<F: Fn([f64;3]) -> f64 + Send + Sync> {
+    pub function:F,
+    pub bounds:Aabb,
+}
+
+impl<F: Fn([f64;3]) -> f64 + Send + Sync> SdfFn<F> {
+    pub fn new(function:F, bounds:Aabb) -> Self {
+        Self {
+            function:function,
+            bounds:bounds,
+        }
+    }
+}
+
+impl<F: Fn([f64;3]) -> f64 + Send + Sync> Sdf for SdfFn<F> {
+    fn distance(&self, point:[f64;3]) -> f64 {
+        (self.function)(point)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+fn cross(a:[f64;3], b:[f64;3]) -> [f64;3] {
+    [a[1]*b[2]-a[2]*b[1], a[2]*b[0]-a[0]*b[2], a[0]*b[1]-a[1]*b[0]]
+}
+
+//points p with dot(normal, p) <= offset lie on the inner side
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal:[f64;3],//unit length, pointing out of the volume
+    pub offset:f64,
+}
+
+impl Plane {
+    //through three points, with the normal facing away from a point known to be inside
+    pub fn from_points(a:[f64;3], b:[f64;3], c:[f64;3], inside:[f64;3]) -> Self {
+        let mut normal = cross(sub(b, a), sub(c, a));
+        let normal_length = length(normal);
+        normal = [normal[0]/normal_length, normal[1]/normal_length, normal[2]/normal_length];
+        if dot(normal, sub(inside, a))>0.0 {
+            normal = [-normal[0], -normal[1], -normal[2]];
+        }
+        Self {
+            normal:normal,
+            offset:dot(normal, a),
+        }
+    }
+
+    pub fn signed_distance(&self, point:[f64;3]) -> f64 {
+        dot(self.normal, point)-self.offset
+    }
+}
+
+//a convex volume bounded by planes, usually a camera's view frustum
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes:Vec<Plane>,
+    pub bounds:Aabb,
+}
+
+impl Frustum {
+    //corners of the near and far rectangles, each going around the rectangle in the same order
+    pub fn from_corners(near:[[f64;3];4], far:[[f64;3];4]) -> Self {
+        let corners:Vec<[f64;3]> = near.iter().chain(far.iter()).cloned().collect();
+        let mut inside = [0f64;3];
+        let mut min = [f64::INFINITY;3];
+        let mut max = [f64::NEG_INFINITY;3];
+        for corner in &corners {
+            for axis in 0..3 {
+                inside[axis]+=corner[axis]/8.0;
+                min[axis] = min[axis].min(corner[axis]);
+                max[axis] = max[axis].max(corner[axis]);
+            }
+        }
+        let mut planes = vec![
+            Plane::from_points(near[0], near[1], near[2], inside),
+            Plane::from_points(far[0], far[1], far[2], inside),
+        ];
+        for side in 0..4 {
+            let next = (side+1)%4;
+            planes.push(Plane::from_points(near[side], near[next], far[side], inside));
+        }
+        Self {
+            planes:planes,
+            bounds:Aabb::new(min, max),
+        }
+    }
+}
+
+impl Sdf for Frustum {
+    //the largest plane distance, which is exact inside and never more than the true distance outside
+    fn distance(&self, point:[f64;3]) -> f64 {
+        self.planes.iter().map(|plane| plane.signed_distance(point)).fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
\ No newline at end of file
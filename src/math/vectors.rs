@@ -1,25 +1,71 @@
-#[derive(Clone, Debug)]
+use crate::utils::binary::{
+    ByteReader,
+    write_u16,
+    write_u64,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct VoxelLocation {
     pub lod:u64,
     pub vec:Vector3U64,
 }
 
 impl VoxelLocation {
-    pub fn linearize(&self, size_x:u64, size_y:u64, size_z:u64, level_depth:u64) -> u64 {
-        ((self.vec.x*size_y*size_z+self.vec.y*size_z+self.vec.z)*(size_x*size_y*size_z)+2).pow((level_depth-self.lod) as u32)
+    pub fn write(&self, out:&mut Vec<u8>) {
+        write_u64(out, self.lod);
+        self.vec.write(out);
+    }
+
+    pub fn read(reader:&mut ByteReader) -> Result<Self, String> {
+        Ok(Self {
+            lod:reader.read_u64()?,
+            vec:Vector3U64::read(reader)?,
+        })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Vector3U64 {
     pub x:u64,
     pub y:u64,
     pub z:u64,
 }
 
+impl Vector3U64 {
+    pub fn write(&self, out:&mut Vec<u8>) {
+        write_u64(out, self.x);
+        write_u64(out, self.y);
+        write_u64(out, self.z);
+    }
+
+    pub fn read(reader:&mut ByteReader) -> Result<Self, String> {
+        Ok(Self {
+            x:reader.read_u64()?,
+            y:reader.read_u64()?,
+            z:reader.read_u64()?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Vector3U16 {
     pub x:u16,
     pub y:u16,
     pub z:u16,
+}
+
+impl Vector3U16 {
+    pub fn write(&self, out:&mut Vec<u8>) {
+        write_u16(out, self.x);
+        write_u16(out, self.y);
+        write_u16(out, self.z);
+    }
+
+    pub fn read(reader:&mut ByteReader) -> Result<Self, String> {
+        Ok(Self {
+            x:reader.read_u16()?,
+            y:reader.read_u16()?,
+            z:reader.read_u16()?,
+        })
+    }
 }
\ No newline at end of file
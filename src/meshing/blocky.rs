@@ -0,0 +1,59 @@
+use crate::meshing::{
+    mesh::Mesh,
+    padded_grid::PaddedGrid,
+};
+
+//the four corners of the unit square spanned by the two axes after the face's own, counter-clockwise seen from the positive side
+pub const QUAD_CORNERS:[[f32;2];4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+//Adds a rectangle lying in the plane where axis equals plane, spanning [min, max] on the two other axes, which are taken in
+//the order axis+1, axis+2. With positive set the face points along the axis, otherwise against it.
+pub fn push_face(mesh:&mut Mesh, axis:usize, positive:bool, plane:f32, min:[f32;2], max:[f32;2], material:u16) {
+    let mut normal = [0.0;3];
+    normal[axis] = if positive {1.0} else {-1.0};
+    let mut corners = [0;4];
+    for (corner, [u, v]) in corners.iter_mut().zip(QUAD_CORNERS.iter()) {
+        let mut position = [0.0;3];
+        position[axis] = plane;
+        position[(axis+1)%3] = min[0]+(max[0]-min[0])*u;
+        position[(axis+2)%3] = min[1]+(max[1]-min[1])*v;
+        *corner = mesh.push_vertex(position, normal, material);
+    }
+    if !positive {
+        corners.reverse();
+    }
+    mesh.push_quad(corners);
+}
+
+//one face per side of a solid voxel that borders an empty one. Padding decides the faces on the level's border but gets no faces of its own.
+pub fn blocky_mesh(grid:&PaddedGrid) -> Mesh {
+    let mut mesh = Mesh::new(grid.world_origin());
+    let size = grid.voxel_size() as f32;
+    let length = grid.level_length as usize;
+    for x in 1..=length {
+        for y in 1..=length {
+            for z in 1..=length {
+                let material = match grid.material(x, y, z) {
+                    Some(material) => material,
+                    None => continue,
+                };
+                let sample = [x, y, z];
+                for axis in 0..3 {
+                    for positive in [false, true] {
+                        let mut neighbour = sample;
+                        neighbour[axis] = if positive {neighbour[axis]+1} else {neighbour[axis]-1};
+                        if grid.material(neighbour[0], neighbour[1], neighbour[2]).is_some() {
+                            continue;
+                        }
+                        //padded sample i covers [i-1, i] voxels from the level's lowest corner
+                        let low = |axis:usize| (sample[axis]-1) as f32*size;
+                        let plane = low(axis)+if positive {size} else {0.0};
+                        let (u, v) = ((axis+1)%3, (axis+2)%3);
+                        push_face(&mut mesh, axis, positive, plane, [low(u), low(v)], [low(u)+size, low(v)+size], material);
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use crate::meshing::{
+    blocky::push_face,
+    mesh::Mesh,
+    padded_grid::PaddedGrid,
+};
+
+//The same faces as blocky_mesh, but merged: each layer of voxels is swept once per face direction, and every run of visible
+//faces sharing a material is grown into the largest rectangle it can, first along one axis and then the other.
+pub fn greedy_mesh(grid:&PaddedGrid) -> Mesh {
+    let mut mesh = Mesh::new(grid.world_origin());
+    let size = grid.voxel_size() as f32;
+    let length = grid.level_length as usize;
+    //the material of the visible face at each voxel of the current layer, indexed u-major over the two axes after the face's own
+    let mut mask:Vec<Option<u16>> = vec![None; length*length];
+    for axis in 0..3 {
+        let (u_axis, v_axis) = ((axis+1)%3, (axis+2)%3);
+        for positive in [false, true] {
+            for layer in 1..=length {
+                for u in 0..length {
+                    for v in 0..length {
+                        let mut sample = [0;3];
+                        sample[axis] = layer;
+                        sample[u_axis] = u+1;
+                        sample[v_axis] = v+1;
+                        let mut neighbour = sample;
+                        neighbour[axis] = if positive {layer+1} else {layer-1};
+                        mask[u*length+v] = match grid.material(sample[0], sample[1], sample[2]) {
+                            Some(material) if grid.material(neighbour[0], neighbour[1], neighbour[2]).is_none() => Some(material),
+                            _ => None,
+                        };
+                    }
+                }
+
+                let plane = (layer-1) as f32*size+if positive {size} else {0.0};
+                for u in 0..length {
+                    for v in 0..length {
+                        let material = match mask[u*length+v] {
+                            Some(material) => material,
+                            None => continue,
+                        };
+                        let mut height = 1;
+                        while v+height<length && mask[u*length+v+height]==Some(material) {
+                            height+=1;
+                        }
+                        let mut width = 1;
+                        while u+width<length && (v..v+height).all(|row| mask[(u+width)*length+row]==Some(material)) {
+                            width+=1;
+                        }
+                        for covered_u in u..u+width {
+                            for covered_v in v..v+height {
+                                mask[covered_u*length+covered_v] = None;
+                            }
+                        }
+                        push_face(
+                            &mut mesh,
+                            axis,
+                            positive,
+                            plane,
+                            [u as f32*size, v as f32*size],
+                            [(u+width) as f32*size, (v+height) as f32*size],
+                            material,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
\ No newline at end of file
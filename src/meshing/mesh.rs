@@ -0,0 +1,95 @@
+use crate::{
+    math::vectors::VoxelLocation,
+    meshing::{
+        blocky::blocky_mesh,
+        greedy::greedy_mesh,
+        padded_grid::PaddedGrid,
+        surface_nets::surface_nets_mesh,
+    },
+    objekt_impl::storage::hybrid_octree::HybridOctree,
+};
+
+//Indexed triangles ready to be copied into vertex and index buffers. Positions are relative to origin, so they stay precise
+//in f32 however far from the world origin the level is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub origin:[f64;3],//world position of the meshed level's lowest corner
+    pub positions:Vec<[f32;3]>,
+    pub normals:Vec<[f32;3]>,
+    pub materials:Vec<u16>,//one per vertex
+    pub indices:Vec<u32>,//three per triangle, counter-clockwise seen from outside of the solid
+}
+
+impl Mesh {
+    pub fn new(origin:[f64;3]) -> Self {
+        Self {
+            origin:origin,
+            ..Default::default()
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()/3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn push_vertex(&mut self, position:[f32;3], normal:[f32;3], material:u16) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.materials.push(material);
+        (self.positions.len()-1) as u32
+    }
+
+    //two triangles over four vertices given counter-clockwise
+    pub fn push_quad(&mut self, corners:[u32;4]) {
+        self.indices.extend_from_slice(&[corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]]);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshMode {
+    Blocky,//a cube face wherever a solid voxel borders an empty one, with flat normals
+    Greedy,//the faces of Blocky, merged into as few rectangles as possible per material
+    SurfaceNets,//one vertex per cell of eight voxel centers that the surface passes through, giving a smooth surface
+}
+
+impl MeshMode {
+    pub fn mesh(&self, grid:&PaddedGrid) -> Mesh {
+        match self {
+            MeshMode::Blocky => blocky_mesh(grid),
+            MeshMode::Greedy => greedy_mesh(grid),
+            MeshMode::SurfaceNets => surface_nets_mesh(grid),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshOptions {
+    pub mode:MeshMode,
+    pub ignored_materials:Vec<u16>,//voxels holding only these materials are meshed as empty
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            mode:MeshMode::SurfaceNets,
+            ignored_materials:vec![],
+        }
+    }
+}
+
+impl HybridOctree {
+    //meshes one loaded level. Faces on a border with a neighbouring level are built by exactly one of the two levels,
+    //so meshes of neighbouring levels fit together without gaps or overlaps as long as both were meshed with the other loaded.
+    pub fn mesh_level(&self, origin:&VoxelLocation, options:&MeshOptions) -> Result<Mesh, String> {
+        let grid = self.padded_grid(origin, &options.ignored_materials)?;
+        Ok(options.mode.mesh(&grid))
+    }
+}
\ No newline at end of file
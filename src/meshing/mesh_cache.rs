@@ -0,0 +1,135 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    sync::Arc,
+};
+
+use crate::{
+    math::{
+        octree_math::{
+            level_key,
+            level_origin,
+            LevelKey,
+        },
+        vectors::VoxelLocation,
+    },
+    meshing::{
+        mesh::{
+            Mesh,
+            MeshOptions,
+        },
+        padded_grid::GridSource,
+    },
+    objekt_impl::storage::hybrid_octree::HybridOctree,
+};
+
+struct CachedMesh {
+    origin:VoxelLocation,
+    mesh:Arc<Mesh>,
+    sources:Vec<(LevelKey, GridSource)>,//revisions of the level and its neighbours when meshed
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshUpdateReport {
+    pub rebuilt:Vec<VoxelLocation>,//origins of levels that were meshed again
+    pub removed:Vec<VoxelLocation>,//origins of levels whose meshes were dropped for the level no longer being loaded
+}
+
+//Meshes of many levels, built with one set of options. A mesh depends on its level and on the borders of the 26 levels
+//around it, so it is only rebuilt once one of those changed revision, was loaded, or was unloaded since it was built.
+//Meshes are kept by level key and compared by revision, so the cache holds on to no level and unloaded levels can be reclaimed.
+pub struct MeshCache {
+    pub options:MeshOptions,//clear the cache after changing these
+    meshes:HashMap<LevelKey, CachedMesh>,
+}
+
+impl MeshCache {
+    pub fn new(options:MeshOptions) -> Self {
+        Self {
+            options:options,
+            meshes:HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, octree:&HybridOctree, origin:&VoxelLocation) -> Option<Arc<Mesh>> {
+        self.meshes.get(&level_key(origin, octree.level_length)?).map(|cached| cached.mesh.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+    }
+
+    //whether the mesh of the level at origin is missing or out of date
+    pub fn is_stale(&self, octree:&HybridOctree, origin:&VoxelLocation) -> bool {
+        match level_key(origin, octree.level_length).and_then(|key| self.meshes.get(&key)) {
+            Some(cached) => cached.sources.iter().any(|(key, source)| *source!=octree.grid_source(*key)),
+            None => true,
+        }
+    }
+
+    //brings the meshes of the given levels up to date, meshing those that are stale and dropping those that are no longer loaded
+    pub fn update(&mut self, octree:&HybridOctree, origins:&[VoxelLocation]) -> Result<MeshUpdateReport, String> {
+        let mut report = MeshUpdateReport::default();
+        for origin in origins {
+            let origin = level_origin(origin, octree.level_length);
+            let key = match level_key(&origin, octree.level_length) {
+                Some(key) => key,
+                None => continue,
+            };
+            if octree.grid_source(key).is_none() {
+                if self.meshes.remove(&key).is_some() {
+                    report.removed.push(origin);
+                }
+                continue;
+            }
+            if !self.is_stale(octree, &origin) {
+                continue;
+            }
+            let (grid, sources) = match octree.padded_grid_with_sources(&origin, &self.options.ignored_materials) {
+                Ok(read) => read,
+                //unloaded since it was checked
+                Err(_) if octree.grid_source(key).is_none() => {
+                    if self.meshes.remove(&key).is_some() {
+                        report.removed.push(origin);
+                    }
+                    continue;
+                }
+                Err(msg) => return Err(msg),
+            };
+            self.meshes.insert(key, CachedMesh {
+                origin:origin.clone(),
+                mesh:Arc::new(self.options.mode.mesh(&grid)),
+                sources:sources,
+            });
+            report.rebuilt.push(origin);
+        }
+        Ok(report)
+    }
+
+    //updates every loaded level at one LOD, and drops the meshes of levels at that LOD that are no longer loaded
+    pub fn update_lod(&mut self, octree:&HybridOctree, lod:u64) -> Result<MeshUpdateReport, String> {
+        //unloaded levels lose their entries, so the levels with cached meshes are visited as well
+        let mut origins:BTreeMap<LevelKey, VoxelLocation> = self.meshes.iter()
+            .filter(|(_key, cached)| cached.origin.lod==lod)
+            .map(|(key, cached)| (*key, cached.origin.clone()))
+            .collect();
+        for sorted_level in octree.levels.levels() {
+            if sorted_level.location.lod==lod {
+                if let Some(key) = level_key(&sorted_level.location, octree.level_length) {
+                    origins.insert(key, sorted_level.location.clone());
+                }
+            }
+        }
+        self.update(octree, &origins.into_values().collect::<Vec<VoxelLocation>>())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,6 @@
+pub mod blocky;
+pub mod greedy;
+pub mod mesh;
+pub mod mesh_cache;
+pub mod padded_grid;
+pub mod surface_nets;
\ No newline at end of file
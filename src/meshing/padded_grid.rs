@@ -0,0 +1,201 @@
+use crate::{
+    math::{
+        octree_math::{
+            index_in_level,
+            level_key,
+            level_origin,
+            LevelKey,
+            voxel_min_corner,
+            voxel_size,
+            MAX_LEVEL_COORD,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        material_composition::MaterialComposition,
+        particle::MATERIAL_COUNT,
+    },
+};
+
+//the revision of a level that a grid was read from, see next_revision. None where the level was missing or not loaded.
+pub type GridSource = Option<u64>;
+
+//A level's voxels plus one voxel of padding on every side, taken from the neighbouring levels at the same LOD.
+//Meshers need the padding to put faces and vertices on level borders in the same place when meshing from either side.
+//Padding where the neighbouring level is not loaded, or lies outside of the addressable range, repeats the level's own
+//border voxels, so no walls are built against space that is unknown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaddedGrid {
+    pub origin:VoxelLocation,//of the level
+    pub level_length:u64,
+    pub materials:Vec<Option<u16>>,//dominant material of each sample, None where empty. Stored x-major like levels, with padding at 0 and level_length+1.
+    pub densities:Vec<f32>,//how full each sample is, from 0 for empty to 1 for a full weight of material
+}
+
+impl PaddedGrid {
+    //every sample empty
+    pub fn new(origin:VoxelLocation, level_length:u64) -> Self {
+        let samples = (level_length as usize+2).pow(3);
+        Self {
+            origin:origin,
+            level_length:level_length,
+            materials:vec![None; samples],
+            densities:vec![0.0; samples],
+        }
+    }
+
+    //samples per axis, including padding
+    pub fn side(&self) -> usize {
+        self.level_length as usize+2
+    }
+
+    pub fn index(&self, x:usize, y:usize, z:usize) -> usize {
+        let side = self.side();
+        (x*side+y)*side+z
+    }
+
+    pub fn material(&self, x:usize, y:usize, z:usize) -> Option<u16> {
+        self.materials[self.index(x, y, z)]
+    }
+
+    pub fn density(&self, x:usize, y:usize, z:usize) -> f32 {
+        self.densities[self.index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x:usize, y:usize, z:usize, weights:&MaterialComposition, ignored:&[u16]) {
+        let index = self.index(x, y, z);
+        let material = weights.dominant(ignored);
+        self.materials[index] = material;
+        self.densities[index] = match material {
+            Some(_) => (weights.total_weight(ignored) as f32/u8::MAX as f32).min(1.0),
+            None => 0.0,
+        };
+    }
+
+    //edge length of one sample in world units
+    pub fn voxel_size(&self) -> f64 {
+        voxel_size(self.origin.lod) as f64
+    }
+
+    //world position of the level's lowest corner, which mesh positions are relative to
+    pub fn world_origin(&self) -> [f64;3] {
+        voxel_min_corner(&self.origin)
+    }
+}
+
+impl HybridOctree {
+    //reads the level at origin and the border voxels of its 26 neighbours, without loading anything. Voxels holding only ignored materials count as empty.
+    pub fn padded_grid(&self, origin:&VoxelLocation, ignored:&[u16]) -> Result<PaddedGrid, String> {
+        self.padded_grid_with_sources(origin, ignored).map(|(grid, _sources)| grid)
+    }
+
+    //what a grid read from the level with this key right now would be read from, without counting as an access to the level
+    pub fn grid_source(&self, key:LevelKey) -> GridSource {
+        let sorted_level = self.levels.get(key)?;
+        let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+        contents.loaded.then_some(contents.revision)
+    }
+
+    //also returns what each of the levels involved was read from, see GridSource, taken while the data was read
+    pub fn padded_grid_with_sources(&self, origin:&VoxelLocation, ignored:&[u16]) -> Result<(PaddedGrid, Vec<(LevelKey, GridSource)>), String> {
+        if let Some(material) = ignored.iter().find(|material| **material as u64>=MATERIAL_COUNT) {
+            return Err(format!("Material {} does not exist, there are only {} materials", material, MATERIAL_COUNT));
+        }
+        let level_length = self.level_length;
+        let length = level_length as usize;
+        let origin = &level_origin(origin, level_length);
+        let mut grid = PaddedGrid::new(origin.clone(), level_length);
+        let mut filled = vec![false; grid.materials.len()];
+        let mut sources = vec![];
+
+        //the padded samples covered by each neighbour, as (first padded index, first voxel within the neighbour, count) per axis
+        let span = |offset:i64| match offset {
+            -1 => (0, length-1, 1),
+            0 => (1, 0, length),
+            _ => (length+1, 0, 1),
+        };
+        let neighbour = |start:u64, offset:i64| match offset {
+            -1 => start.checked_sub(level_length),
+            1 => start.checked_add(level_length).filter(|coord| coord/level_length<=MAX_LEVEL_COORD),
+            _ => Some(start),
+        };
+        for dx in -1i64..=1 {
+            for dy in -1i64..=1 {
+                for dz in -1i64..=1 {
+                    let location = match (neighbour(origin.vec.x, dx), neighbour(origin.vec.y, dy), neighbour(origin.vec.z, dz)) {
+                        (Some(x), Some(y), Some(z)) => VoxelLocation {
+                            lod:origin.lod,
+                            vec:Vector3U64 {
+                                x:x,
+                                y:y,
+                                z:z,
+                            },
+                        },
+                        _ => continue,
+                    };
+                    let key = match level_key(&location, level_length) {
+                        Some(key) => key,
+                        None if (dx, dy, dz)==(0, 0, 0) => return Err(format!("Level at {:?} is not loaded", origin)),
+                        None => continue,
+                    };
+                    let sorted_level = match self.find_level(&location) {
+                        Some(sorted_level) => sorted_level,
+                        None if (dx, dy, dz)==(0, 0, 0) => return Err(format!("Level at {:?} is not loaded", origin)),
+                        None => {
+                            sources.push((key, None));
+                            continue;
+                        }
+                    };
+                    let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+                    if !contents.loaded {
+                        if (dx, dy, dz)==(0, 0, 0) {
+                            return Err(format!("Level at {:?} is not loaded", origin));
+                        }
+                        sources.push((key, None));
+                        continue;
+                    }
+                    sources.push((key, Some(contents.revision)));
+                    let ((x_padded, x_voxel, x_count), (y_padded, y_voxel, y_count), (z_padded, z_voxel, z_count)) = (span(dx), span(dy), span(dz));
+                    for x in 0..x_count {
+                        for y in 0..y_count {
+                            for z in 0..z_count {
+                                let voxel = VoxelLocation {
+                                    lod:origin.lod,
+                                    vec:Vector3U64 {
+                                        x:(x_voxel+x) as u64,
+                                        y:(y_voxel+y) as u64,
+                                        z:(z_voxel+z) as u64,
+                                    },
+                                };
+                                let weights = &contents.data.material[index_in_level(&voxel, level_length)];
+                                grid.set(x_padded+x, y_padded+y, z_padded+z, weights, ignored);
+                                filled[grid.index(x_padded+x, y_padded+y, z_padded+z)] = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        //padding nobody filled in repeats the nearest voxel of the level itself
+        let side = grid.side();
+        let clamp = |coord:usize| coord.clamp(1, length);
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let index = grid.index(x, y, z);
+                    if !filled[index] {
+                        let nearest = grid.index(clamp(x), clamp(y), clamp(z));
+                        grid.materials[index] = grid.materials[nearest];
+                        grid.densities[index] = grid.densities[nearest];
+                    }
+                }
+            }
+        }
+        Ok((grid, sources))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use crate::meshing::{
+    mesh::Mesh,
+    padded_grid::PaddedGrid,
+};
+
+//the twelve edges of a cell, as pairs of corners numbered with bit 0 for x, bit 1 for y and bit 2 for z
+const CELL_EDGES:[(usize, usize);12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+fn corner_offset(corner:usize) -> [usize;3] {
+    [corner&1, corner>>1&1, corner>>2&1]
+}
+
+//Surface nets over the voxel centers: a cell spans eight neighbouring samples, and every cell with both solid and empty
+//corners gets one vertex, at the mean of the points where the surface crosses its edges. A surface crossing sits half of a
+//solid sample's density away from it, so full voxels put it midway and partly filled ones pull it in. Every edge between a
+//solid and an empty sample then becomes a quad over the four cells around it.
+//A level owns the edges that start at one of its own voxels, so the edges reaching into the padding on the lower sides are left to the neighbours there.
+pub fn surface_nets_mesh(grid:&PaddedGrid) -> Mesh {
+    let mut mesh = Mesh::new(grid.world_origin());
+    let size = grid.voxel_size() as f32;
+    let length = grid.level_length as usize;
+    let cells = length+1;
+    let cell_index = |cell:[usize;3]| (cell[0]*cells+cell[1])*cells+cell[2];
+    let mut cell_vertices = vec![u32::MAX; cells.pow(3)];
+
+    //the vertex of the cell whose lowest corner is the given sample, made on first use
+    let mut vertex = |mesh:&mut Mesh, cell:[usize;3]| -> u32 {
+        let index = cell_index(cell);
+        if cell_vertices[index]!=u32::MAX {
+            return cell_vertices[index];
+        }
+        let corners:Vec<(Option<u16>, f32)> = (0..8).map(|corner| {
+            let offset = corner_offset(corner);
+            let sample = [cell[0]+offset[0], cell[1]+offset[1], cell[2]+offset[2]];
+            (grid.material(sample[0], sample[1], sample[2]), grid.density(sample[0], sample[1], sample[2]))
+        }).collect();
+
+        let mut sum = [0.0f32;3];
+        let mut crossings = 0;
+        for (a, b) in CELL_EDGES.iter() {
+            let (solid, empty, density) = match (corners[*a], corners[*b]) {
+                ((Some(_), density), (None, _)) => (*a, *b, density),
+                ((None, _), (Some(_), density)) => (*b, *a, density),
+                _ => continue,
+            };
+            let (from, to) = (corner_offset(solid), corner_offset(empty));
+            let t = 0.5*density;
+            for axis in 0..3 {
+                sum[axis]+=from[axis] as f32+(to[axis] as f32-from[axis] as f32)*t;
+            }
+            crossings+=1;
+        }
+        //padded sample i is the center of the voxel covering [i-1, i] from the level's lowest corner
+        let position = [
+            (cell[0] as f32-0.5+sum[0]/crossings as f32)*size,
+            (cell[1] as f32-0.5+sum[1]/crossings as f32)*size,
+            (cell[2] as f32-0.5+sum[2]/crossings as f32)*size,
+        ];
+
+        //the most common material among the solid corners, ties going to the lower material
+        let solids:Vec<u16> = corners.iter().filter_map(|(material, _density)| *material).collect();
+        let mut material = solids[0];
+        let mut best = 0;
+        for candidate in &solids {
+            let count = solids.iter().filter(|solid| *solid==candidate).count();
+            if count>best || (count==best && *candidate<material) {
+                material = *candidate;
+                best = count;
+            }
+        }
+
+        let created = mesh.push_vertex(position, [0.0;3], material);
+        cell_vertices[index] = created;
+        created
+    };
+
+    for x in 1..=length {
+        for y in 1..=length {
+            for z in 1..=length {
+                let sample = [x, y, z];
+                let inside = grid.material(x, y, z).is_some();
+                for axis in 0..3 {
+                    let mut next = sample;
+                    next[axis]+=1;
+                    if grid.material(next[0], next[1], next[2]).is_some()==inside {
+                        continue;
+                    }
+                    //the four cells around the edge, counter-clockwise seen from the positive side of the axis
+                    let (u, v) = ((axis+1)%3, (axis+2)%3);
+                    let mut corners = [0;4];
+                    for (corner, (du, dv)) in corners.iter_mut().zip([(1, 1), (0, 1), (0, 0), (1, 0)]) {
+                        let mut cell = sample;
+                        cell[u]-=du;
+                        cell[v]-=dv;
+                        *corner = vertex(&mut mesh, cell);
+                    }
+                    //the surface faces away from the solid side
+                    if !inside {
+                        corners.reverse();
+                    }
+                    mesh.push_quad(corners);
+                }
+            }
+        }
+    }
+
+    //smooth normals, summed from the triangles around each vertex weighted by their area
+    let mut normals = vec![[0.0f32;3]; mesh.positions.len()];
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [mesh.positions[triangle[0] as usize], mesh.positions[triangle[1] as usize], mesh.positions[triangle[2] as usize]];
+        let (ab, ac) = ([b[0]-a[0], b[1]-a[1], b[2]-a[2]], [c[0]-a[0], c[1]-a[1], c[2]-a[2]]);
+        let cross = [ab[1]*ac[2]-ab[2]*ac[1], ab[2]*ac[0]-ab[0]*ac[2], ab[0]*ac[1]-ab[1]*ac[0]];
+        for index in triangle {
+            for axis in 0..3 {
+                normals[*index as usize][axis]+=cross[axis];
+            }
+        }
+    }
+    for normal in &mut normals {
+        let length = (normal[0]*normal[0]+normal[1]*normal[1]+normal[2]*normal[2]).sqrt();
+        if length>0.0 {
+            *normal = [normal[0]/length, normal[1]/length, normal[2]/length];
+        }
+    }
+    mesh.normals = normals;
+    mesh
+}
\ No newline at end of file
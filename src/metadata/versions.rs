@@ -1,3 +1,8 @@
+use crate::utils::binary::{
+    ByteReader,
+    write_u32,
+};
+
 #[derive(Clone)]
 pub struct MoleculeApplicationVersion {
     pub major: u32,
@@ -5,9 +10,25 @@ pub struct MoleculeApplicationVersion {
     pub patch: u32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct MoleculeVersion {
     pub major: u32,
     pub minor: u32,
     pub patch: u32,
+}
+
+impl MoleculeVersion {
+    pub fn write(&self, out:&mut Vec<u8>) {
+        write_u32(out, self.major);
+        write_u32(out, self.minor);
+        write_u32(out, self.patch);
+    }
+
+    pub fn read(reader:&mut ByteReader) -> Result<Self, String> {
+        Ok(Self {
+            major:reader.read_u32()?,
+            minor:reader.read_u32()?,
+            patch:reader.read_u32()?,
+        })
+    }
 }
\ No newline at end of file
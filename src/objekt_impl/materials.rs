@@ -0,0 +1,298 @@
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    concurrency::{
+        molecule_objekt::MoleculeObjekt,
+        objekt_snapshot::SerializableObjekt,
+    },
+    objekt_impl::storage::particle::MATERIAL_COUNT,
+    utils::binary::{
+        ByteReader,
+        write_str,
+    },
+};
+
+//shown for materials nobody defined, so they stand out
+pub const UNDEFINED_COLOR:[u8;4] = [255, 0, 255, 255];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Solid,
+    Liquid,
+    Gas,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Solid => "solid",
+            Phase::Liquid => "liquid",
+            Phase::Gas => "gas",
+        }
+    }
+
+    pub fn parse(text:&str) -> Result<Self, String> {
+        match text {
+            "solid" => Ok(Phase::Solid),
+            "liquid" => Ok(Phase::Liquid),
+            "gas" => Ok(Phase::Gas),
+            _ => Err(format!("Unknown phase {}, expected solid, liquid or gas", text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialDefinition {
+    pub name:String,//unique within a registry
+    pub density:f32,//kilograms per cubic meter
+    pub color:[u8;4],//sRGB and alpha
+    pub phase:Phase,
+    pub hardness:f32,//on the Mohs scale
+    pub thermal_conductivity:f32,//watts per meter kelvin
+    pub flammability:f32,//from 0 for inert to 1 for catching fire at once
+}
+
+impl MaterialDefinition {
+    //a solid with the density of water, for fields a data file leaves out
+    pub fn new(name:String) -> Self {
+        Self {
+            name:name,
+            density:1000.0,
+            color:UNDEFINED_COLOR,
+            phase:Phase::Solid,
+            hardness:0.0,
+            thermal_conductivity:0.0,
+            flammability:0.0,
+        }
+    }
+}
+
+fn parse_color(text:&str) -> Result<[u8;4], String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if (hex.len()!=6 && hex.len()!=8) || !hex.is_ascii() {
+        return Err(format!("Color {} should be written as RRGGBB or RRGGBBAA in hexadecimal", text));
+    }
+    let mut color = [255;4];
+    for (channel, value) in color.iter_mut().zip(0..hex.len()/2) {
+        *channel = u8::from_str_radix(&hex[value*2..value*2+2], 16).map_err(|e| format!("Color {} is not hexadecimal: {}", text, e))?;
+    }
+    Ok(color)
+}
+
+fn parse_number(key:&str, text:&str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("{} should be a number, not {}", key, text)),
+    }
+}
+
+//What each of the MATERIAL_COUNT weights of a Particle stands for. Generators, physics and rendering all read from the same registry,
+//which is kept in a plain text file of one section per material:
+//
+//  # comment
+//  [1]
+//  name = stone
+//  density = 2600
+//  color = 7f7f7f
+//  phase = solid
+//  hardness = 6
+//  thermal_conductivity = 2.5
+//  flammability = 0
+//
+//Sections are headed by the material's index, and every key but name may be left out, see MaterialDefinition::new.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialRegistry {
+    materials:Vec<Option<MaterialDefinition>>,//indexed by material
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        Self {
+            materials:vec![None; MATERIAL_COUNT as usize],
+        }
+    }
+
+    pub fn define(&mut self, index:u16, definition:MaterialDefinition) -> Result<(), String> {
+        if index as u64>=MATERIAL_COUNT {
+            return Err(format!("Material {} does not exist, there are only {} materials", index, MATERIAL_COUNT));
+        }
+        //names have to survive a trip through the text format
+        if definition.name.is_empty() || definition.name.trim()!=definition.name || definition.name.chars().any(|c| c.is_control()) {
+            return Err(format!("Material name {:?} may not be empty, start or end with whitespace, or hold control characters", definition.name));
+        }
+        if let Some(other) = self.index_of(&definition.name).filter(|other| *other!=index) {
+            return Err(format!("Material name {} is already used by material {}", definition.name, other));
+        }
+        self.materials[index as usize] = Some(definition);
+        Ok(())
+    }
+
+    pub fn get(&self, index:u16) -> Option<&MaterialDefinition> {
+        self.materials.get(index as usize)?.as_ref()
+    }
+
+    pub fn index_of(&self, name:&str) -> Option<u16> {
+        self.iter().find(|(_index, definition)| definition.name==name).map(|(index, _definition)| index)
+    }
+
+    //defined materials in index order
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &MaterialDefinition)> {
+        self.materials.iter().enumerate().filter_map(|(index, definition)| definition.as_ref().map(|definition| (index as u16, definition)))
+    }
+
+    //number of defined materials
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len()==0
+    }
+
+    pub fn color(&self, index:u16) -> [u8;4] {
+        self.get(index).map_or(UNDEFINED_COLOR, |definition| definition.color)
+    }
+
+    //one color per material index, ready to upload as a lookup table
+    pub fn colors(&self) -> Vec<[u8;4]> {
+        (0..MATERIAL_COUNT as u16).map(|index| self.color(index)).collect()
+    }
+
+    pub fn parse(text:&str) -> Result<Self, String> {
+        let mut registry = Self::new();
+        //index, definition and line of the section header being read
+        let mut section:Option<(u16, MaterialDefinition, usize)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let number = number+1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let index = header.strip_suffix(']')
+                    .and_then(|index| index.trim().parse::<u16>().ok())
+                    .ok_or_else(|| format!("Line {}: section header {} should be a material index in brackets", number, line))?;
+                registry.finish_section(section.take())?;
+                section = Some((index, MaterialDefinition::new(String::new()), number));
+                continue;
+            }
+            let definition = match &mut section {
+                Some((_index, definition, _line)) => definition,
+                None => return Err(format!("Line {}: {} comes before the first material section", number, line)),
+            };
+            let (key, value) = line.split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format!("Line {}: expected key = value, found {}", number, line))?;
+            let parsed = match key {
+                "name" => {
+                    definition.name = value.to_string();
+                    Ok(())
+                }
+                "density" => parse_number(key, value).map(|value| definition.density = value),
+                "color" => parse_color(value).map(|value| definition.color = value),
+                "phase" => Phase::parse(value).map(|value| definition.phase = value),
+                "hardness" => parse_number(key, value).map(|value| definition.hardness = value),
+                "thermal_conductivity" => parse_number(key, value).map(|value| definition.thermal_conductivity = value),
+                "flammability" => parse_number(key, value).map(|value| definition.flammability = value),
+                _ => Err(format!("unknown key {}", key)),
+            };
+            parsed.map_err(|msg| format!("Line {}: {}", number, msg))?;
+        }
+        registry.finish_section(section)?;
+        Ok(registry)
+    }
+
+    fn finish_section(&mut self, section:Option<(u16, MaterialDefinition, usize)>) -> Result<(), String> {
+        let (index, definition, line) = match section {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        if definition.name.is_empty() {
+            return Err(format!("Line {}: material {} has no name", line, index));
+        }
+        if self.get(index).is_some() {
+            return Err(format!("Line {}: material {} is defined more than once", line, index));
+        }
+        self.define(index, definition).map_err(|msg| format!("Line {}: {}", line, msg))
+    }
+
+    //the text format read by parse, with every key written out
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (index, definition) in self.iter() {
+            let color = definition.color;
+            text.push_str(&format!(
+                "[{}]\nname = {}\ndensity = {}\ncolor = {:02x}{:02x}{:02x}{:02x}\nphase = {}\nhardness = {}\nthermal_conductivity = {}\nflammability = {}\n\n",
+                index,
+                definition.name,
+                definition.density,
+                color[0], color[1], color[2], color[3],
+                definition.phase.name(),
+                definition.hardness,
+                definition.thermal_conductivity,
+                definition.flammability,
+            ));
+        }
+        text
+    }
+
+    pub fn load(path:&Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read material registry {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|msg| format!("Could not parse material registry {}: {}", path.display(), msg))
+    }
+
+    pub fn save(&self, path:&Path) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Could not write material registry {}: {}", path.display(), e))
+    }
+}
+
+//the registry is only read once loaded, so every task shares one copy
+#[derive(Clone)]
+pub struct MaterialRegistryObjekt {
+    name: String,
+    inner: Arc<MaterialRegistry>,
+}
+
+impl MaterialRegistryObjekt {
+    pub fn new(name:String, registry:MaterialRegistry) -> Self {
+        Self {
+            name:name,
+            inner:Arc::new(registry),
+        }
+    }
+
+    pub fn inner(&self) -> Arc<MaterialRegistry> {
+        self.inner.clone()
+    }
+}
+
+impl MoleculeObjekt for MaterialRegistryObjekt {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl SerializableObjekt for MaterialRegistryObjekt {
+    fn type_tag() -> &'static str {
+        "molecule_engine::MaterialRegistry"
+    }
+
+    fn serialize(&self, out:&mut Vec<u8>) -> Result<(), String> {
+        write_str(out, &self.inner.to_text());
+        Ok(())
+    }
+
+    fn deserialize(name:String, reader:&mut ByteReader) -> Result<Self, String> {
+        Ok(Self::new(name, MaterialRegistry::parse(&reader.read_str()?)?))
+    }
+}
\ No newline at end of file
@@ -1 +1,2 @@
+pub mod materials;
 pub mod storage;
\ No newline at end of file
@@ -0,0 +1,456 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+use crate::{
+    math::vectors::Vector3U16,
+    objekt_impl::storage::{
+        material_composition::MaterialComposition,
+        particle::{
+            Particle,
+            ParticleVec,
+            MATERIAL_COUNT,
+        },
+    },
+    utils::binary::{
+        ByteReader,
+        write_u8,
+        write_u16,
+        write_varint,
+    },
+};
+
+//Ways of writing one column of numbers. Levels mostly hold long runs of the same value, such as every generated pos sitting at
+//HALF_LIMIT, or a few values repeated all over, so one of these nearly always beats writing each value out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnCodec {
+    Plain,//each value as a varint
+    Rle,//(run length, value) pairs
+    Delta,//the zigzag encoded difference from the previous value, starting from 0
+    Palette,//the distinct values in order of first appearance, then an index into them per value, packed into as few bits as fit
+}
+
+impl ColumnCodec {
+    pub const ALL:[ColumnCodec;4] = [ColumnCodec::Plain, ColumnCodec::Rle, ColumnCodec::Delta, ColumnCodec::Palette];
+
+    fn tag(&self) -> u8 {
+        match self {
+            ColumnCodec::Plain => 0,
+            ColumnCodec::Rle => 1,
+            ColumnCodec::Delta => 2,
+            ColumnCodec::Palette => 3,
+        }
+    }
+
+    fn from_tag(tag:u8) -> Result<Self, String> {
+        ColumnCodec::ALL.iter().find(|codec| codec.tag()==tag).cloned().ok_or_else(|| format!("Unknown column codec {}", tag))
+    }
+
+    pub fn encode(&self, values:&[u64], out:&mut Vec<u8>) {
+        match self {
+            ColumnCodec::Plain => {
+                for value in values {
+                    write_varint(out, *value);
+                }
+            }
+            ColumnCodec::Rle => {
+                let mut start = 0;
+                while start<values.len() {
+                    let run = values[start..].iter().take_while(|value| **value==values[start]).count();
+                    write_varint(out, run as u64);
+                    write_varint(out, values[start]);
+                    start+=run;
+                }
+            }
+            ColumnCodec::Delta => {
+                let mut previous = 0u64;
+                for value in values {
+                    let delta = value.wrapping_sub(previous) as i64;
+                    write_varint(out, ((delta<<1)^(delta>>63)) as u64);
+                    previous = *value;
+                }
+            }
+            ColumnCodec::Palette => {
+                let mut palette = vec![];
+                let mut indices = HashMap::new();
+                for value in values {
+                    indices.entry(*value).or_insert_with(|| {
+                        palette.push(*value);
+                        palette.len()-1
+                    });
+                }
+                write_varint(out, palette.len() as u64);
+                for value in &palette {
+                    write_varint(out, *value);
+                }
+                let bits = index_bits(palette.len());
+                let mut packed = vec![0u8; (values.len()*bits).div_ceil(8)];
+                for (position, value) in values.iter().enumerate() {
+                    let index = indices[value];
+                    for bit in 0..bits {
+                        if index>>bit&1==1 {
+                            let packed_bit = position*bits+bit;
+                            packed[packed_bit/8]|=1<<(packed_bit%8);
+                        }
+                    }
+                }
+                out.extend_from_slice(&packed);
+            }
+        }
+    }
+
+    //reads exactly len values written by encode
+    pub fn decode(&self, reader:&mut ByteReader, len:usize) -> Result<Vec<u64>, String> {
+        let mut values = vec![];
+        match self {
+            ColumnCodec::Plain => {
+                for _i in 0..len {
+                    values.push(reader.read_varint()?);
+                }
+            }
+            ColumnCodec::Rle => {
+                while values.len()<len {
+                    let run = reader.read_varint()?;
+                    if run==0 || run>(len-values.len()) as u64 {
+                        return Err(format!("Run of {} values does not fit in a column of {} with {} already read", run, len, values.len()));
+                    }
+                    let value = reader.read_varint()?;
+                    values.resize(values.len()+run as usize, value);
+                }
+            }
+            ColumnCodec::Delta => {
+                let mut previous = 0u64;
+                for _i in 0..len {
+                    let zigzag = reader.read_varint()?;
+                    let delta = ((zigzag>>1) as i64)^-((zigzag&1) as i64);
+                    previous = previous.wrapping_add(delta as u64);
+                    values.push(previous);
+                }
+            }
+            ColumnCodec::Palette => {
+                let palette_len = reader.read_varint()?;
+                //every palette entry takes at least a byte, and only a palette of one value can cover values without any index bits
+                if palette_len>reader.remaining() as u64 || (palette_len==0 && len>0) {
+                    return Err(format!("Palette of {} values cannot hold a column of {}", palette_len, len));
+                }
+                let mut palette = vec![];
+                for _i in 0..palette_len {
+                    palette.push(reader.read_varint()?);
+                }
+                let bits = index_bits(palette.len());
+                let packed_len = len.checked_mul(bits).map(|bit_len| bit_len.div_ceil(8))
+                    .ok_or_else(|| format!("Column of {} values is too long", len))?;
+                let packed = reader.read_slice(packed_len)?;
+                for position in 0..len {
+                    let mut index = 0;
+                    for bit in 0..bits {
+                        let packed_bit = position*bits+bit;
+                        index|=((packed[packed_bit/8]>>(packed_bit%8)&1) as usize)<<bit;
+                    }
+                    match palette.get(index) {
+                        Some(value) => values.push(*value),
+                        None => return Err(format!("Palette index {} is past the end of a palette of {} values", index, palette.len())),
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+//bits needed to tell apart palette_len indices
+fn index_bits(palette_len:usize) -> usize {
+    (usize::BITS-palette_len.saturating_sub(1).leading_zeros()) as usize
+}
+
+//A general purpose compressor run over a whole encoded level, for when the column codecs leave patterns behind.
+//Implementations must decompress exactly what they compressed.
+pub trait ByteCompressor: Send + Sync {
+    fn id(&self) -> u8;//written ahead of the compressed data so readers can tell compressors apart. 0 means none, and LzCompressor uses 1.
+    fn compress(&self, data:&[u8]) -> Vec<u8>;
+    fn decompress(&self, data:&[u8], max_len:usize) -> Result<Vec<u8>, String>;//fails rather than producing more than max_len bytes
+}
+
+pub const NO_COMPRESSOR_ID:u8 = 0;
+pub const LZ_COMPRESSOR_ID:u8 = 1;
+
+const LZ_MIN_MATCH:usize = 4;
+const LZ_HASH_BITS:u32 = 14;
+
+//A small LZ77 compressor with no dictionary limit: the uncompressed length, then (literal count, literals, match offset, match length)
+//sequences, with the last sequence holding only literals. Quick rather than thorough, matches are taken as soon as they are found.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LzCompressor;
+
+fn lz_hash(bytes:&[u8]) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (value.wrapping_mul(2654435761)>>(32-LZ_HASH_BITS)) as usize
+}
+
+impl ByteCompressor for LzCompressor {
+    fn id(&self) -> u8 {
+        LZ_COMPRESSOR_ID
+    }
+
+    fn compress(&self, data:&[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        write_varint(&mut out, data.len() as u64);
+        //the last position each hashed four bytes were seen at
+        let mut table = vec![usize::MAX; 1<<LZ_HASH_BITS];
+        let mut literal_start = 0;
+        let mut position = 0;
+        while position+LZ_MIN_MATCH<=data.len() {
+            let hash = lz_hash(&data[position..]);
+            let candidate = table[hash];
+            table[hash] = position;
+            if candidate==usize::MAX || data[candidate..candidate+LZ_MIN_MATCH]!=data[position..position+LZ_MIN_MATCH] {
+                position+=1;
+                continue;
+            }
+            let mut len = LZ_MIN_MATCH;
+            while position+len<data.len() && data[candidate+len]==data[position+len] {
+                len+=1;
+            }
+            write_varint(&mut out, (position-literal_start) as u64);
+            out.extend_from_slice(&data[literal_start..position]);
+            write_varint(&mut out, (position-candidate) as u64);
+            write_varint(&mut out, (len-LZ_MIN_MATCH) as u64);
+            position+=len;
+            literal_start = position;
+        }
+        write_varint(&mut out, (data.len()-literal_start) as u64);
+        out.extend_from_slice(&data[literal_start..]);
+        out
+    }
+
+    fn decompress(&self, data:&[u8], max_len:usize) -> Result<Vec<u8>, String> {
+        let mut reader = ByteReader::new(data);
+        let len = reader.read_varint()?;
+        if len>max_len as u64 {
+            return Err(format!("Compressed data claims to hold {} bytes, more than the {} allowed", len, max_len));
+        }
+        let len = len as usize;
+        let mut out = Vec::with_capacity(len);
+        loop {
+            let literals = reader.read_varint()?;
+            if literals>(len-out.len()) as u64 {
+                return Err(format!("{} literal bytes run past the uncompressed length {}", literals, len));
+            }
+            out.extend_from_slice(reader.read_slice(literals as usize)?);
+            if out.len()==len {
+                break;
+            }
+            let offset = reader.read_varint()?;
+            let match_len = reader.read_varint()?.saturating_add(LZ_MIN_MATCH as u64);
+            if offset==0 || offset>out.len() as u64 || match_len>(len-out.len()) as u64 {
+                return Err(format!("Match of {} bytes at offset {} does not fit after {} of {} bytes", match_len, offset, out.len(), len));
+            }
+            //byte by byte, since a match may overlap the bytes it produces
+            let start = out.len()-offset as usize;
+            for index in 0..match_len as usize {
+                out.push(out[start+index]);
+            }
+        }
+        if reader.remaining()!=0 {
+            return Err(format!("Compressed data has {} trailing bytes", reader.remaining()));
+        }
+        Ok(out)
+    }
+}
+
+const SPARSE_COMPOSITION:u8 = 0;
+const DENSE_COMPOSITION:u8 = 1;
+
+//Settings for encoding levels, shared by region files and level payloads. The encoding is self describing, so data written
+//with any codecs can be decoded with any settings, as long as the compressor it used is LzCompressor or the one given here.
+#[derive(Clone, Default)]
+pub struct LevelCompression {
+    pub codec:Option<ColumnCodec>,//used for every column, or None to pick the smallest encoding for each column separately
+    pub compressor:Option<Arc<dyn ByteCompressor>>,
+}
+
+impl LevelCompression {
+    pub fn new(codec:Option<ColumnCodec>, compressor:Option<Arc<dyn ByteCompressor>>) -> Self {
+        Self {
+            codec:codec,
+            compressor:compressor,
+        }
+    }
+
+    //the smallest column encodings with LzCompressor on top, trading a little time for the smallest output
+    pub fn smallest() -> Self {
+        Self::new(None, Some(Arc::new(LzCompressor)))
+    }
+
+    fn write_column(&self, values:&[u64], out:&mut Vec<u8>) {
+        let codec = match self.codec {
+            Some(codec) => codec,
+            None => *ColumnCodec::ALL.iter()
+                .min_by_key(|codec| {
+                    let mut encoded = vec![];
+                    codec.encode(values, &mut encoded);
+                    encoded.len()
+                })
+                .expect("There is always a codec"),
+        };
+        write_u8(out, codec.tag());
+        codec.encode(values, out);
+    }
+
+    //Layout: compressor id, then, compressed if the id is not 0: particle count, then each column as a codec tag and its encoding.
+    //Materials are written as a palette of the distinct compositions, each either listing its materials or holding every weight,
+    //followed by a column of indices into it. pos is written as one column per axis.
+    pub fn encode(&self, particles:&ParticleVec) -> Vec<u8> {
+        let mut body = vec![];
+        write_varint(&mut body, particles.len() as u64);
+        let current:Vec<u64> = particles._gpu_only_level_index_current.iter().map(|index| *index as u64).collect();
+        self.write_column(&current, &mut body);
+        let next:Vec<u64> = particles._gpu_only_level_index_next.iter().map(|index| *index as u64).collect();
+        self.write_column(&next, &mut body);
+
+        let mut palette = vec![];
+        let mut palette_indices = HashMap::new();
+        let mut material_indices = vec![];
+        for material in &particles.material {
+            let entries:Vec<(u16, u8)> = material.iter().collect();
+            let index = *palette_indices.entry(entries).or_insert_with_key(|entries| {
+                palette.push(entries.clone());
+                palette.len()-1
+            });
+            material_indices.push(index as u64);
+        }
+        write_varint(&mut body, palette.len() as u64);
+        for entries in &palette {
+            //a composition of many materials is smaller as one weight per material
+            if entries.len()*3>MATERIAL_COUNT as usize {
+                write_u8(&mut body, DENSE_COMPOSITION);
+                body.extend_from_slice(&MaterialComposition::from_entries(entries.iter().cloned()).weights());
+                continue;
+            }
+            write_u8(&mut body, SPARSE_COMPOSITION);
+            write_varint(&mut body, entries.len() as u64);
+            for (material, weight) in entries {
+                write_u16(&mut body, *material);
+                write_u8(&mut body, *weight);
+            }
+        }
+        self.write_column(&material_indices, &mut body);
+
+        let axes:[fn(&Vector3U16) -> u16;3] = [|pos| pos.x, |pos| pos.y, |pos| pos.z];
+        for axis in axes {
+            let values:Vec<u64> = particles.pos.iter().map(|pos| axis(pos) as u64).collect();
+            self.write_column(&values, &mut body);
+        }
+
+        let mut out = vec![];
+        match &self.compressor {
+            Some(compressor) => {
+                write_u8(&mut out, compressor.id());
+                out.extend_from_slice(&compressor.compress(&body));
+            }
+            None => {
+                write_u8(&mut out, NO_COMPRESSOR_ID);
+                out.extend_from_slice(&body);
+            }
+        }
+        out
+    }
+
+    //data must hold exactly one encoded ParticleVec of particle_count Particles, which bounds how much memory decoding may take
+    pub fn decode(&self, data:&[u8], particle_count:usize) -> Result<ParticleVec, String> {
+        let mut reader = ByteReader::new(data);
+        let compressor_id = reader.read_u8()?;
+        let rest = reader.read_slice(reader.remaining())?;
+        let decompressed;
+        let body = match compressor_id {
+            NO_COMPRESSOR_ID => rest,
+            _ => {
+                let compressor:&dyn ByteCompressor = match &self.compressor {
+                    Some(compressor) if compressor.id()==compressor_id => compressor.as_ref(),
+                    _ if compressor_id==LZ_COMPRESSOR_ID => &LzCompressor,
+                    _ => return Err(format!("Level was compressed with unknown compressor {}", compressor_id)),
+                };
+                decompressed = compressor.decompress(rest, max_body_len(particle_count))?;
+                &decompressed[..]
+            }
+        };
+
+        let mut reader = ByteReader::new(body);
+        let len = reader.read_varint()?;
+        if len!=particle_count as u64 {
+            return Err(format!("Encoded level holds {} Particles, expected {}", len, particle_count));
+        }
+        let current = read_column(&mut reader, particle_count, u32::MAX as u64)?;
+        let next = read_column(&mut reader, particle_count, u32::MAX as u64)?;
+
+        let palette_len = reader.read_varint()?;
+        if palette_len>reader.remaining() as u64 || (palette_len==0 && particle_count>0) {
+            return Err(format!("Material palette of {} compositions cannot cover {} Particles", palette_len, particle_count));
+        }
+        let mut palette = vec![];
+        for _i in 0..palette_len {
+            match reader.read_u8()? {
+                SPARSE_COMPOSITION => {}
+                DENSE_COMPOSITION => {
+                    palette.push(MaterialComposition::from_weights(reader.read_slice(MATERIAL_COUNT as usize)?));
+                    continue;
+                }
+                form => return Err(format!("Unknown material composition form {}", form)),
+            }
+            let entry_count = reader.read_varint()?;
+            if entry_count>MATERIAL_COUNT {
+                return Err(format!("Material composition holds {} materials, but there are only {} materials", entry_count, MATERIAL_COUNT));
+            }
+            let mut entries:Vec<(u16, u8)> = vec![];
+            for _j in 0..entry_count {
+                let entry = (reader.read_u16()?, reader.read_u8()?);
+                //anything else would not come back out of MaterialComposition as written
+                if entry.0 as u64>=MATERIAL_COUNT || entry.1==0 || entries.last().is_some_and(|last| last.0>=entry.0) {
+                    return Err(format!("Material composition entry {:?} is out of range or out of order", entry));
+                }
+                entries.push(entry);
+            }
+            palette.push(MaterialComposition::from_entries(entries));
+        }
+        let material_indices = read_column(&mut reader, particle_count, palette_len.saturating_sub(1))?;
+
+        let x = read_column(&mut reader, particle_count, u16::MAX as u64)?;
+        let y = read_column(&mut reader, particle_count, u16::MAX as u64)?;
+        let z = read_column(&mut reader, particle_count, u16::MAX as u64)?;
+        if reader.remaining()!=0 {
+            return Err(format!("Encoded level has {} trailing bytes", reader.remaining()));
+        }
+
+        let mut particles = ParticleVec::with_capacity(particle_count);
+        for index in 0..particle_count {
+            particles.push(Particle {
+                _gpu_only_level_index_current: current[index] as u32,
+                _gpu_only_level_index_next: next[index] as u32,
+                material: palette[material_indices[index] as usize].clone(),
+                pos: Vector3U16 {
+                    x: x[index] as u16,
+                    y: y[index] as u16,
+                    z: z[index] as u16,
+                },
+            });
+        }
+        Ok(particles)
+    }
+}
+
+fn read_column(reader:&mut ByteReader, len:usize, max:u64) -> Result<Vec<u64>, String> {
+    let codec = ColumnCodec::from_tag(reader.read_u8()?)?;
+    let values = codec.decode(reader, len)?;
+    match values.iter().find(|value| **value>max) {
+        Some(value) => Err(format!("Column value {} is larger than the largest allowed, {}", value, max)),
+        None => Ok(values),
+    }
+}
+
+//more than an encoded level of particle_count Particles can take: six columns of at most two varints per value,
+//and a distinct composition of every material for each Particle
+fn max_body_len(particle_count:usize) -> usize {
+    let per_particle = 6*2*10+10+MATERIAL_COUNT as usize*3;
+    particle_count.saturating_mul(per_particle).saturating_add(64)
+}
\ No newline at end of file
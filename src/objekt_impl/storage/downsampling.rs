@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    math::{
+        octree_math::{
+            children,
+            index_in_level,
+            level_key,
+            level_key_origin,
+            level_origin,
+            to_lod,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        material_composition::MaterialComposition,
+        particle::{
+            one_hot_material,
+            MATERIAL_COUNT,
+        },
+    },
+};
+
+//how the materials of up to eight child voxels combine into their parent. Children in levels that are not loaded are left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DownsamplePolicy {
+    Majority,//the parent takes the most common dominant material at full weight, or stays empty if more children are empty than hold any one material
+    Average,//each material weight is the rounded mean over the children
+    MaxDensity,//each material weight is the largest among the children, so thin features stay visible from afar
+}
+
+impl DownsamplePolicy {
+    pub fn aggregate(&self, children:&[MaterialComposition]) -> MaterialComposition {
+        if children.is_empty() {
+            return MaterialComposition::Empty;
+        }
+        match self {
+            DownsamplePolicy::Majority => {
+                let mut votes = vec![0usize; MATERIAL_COUNT as usize];
+                let mut empty_votes = 0;
+                for child in children {
+                    match child.dominant(&[]) {
+                        Some(material) => votes[material as usize]+=1,
+                        None => empty_votes+=1,
+                    }
+                }
+                let mut winner = None;
+                let mut winner_votes = 0;
+                for (material, count) in votes.iter().enumerate() {
+                    if *count>winner_votes {
+                        winner = Some(material as u16);
+                        winner_votes = *count;
+                    }
+                }
+                match winner {
+                    Some(material) if winner_votes>=empty_votes => one_hot_material(material),
+                    _ => MaterialComposition::Empty,
+                }
+            }
+            DownsamplePolicy::Average => {
+                //only materials some child holds can end up with a weight
+                let count = children.len();
+                let mut sums = BTreeMap::new();
+                for child in children {
+                    for (material, weight) in child.iter() {
+                        *sums.entry(material).or_insert(0usize)+=weight as usize;
+                    }
+                }
+                MaterialComposition::from_entries(sums.into_iter().map(|(material, sum)| (material, ((sum+count/2)/count) as u8)))
+            }
+            DownsamplePolicy::MaxDensity => {
+                let mut maxima = BTreeMap::new();
+                for child in children {
+                    for (material, weight) in child.iter() {
+                        let maximum = maxima.entry(material).or_insert(0u8);
+                        *maximum = (*maximum).max(weight);
+                    }
+                }
+                MaterialComposition::from_entries(maxima)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DownsampleReport {
+    pub rebuilt_levels:Vec<VoxelLocation>,//origins of parent levels whose Particles changed, finest LOD first
+    pub loaded_levels:Vec<VoxelLocation>,//origins of levels that had to be loaded first
+}
+
+//Coarse LODs hold a summary of the LOD below them, which raycasts and distant rendering rely on.
+//Changing a level queues it in lod_dirty, and downsample_dirty then rebuilds the parent voxels it covers, finest LOD first,
+//so a change ripples all the way up. Parents that come out unchanged stop the ripple early.
+impl HybridOctree {
+    //marks a level as having unsaved changes and queues it for downsampling, returning whether it is loaded
+    pub fn mark_level_changed(&self, pos:&VoxelLocation) -> bool {
+        let sorted_level = match self.find_level(pos) {
+            Some(sorted_level) => sorted_level,
+            None => return false,
+        };
+        {
+            let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+            if !contents.loaded {
+                return false;
+            }
+            contents.mark_changed();
+        }
+        self.queue_downsample(pos)
+    }
+
+    //queues the level holding pos for downsample_dirty, returning false if pos is outside of the addressable range
+    pub fn queue_downsample(&self, pos:&VoxelLocation) -> bool {
+        match level_key(pos, self.level_length) {
+            Some(key) => {
+                self.lod_dirty.lock().expect("Could not lock downsampling queue").insert(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn downsample_dirty(&self, policy:DownsamplePolicy) -> Result<DownsampleReport, String> {
+        let mut report = DownsampleReport::default();
+        //finer LODs have larger keys, so taking the largest key first finishes each LOD before its parent LOD starts
+        loop {
+            let key = match self.lod_dirty.lock().expect("Could not lock downsampling queue").pop_last() {
+                Some(key) => key,
+                None => break,
+            };
+            let origin = match level_key_origin(key, self.level_length) {
+                Some(origin) if origin.lod+1<self.level_depth => origin,
+                _ => continue,
+            };
+            match self.downsample_level(&origin, policy, &mut report.loaded_levels) {
+                Ok(Some(parent)) => report.rebuilt_levels.push(parent),
+                Ok(None) => {}
+                Err(msg) => {
+                    //queued again, so the next attempt picks it back up
+                    self.queue_downsample(&origin);
+                    return Err(msg);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn loaded_weights(&self, location:&VoxelLocation) -> Option<MaterialComposition> {
+        let sorted_level = self.find_level(location)?;
+        let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+        if !contents.loaded {
+            return None;
+        }
+        contents.data.material.get(index_in_level(location, self.level_length)).cloned()
+    }
+
+    //rebuilds the parent voxels covering one level, returning the parent level's origin if any of them changed
+    fn downsample_level(&self, origin:&VoxelLocation, policy:DownsamplePolicy, loaded_levels:&mut Vec<VoxelLocation>) -> Result<Option<VoxelLocation>, String> {
+        let level_length = self.level_length;
+        let parent_origin = level_origin(&to_lod(origin, origin.lod+1), level_length);
+        //keep the child around while the parent loads, in case that pushes the octree over its memory budget
+        let (was_pinned, loaded_now) = self.with_level_mut(origin, |contents| {
+            let was_pinned = contents.pinned;
+            contents.pinned = true;
+            was_pinned
+        })?;
+        if loaded_now {
+            loaded_levels.push(origin.clone());
+        }
+        let parent_loaded = self.with_level_mut(&parent_origin, |_contents| {});
+        self.pin_level(origin, was_pinned);
+        if parent_loaded?.1 {
+            loaded_levels.push(parent_origin.clone());
+        }
+
+        //the parent voxels whose children lie in this level, which with an odd level_length also have children in neighbouring levels
+        let first = |start:u64| start/2;
+        let last = |start:u64| (start+level_length-1)/2;
+        let mut rebuilt = vec![];
+        for x in first(origin.vec.x)..=last(origin.vec.x) {
+            for y in first(origin.vec.y)..=last(origin.vec.y) {
+                for z in first(origin.vec.z)..=last(origin.vec.z) {
+                    let parent = VoxelLocation {
+                        lod:origin.lod+1,
+                        vec:Vector3U64 {
+                            x:x,
+                            y:y,
+                            z:z,
+                        },
+                    };
+                    let child_weights:Vec<MaterialComposition> = children(&parent)
+                        .expect("Parent voxels are never at LOD 0")
+                        .iter()
+                        .filter_map(|child| self.loaded_weights(child))
+                        .collect();
+                    rebuilt.push((index_in_level(&parent, level_length), policy.aggregate(&child_weights)));
+                }
+            }
+        }
+
+        let (changed, loaded_now) = self.with_level_mut(&parent_origin, |contents| {
+            let mut changed = false;
+            for (index, weights) in rebuilt {
+                if contents.data.material[index]!=weights {
+                    contents.data.material[index] = weights;
+                    changed = true;
+                }
+            }
+            if changed {
+                contents.mark_changed();
+            }
+            changed
+        })?;
+        if loaded_now {
+            loaded_levels.push(parent_origin.clone());
+        }
+        if !changed {
+            return Ok(None);
+        }
+        self.queue_downsample(&parent_origin);
+        Ok(Some(parent_origin))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use crate::{
+    math::{
+        octree_math::{
+            level_key,
+            level_origin,
+            LevelKey,
+        },
+        vectors::{
+            Vector3U16,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::{
+            HybridOctree,
+            Level,
+        },
+        particle::{
+            ParticleVec,
+            HALF_LIMIT,
+        },
+        sorted_level_list::SortedLevel,
+    },
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FsckOptions {
+    pub repair:bool,
+    pub check_storage:bool,//also reads back every stored level, see LevelStorage::corrupt_levels
+}
+
+impl FsckOptions {
+    pub fn new(repair:bool) -> Self {
+        Self {
+            repair:repair,
+            check_storage:true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FsckIssue {
+    //Stored under a key that does not match its location, which puts it out of order in the SortedLevelList and hides
+    //the level that belongs under that key. Also covers locations that are no level's origin or lie beyond the octree.
+    MisplacedLevel {
+        location:VoxelLocation,
+        key:LevelKey,
+        reason:String,
+    },
+    ColumnLengthMismatch {
+        level:VoxelLocation,
+        lengths:[usize;4],//level_index_current, level_index_next, material and pos
+        expected:usize,
+    },
+    UnloadedWithData {
+        level:VoxelLocation,
+    },
+    InvalidMaterial {
+        level:VoxelLocation,
+        index:usize,
+        reason:String,
+    },
+    CorruptStored {
+        level:VoxelLocation,
+    },
+    StorageUnreadable {
+        reason:String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FsckFinding {
+    pub issue:FsckIssue,
+    pub repaired:bool,
+    pub repair_error:Option<String>,//why the repair failed, if one was tried
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FsckReport {
+    pub checked_levels:usize,
+    pub findings:Vec<FsckFinding>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn unrepaired(&self) -> Vec<&FsckIssue> {
+        self.findings.iter().filter(|finding| !finding.repaired).map(|finding| &finding.issue).collect()
+    }
+
+    fn push(&mut self, issue:FsckIssue, repaired:bool) {
+        self.push_attempt(issue, Ok(repaired));
+    }
+
+    //Ok with whether the issue was repaired, or Err with why the repair failed
+    fn push_attempt(&mut self, issue:FsckIssue, attempt:Result<bool, String>) {
+        let (repaired, repair_error) = match attempt {
+            Ok(repaired) => (repaired, None),
+            Err(reason) => (false, Some(reason)),
+        };
+        self.findings.push(FsckFinding {
+            issue:issue,
+            repaired:repaired,
+            repair_error:repair_error,
+        });
+    }
+}
+
+//pads with Particles that hold no material, centered in their voxels, see particle::empty_particle_vec
+fn resize_columns(data:&mut ParticleVec, len:usize) {
+    data._gpu_only_level_index_current.resize(len, 0);
+    data._gpu_only_level_index_next.resize(len, 0);
+    data.material.resize(len, Default::default());
+    data.pos.resize(len, Vector3U16 {
+        x:HALF_LIMIT,
+        y:HALF_LIMIT,
+        z:HALF_LIMIT,
+    });
+}
+
+//Checks that the loaded levels, and optionally the stored ones, are what the rest of the octree assumes they are.
+//Particle positions need no check of their own: they are u16 offsets within their voxel, so they can not leave it, and the
+//voxels themselves stay within bounds as long as their level is where its key says it is.
+//Nor do unloaded parent LODs: a parent may have been evicted, and a change that has to reach it stays in lod_dirty
+//until downsample_dirty loads the parent and rebuilds it.
+impl HybridOctree {
+    pub fn fsck(&self, options:&FsckOptions) -> FsckReport {
+        let mut report = FsckReport::default();
+        let levels = self.levels.levels();
+        report.checked_levels = levels.len();
+        let expected = self.level_length.pow(3) as usize;
+        let mut misplaced = vec![];
+        for sorted_level in &levels {
+            let location = &sorted_level.location;
+            let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+            if !contents.loaded {
+                if !contents.data.is_empty() || contents.dirty {
+                    if options.repair {
+                        contents.data = ParticleVec::new();
+                        contents.dirty = false;
+                    }
+                    report.push(FsckIssue::UnloadedWithData {
+                        level:location.clone(),
+                    }, options.repair);
+                }
+                continue;
+            }
+
+            let lengths = [contents.data._gpu_only_level_index_current.len(), contents.data._gpu_only_level_index_next.len(),
+                contents.data.material.len(), contents.data.pos.len()];
+            if lengths.iter().any(|len| *len!=expected) {
+                if options.repair {
+                    resize_columns(&mut contents.data, expected);
+                    contents.mark_changed();
+                }
+                report.push(FsckIssue::ColumnLengthMismatch {
+                    level:location.clone(),
+                    lengths:lengths,
+                    expected:expected,
+                }, options.repair);
+            }
+
+            let mut materials_changed = false;
+            for index in 0..contents.data.material.len() {
+                if let Err(reason) = contents.data.material[index].validate() {
+                    if options.repair {
+                        contents.data.material[index] = contents.data.material[index].normalized();
+                        materials_changed = true;
+                    }
+                    report.push(FsckIssue::InvalidMaterial {
+                        level:location.clone(),
+                        index:index,
+                        reason:reason,
+                    }, options.repair);
+                }
+            }
+            if materials_changed {
+                contents.mark_changed();
+            }
+
+            if let Some((reason, correct_key)) = self.misplaced_reason(sorted_level) {
+                misplaced.push((sorted_level.clone(), reason, correct_key));
+            }
+        }
+
+        for (sorted_level, reason, correct_key) in misplaced {
+            let repaired = options.repair && correct_key.is_some_and(|key| self.move_level(&sorted_level, key));
+            report.push(FsckIssue::MisplacedLevel {
+                location:sorted_level.location.clone(),
+                key:sorted_level.ordinal,
+                reason:reason,
+            }, repaired);
+        }
+
+        if options.check_storage {
+            self.fsck_storage(options, &mut report);
+        }
+        report
+    }
+
+    //why the level is not where its key says, along with the key it belongs under if it belongs anywhere
+    fn misplaced_reason(&self, sorted_level:&SortedLevel) -> Option<(String, Option<LevelKey>)> {
+        let location = &sorted_level.location;
+        if location.lod>=self.level_depth {
+            return Some((format!("LOD {} is beyond the {} LODs of the octree", location.lod, self.level_depth), None));
+        }
+        if level_origin(location, self.level_length)!=*location {
+            return Some((String::from("the location is not the origin of a level"), None));
+        }
+        match level_key(location, self.level_length) {
+            None => Some((String::from("the location is outside of the addressable range"), None)),
+            Some(key) if key!=sorted_level.ordinal => Some((format!("the level belongs under key {}", key), Some(key))),
+            Some(_) => None,
+        }
+    }
+
+    //Moves a misplaced level's Particles under key, as long as no loaded level is there already. The misplaced entry is
+    //left unloaded, so whichever level its own key belongs to is looked up again the next time it is needed.
+    fn move_level(&self, sorted_level:&Arc<SortedLevel>, key:LevelKey) -> bool {
+        let existing = self.levels.get(key);
+        if let Some(existing) = &existing {
+            if existing.level.contents.read().expect("Could not lock Level for read access").loaded {
+                return false;
+            }
+        }
+        let (data, pinned) = {
+            let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+            if !contents.loaded {
+                return false;
+            }
+            let taken = (std::mem::replace(&mut contents.data, ParticleVec::new()), contents.pinned);
+            contents.loaded = false;
+            contents.dirty = false;
+            contents.pinned = false;
+            taken
+        };
+        let level = Level::new(data);
+        {
+            let mut contents = level.contents.write().expect("Could not lock Level for write access");
+            contents.pinned = pinned;
+            contents.mark_changed();
+        }
+        //if another thread loaded the level in the meantime, its copy wins as it does in load_level
+        let moved = self.levels.try_insert(SortedLevel {
+            ordinal:key,
+            location:sorted_level.location.clone(),
+            level:level,
+        }, existing.as_ref()).is_ok();
+        if moved {
+            self.queue_downsample(&sorted_level.location);
+        }
+        moved
+    }
+
+    fn fsck_storage(&self, options:&FsckOptions, report:&mut FsckReport) {
+        let corrupt = match self.with_storage(|storage| storage.corrupt_levels()) {
+            Some(Ok(corrupt)) => corrupt,
+            Some(Err(reason)) => {
+                report.push(FsckIssue::StorageUnreadable {
+                    reason:reason,
+                }, false);
+                return;
+            }
+            None => return,
+        };
+        for level in corrupt {
+            //a loaded copy is written over the corrupt one, anything else is generated again when next loaded
+            let attempt = if options.repair {
+                match self.save_level(&level) {
+                    Ok(true) => Ok(true),
+                    Ok(false) => match self.with_storage(|storage| storage.remove_level(&level)) {
+                        Some(Ok(_removed)) => Ok(true),
+                        Some(Err(msg)) => Err(format!("Could not remove level at {:?} from storage: {}", level, msg)),
+                        None => Ok(false),
+                    },
+                    Err(msg) => Err(format!("Could not rewrite level at {:?}: {}", level, msg)),
+                }
+            } else {
+                Ok(false)
+            };
+            report.push_attempt(FsckIssue::CorruptStored {
+                level:level,
+            }, attempt);
+        }
+    }
+}
\ No newline at end of file
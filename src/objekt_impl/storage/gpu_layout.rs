@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+};
+
+use crate::{
+    math::{
+        octree_math::{
+            level_origin,
+            location_in_level,
+            to_lod,
+        },
+        vectors::{
+            Vector3U16,
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        material_composition::MaterialComposition,
+        particle::{
+            Particle,
+            ParticleVec,
+        },
+    },
+    utils::binary::write_u32,
+};
+
+//Levels laid out as three std430 storage buffers, declared in GLSL as
+//
+//  struct Level {
+//      uvec4 origin_low;//low 32 bits of x, y and z, then the LOD
+//      uvec4 origin_high;//high 32 bits of x, y and z, then 0
+//      uint first_particle;
+//      uint particle_count;
+//      uint parent;//the level one LOD up containing this one, or NO_LEVEL
+//      uint padding;
+//  };
+//  struct Particle {
+//      uint level_index_current;//the level holding this Particle
+//      uint level_index_next;//the level one LOD down within this Particle's voxel, or NO_LEVEL
+//      uint first_material;
+//      uint material_count;
+//      uint pos_xy;//x in the low 16 bits, y in the high 16 bits
+//      uint pos_z;
+//  };
+//  layout(std430) buffer Levels { Level levels[]; };
+//  layout(std430) buffer Particles { Particle particles[]; };
+//  layout(std430) buffer Materials { uint materials[]; };//material index in the low 16 bits, weight in bits 16 to 23
+//
+//Particles of a level are stored in octree_math::index_in_level order, and the materials of a Particle are its
+//MaterialComposition entries lowest index first, so empty Particles take no material data at all.
+pub const GPU_LEVEL_SIZE:usize = 48;
+pub const GPU_PARTICLE_SIZE:usize = 24;
+pub const GPU_MATERIAL_SIZE:usize = 4;
+pub const NO_LEVEL:u32 = u32::MAX;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackedLevels {
+    pub origins:Vec<VoxelLocation>,//one per entry of the level table, in the same order
+    pub level_table:Vec<u8>,
+    pub particles:Vec<u8>,
+    pub materials:Vec<u8>,
+}
+
+impl PackedLevels {
+    pub fn level_count(&self) -> usize {
+        self.level_table.len()/GPU_LEVEL_SIZE
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()/GPU_PARTICLE_SIZE
+    }
+
+    pub fn material_count(&self) -> usize {
+        self.materials.len()/GPU_MATERIAL_SIZE
+    }
+}
+
+//Builds PackedLevels one level at a time, so levels only need to stay locked while they are copied.
+//The level indices of the table and the Particles are filled in by finish, once every level is known.
+pub struct LevelPacker {
+    level_length:u64,
+    packed:PackedLevels,
+    indices:HashMap<VoxelLocation, u32>,
+}
+
+impl LevelPacker {
+    pub fn new(level_length:u64) -> Self {
+        Self {
+            level_length:level_length,
+            packed:PackedLevels::default(),
+            indices:HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, origin:&VoxelLocation, particles:&ParticleVec) -> Result<u32, String> {
+        if level_origin(origin, self.level_length)!=*origin {
+            return Err(format!("{:?} is not the origin of a level", origin));
+        }
+        if particles.len() as u64!=self.level_length.pow(3) {
+            return Err(format!("Level at {:?} has {} Particles, expected {}", origin, particles.len(), self.level_length.pow(3)));
+        }
+        if self.indices.contains_key(origin) {
+            return Err(format!("Level at {:?} is packed twice", origin));
+        }
+        let index:u32 = self.packed.origins.len().try_into().ok().filter(|index| *index!=NO_LEVEL)
+            .ok_or_else(|| String::from("Too many levels to index with 32 bits"))?;
+        let first_particle = u32_offset(self.packed.particle_count(), particles.len(), "Particles")?;
+        let material_entries = particles.material.iter().map(|material| material.material_count()).sum();
+        u32_offset(self.packed.material_count(), material_entries, "material entries")?;
+        let lod:u32 = origin.lod.try_into().map_err(|_| format!("LOD of {:?} does not fit 32 bits", origin))?;
+
+        let table = &mut self.packed.level_table;
+        for value in [origin.vec.x, origin.vec.y, origin.vec.z] {
+            write_u32(table, value as u32);
+        }
+        write_u32(table, lod);
+        for value in [origin.vec.x, origin.vec.y, origin.vec.z] {
+            write_u32(table, (value>>32) as u32);
+        }
+        write_u32(table, 0);
+        write_u32(table, first_particle);
+        write_u32(table, particles.len() as u32);
+        write_u32(table, NO_LEVEL);//parent, see finish
+        write_u32(table, 0);
+
+        for (material, pos) in particles.material.iter().zip(&particles.pos) {
+            let first_material = self.packed.material_count() as u32;
+            let out = &mut self.packed.particles;
+            write_u32(out, index);
+            write_u32(out, NO_LEVEL);//level_index_next, see finish
+            write_u32(out, first_material);
+            write_u32(out, material.material_count() as u32);
+            write_u32(out, pos.x as u32 | (pos.y as u32)<<16);
+            write_u32(out, pos.z as u32);
+            for (material, weight) in material.iter() {
+                write_u32(&mut self.packed.materials, material as u32 | (weight as u32)<<16);
+            }
+        }
+        self.packed.origins.push(origin.clone());
+        self.indices.insert(origin.clone(), index);
+        Ok(index)
+    }
+
+    //links levels to their parents and Particles to the levels within their voxels, among the levels pushed
+    pub fn finish(mut self) -> PackedLevels {
+        let level_length = self.level_length;
+        let particles_per_level = level_length.pow(3) as usize;
+        for (level, origin) in self.packed.origins.iter().enumerate() {
+            let parent = level_origin(&to_lod(origin, origin.lod+1), level_length);
+            let parent_index = self.indices.get(&parent).cloned().unwrap_or(NO_LEVEL);
+            let entry = level*GPU_LEVEL_SIZE;
+            self.packed.level_table[entry+40..entry+44].copy_from_slice(&parent_index.to_le_bytes());
+            if origin.lod==0 {
+                continue;
+            }
+            let first_particle = read_u32(&self.packed.level_table, entry+32) as usize;
+            for index in 0..particles_per_level {
+                //the child on the lowest corner, which shares its level with the other children unless level_length is odd
+                let location = location_in_level(origin, index, level_length);
+                let child_level = level_origin(&to_lod(&location, origin.lod-1), level_length);
+                if let Some(child_index) = self.indices.get(&child_level) {
+                    let offset = (first_particle+index)*GPU_PARTICLE_SIZE+4;
+                    self.packed.particles[offset..offset+4].copy_from_slice(&child_index.to_le_bytes());
+                }
+            }
+        }
+        self.packed
+    }
+}
+
+fn u32_offset(start:usize, len:usize, what:&str) -> Result<u32, String> {
+    start.checked_add(len).filter(|end| *end<=u32::MAX as usize)
+        .map(|_| start as u32)
+        .ok_or_else(|| format!("Too many {} to index with 32 bits", what))
+}
+
+fn read_u32(data:&[u8], offset:usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset+4].try_into().expect("Slice is four bytes long"))
+}
+
+//Reads PackedLevels back into Particles, checking every offset and count along the way. Meant for verifying
+//the layout, so it rejects anything pack would not have written.
+pub fn unpack_levels(packed:&PackedLevels, level_length:u64) -> Result<Vec<(VoxelLocation, ParticleVec)>, String> {
+    if !packed.level_table.len().is_multiple_of(GPU_LEVEL_SIZE) || !packed.particles.len().is_multiple_of(GPU_PARTICLE_SIZE) || !packed.materials.len().is_multiple_of(GPU_MATERIAL_SIZE) {
+        return Err(String::from("Packed buffers do not hold whole entries"));
+    }
+    if packed.origins.len()!=packed.level_count() {
+        return Err(format!("{} origins for {} packed levels", packed.origins.len(), packed.level_count()));
+    }
+    let mut levels = vec![];
+    for (level, origin) in packed.origins.iter().enumerate() {
+        let entry = &packed.level_table[level*GPU_LEVEL_SIZE..(level+1)*GPU_LEVEL_SIZE];
+        let word = |index:usize| read_u32(entry, index*4);
+        let packed_origin = VoxelLocation {
+            lod:word(3) as u64,
+            vec:Vector3U64 {
+                x:word(0) as u64 | (word(4) as u64)<<32,
+                y:word(1) as u64 | (word(5) as u64)<<32,
+                z:word(2) as u64 | (word(6) as u64)<<32,
+            },
+        };
+        if packed_origin!=*origin {
+            return Err(format!("Level {} is packed at {:?}, but listed at {:?}", level, packed_origin, origin));
+        }
+        let (first_particle, particle_count, parent) = (word(8) as usize, word(9) as usize, word(10));
+        if particle_count as u64!=level_length.pow(3) {
+            return Err(format!("Level {} holds {} Particles, expected {}", level, particle_count, level_length.pow(3)));
+        }
+        if parent!=NO_LEVEL && parent as usize>=packed.level_count() {
+            return Err(format!("Level {} has parent {} beyond the level table", level, parent));
+        }
+        if first_particle+particle_count>packed.particle_count() {
+            return Err(format!("Level {} reaches beyond the {} packed Particles", level, packed.particle_count()));
+        }
+        let mut particles = ParticleVec::with_capacity(particle_count);
+        for index in first_particle..first_particle+particle_count {
+            let data = &packed.particles[index*GPU_PARTICLE_SIZE..(index+1)*GPU_PARTICLE_SIZE];
+            let word = |index:usize| read_u32(data, index*4);
+            if word(0)!=level as u32 {
+                return Err(format!("Particle {} of level {} names level {} as its own", index, level, word(0)));
+            }
+            if word(1)!=NO_LEVEL && word(1) as usize>=packed.level_count() {
+                return Err(format!("Particle {} names level {} beyond the level table", index, word(1)));
+            }
+            let (first_material, material_count) = (word(2) as usize, word(3) as usize);
+            if first_material+material_count>packed.material_count() {
+                return Err(format!("Particle {} reaches beyond the {} packed material entries", index, packed.material_count()));
+            }
+            let mut entries = vec![];
+            for material in first_material..first_material+material_count {
+                let entry = read_u32(&packed.materials, material*GPU_MATERIAL_SIZE);
+                if entry>>24!=0 || entry>>16==0 {
+                    return Err(format!("Material entry {} holds {:#x}, which is not a material with a weight", material, entry));
+                }
+                if entries.last().is_some_and(|(last, _)| *last>=entry as u16) {
+                    return Err(format!("Material entries of Particle {} are not in ascending order", index));
+                }
+                entries.push((entry as u16, (entry>>16) as u8));
+            }
+            if word(5)>>16!=0 {
+                return Err(format!("Particle {} has a z position of {:#x}, beyond 16 bits", index, word(5)));
+            }
+            particles.push(Particle {
+                _gpu_only_level_index_current:word(0),
+                _gpu_only_level_index_next:word(1),
+                material:MaterialComposition::from_entries(entries),
+                pos:Vector3U16 {
+                    x:word(4) as u16,
+                    y:(word(4)>>16) as u16,
+                    z:word(5) as u16,
+                },
+            });
+        }
+        levels.push((origin.clone(), particles));
+    }
+    Ok(levels)
+}
+
+impl HybridOctree {
+    //Packs the levels in the order given, loading them as needed.
+    pub fn pack_levels(&self, origins:&[VoxelLocation]) -> Result<PackedLevels, String> {
+        let mut packer = LevelPacker::new(self.level_length);
+        for origin in origins {
+            let origin = level_origin(origin, self.level_length);
+            self.with_level(&origin, |contents| packer.push(&origin, &contents.data))?.0?;
+        }
+        Ok(packer.finish())
+    }
+}
\ No newline at end of file
@@ -1,18 +1,31 @@
 use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{
-        Hash,
-        Hasher,
-    },
+    collections::BTreeSet,
     sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
         Arc,
+        Mutex,
         RwLock,
     },
 };
 
 use crate::{
-    concurrency::molecule_objekt::MoleculeObjekt,
+    concurrency::{
+        molecule_objekt::MoleculeObjekt,
+        objekt_snapshot::SerializableObjekt,
+    },
     math::{
+        octree_math::{
+            index_in_level,
+            level_key,
+            level_origin,
+            max_level_depth,
+            to_lod,
+            LevelKey,
+            VoxelRange,
+        },
         vectors::{
             Vector3U16,
             VoxelLocation,
@@ -20,33 +33,137 @@ use crate::{
     },
     objekt_impl::{
         storage::{
+            level_generator::{
+                EmptyGenerator,
+                LevelGenerator,
+            },
+            level_storage::LevelStorage,
+            material_composition::MaterialComposition,
             sorted_level_list::{
                 SortedLevel,
                 SortedLevelList,
             },
             particle::{
-                MATERIAL_COUNT,
                 ParticleVec,
+                MaterialStorageReport,
+                check_particle_vec_len,
+                empty_particle_vec,
+                material_storage_report,
+                particle_vec_memory_usage,
+                read_particle_vec,
+                write_particle_vec,
             },
         },
     },
+    utils::binary::{
+        ByteReader,
+        write_u64,
+    },
 };
 
+static REVISION_CLOCK:AtomicU64 = AtomicU64::new(1);
+
+//One counter hands out the revisions of every level in every octree, so a level key and revision still tell two copies of
+//a level apart after it was unloaded and loaded again. Derived data such as meshes uses that to tell when it went stale.
+pub fn next_revision() -> u64 {
+    REVISION_CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug)]
 pub struct LevelContents {
-    pub loaded: bool,
+    pub loaded: bool,//false once the level has been unloaded, after which data is empty and the level must be looked up again
+    pub dirty: bool,//changed since it was generated or last written to storage
+    pub pinned: bool,//never evicted to meet the memory budget
+    pub revision: u64,//changes whenever the data does, and is never reused, see next_revision
     pub data: ParticleVec,
 }
 
+impl LevelContents {
+    //call after changing data
+    pub fn mark_changed(&mut self) {
+        self.dirty = true;
+        self.revision = next_revision();
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        particle_vec_memory_usage(&self.data)
+    }
+
+    pub fn material_storage(&self) -> MaterialStorageReport {
+        material_storage_report(&self.data)
+    }
+}
+
 #[derive(Debug)]
 pub struct Level {
-    pub contents:RwLock<LevelContents>
+    pub contents:RwLock<LevelContents>,
+    pub last_access:AtomicU64,//value of the octree's access clock when this level was last looked up
 }
 
+impl Level {
+    pub fn new(data:ParticleVec) -> Self {
+        Self {
+            contents:RwLock::new(LevelContents {
+                loaded:true,
+                dirty:false,
+                pinned:false,
+                revision:next_revision(),
+                data:data,
+            }),
+            last_access:AtomicU64::new(0),
+        }
+    }
+
+    //holds the place of a level while it is being loaded, see SortedLevelList
+    pub fn unloaded() -> Self {
+        let level = Self::new(ParticleVec::new());
+        level.contents.write().expect("Could not lock Level for write access").loaded = false;
+        level
+    }
+}
+
+//a copy of the Particle covering a queried location, along with where it lives
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleRef {
+    pub location:VoxelLocation,//the voxel holding the Particle, at the finest LOD that was loaded
+    pub level:VoxelLocation,//origin of the level holding the Particle
+    pub index:usize,//index of the Particle within its level
+    pub material:MaterialComposition,
+    pub pos:Vector3U16,
+}
+
+//The octree does its own locking, per level, so it is shared directly instead of sitting behind one lock:
+//generation and IO threads can load levels while other tasks read and edit the ones already loaded.
+//A snapshot holds the loaded levels but not the generator, which is code rather than data, so a restored octree generates
+//nothing until set_generator is called. Levels that were unloaded when the snapshot was taken come back empty until then.
 #[derive(Clone)]
 pub struct HybridOctreeObjekt {
     name: String,
-    inner: Arc<RwLock<HybridOctree>>,
+    inner: Arc<HybridOctree>,
+}
+
+impl HybridOctreeObjekt {
+    pub fn new(name:String, octree:HybridOctree) -> Self {
+        Self {
+            name:name,
+            inner:Arc::new(octree),
+        }
+    }
+
+    pub fn inner(&self) -> Arc<HybridOctree> {
+        self.inner.clone()
+    }
+
+    //only works while nothing else holds the octree, such as right after a snapshot is read
+    pub fn set_generator(&mut self, generator:Box<dyn LevelGenerator>) -> Result<(), String> {
+        match Arc::get_mut(&mut self.inner) {
+            Some(octree) => {
+                octree.generator = generator;
+                Ok(())
+            }
+            None => Err(format!("Octree {} is shared, so its generator can not be replaced", self.name)),
+        }
+    }
 }
 
 impl MoleculeObjekt for HybridOctreeObjekt {
@@ -55,84 +172,448 @@ impl MoleculeObjekt for HybridOctreeObjekt {
     }
 }
 
+impl SerializableObjekt for HybridOctreeObjekt {
+    fn type_tag() -> &'static str {
+        "molecule_engine::HybridOctree"
+    }
+
+    fn serialize(&self, out:&mut Vec<u8>) -> Result<(), String> {
+        let octree = &self.inner;
+        write_u64(out, octree.level_depth);
+        write_u64(out, octree.level_length);
+        let levels = octree.levels.levels();
+        let loaded:Vec<_> = levels.iter()
+            .map(|sorted_level| (sorted_level, sorted_level.level.contents.read().expect("Could not lock Level for read access")))
+            .filter(|(_sorted_level, contents)| contents.loaded)
+            .collect();
+        //only loaded levels are written, unloaded ones hold no Particles
+        write_u64(out, loaded.len() as u64);
+        for (sorted_level, contents) in &loaded {
+            sorted_level.location.write(out);
+            write_particle_vec(&contents.data, out);
+        }
+        Ok(())
+    }
+
+    fn deserialize(name:String, reader:&mut ByteReader) -> Result<Self, String> {
+        let level_depth = reader.read_u64()?;
+        let level_length = reader.read_u64()?;
+        if level_length<=1 {
+            return Err(format!("Level length {} is too small", level_length));
+        }
+        if level_depth>max_level_depth(level_length) {
+            return Err(format!("Level depth {} exceeds {} for levels of length {}", level_depth, max_level_depth(level_length), level_length));
+        }
+        //generators are not part of a snapshot, see HybridOctreeObjekt
+        let octree = HybridOctree::new(level_depth, level_length, Box::new(EmptyGenerator));
+        let level_count = reader.read_u64()?;
+        for _i in 0..level_count {
+            let location = VoxelLocation::read(reader)?;
+            let data = read_particle_vec(reader)?;
+            if location.lod>=level_depth || level_origin(&location, level_length)!=location {
+                return Err(format!("Level at {:?} is not a valid level origin", location));
+            }
+            let particle_count = level_length.checked_pow(3).filter(|count| *count<=usize::MAX as u64)
+                .ok_or_else(|| format!("Level length {} is too large", level_length))?;
+            check_particle_vec_len(&data, particle_count as usize).map_err(|msg| format!("Level at {:?} is corrupt: {}", location, msg))?;
+            let ordinal = match level_key(&location, level_length) {
+                Some(ordinal) => ordinal,
+                None => return Err(format!("Level at {:?} is outside of the addressable range", location)),
+            };
+            if octree.levels.get(ordinal).is_some() {
+                return Err(format!("Level at {:?} is stored more than once", location));
+            }
+            octree.levels.insert(
+                SortedLevel {
+                    ordinal: ordinal,
+                    location: location,
+                    level: Level::new(data),
+                },
+                None,
+            );
+        }
+        Ok(Self::new(name, octree))
+    }
+}
+
+//Every method takes &self, so the octree can be shared between threads. Each level has its own lock, the storage sits
+//behind a RwLock so levels can be read from it in parallel, and the memory budget and downsampling queue each sit behind
+//a Mutex. Locks are always taken in the order level, then storage, and levels are only ever locked one at a time or in
+//level key order.
 pub struct HybridOctree {
     pub level_depth:u64,//number of levels
     pub level_length:u64,
     pub levels:SortedLevelList,
+    pub generator:Box<dyn LevelGenerator>,//fills in levels that are not in storage
+    memory_budget:Mutex<Option<usize>>,//bytes of Particle data to keep loaded before evicting levels, None for no limit
+    storage:RwLock<Option<Box<dyn LevelStorage>>>,//where evicted levels with unsaved changes are written
+    pub lod_dirty:Mutex<BTreeSet<LevelKey>>,//levels changed since their parent LOD was last rebuilt from them, see downsampling
+    access_clock:AtomicU64,
 }
 
 impl HybridOctree {
-    pub fn new(level_depth:u64, level_length:u64) -> Self {
+    pub fn new(level_depth:u64, level_length:u64, generator:Box<dyn LevelGenerator>) -> Self {
         if level_length<=1 {
             panic!("Level sizes must exceed 1 on each axis");
         }
+        if level_depth>max_level_depth(level_length) {
+            panic!("Level depth may not exceed {} for levels of length {}", max_level_depth(level_length), level_length);
+        }
 
         HybridOctree {
             level_depth:level_depth,
             level_length:level_length,
             levels:SortedLevelList::new(),
+            generator:generator,
+            memory_budget:Mutex::new(None),
+            storage:RwLock::new(None),
+            lod_dirty:Mutex::new(BTreeSet::new()),
+            access_clock:AtomicU64::new(0),
         }
     }
 
-    pub fn get_level(&self, pos:VoxelLocation) -> &Level {
-                let index = self.levels.get(pos.linearize(self.level_length, self.level_length, self.level_length, self.level_depth));
-                if !index.0 {
-                    panic!("This Level is not loaded")
+    fn touch(&self, level:&Level) {
+        level.last_access.store(self.access_clock.fetch_add(1, Ordering::Relaxed)+1, Ordering::Relaxed);
+    }
+
+    pub fn set_storage(&self, storage:Option<Box<dyn LevelStorage>>) {
+        *self.storage.write().expect("Could not lock LevelStorage for write access") = storage;
+    }
+
+    pub fn has_storage(&self) -> bool {
+        self.storage.read().expect("Could not lock LevelStorage for read access").is_some()
+    }
+
+    //runs f on the storage, returning None if there is none. f must not lock any level, see the lock order above.
+    pub fn with_storage<R, F: FnOnce(&mut dyn LevelStorage) -> R>(&self, f:F) -> Option<R> {
+        self.storage.write().expect("Could not lock LevelStorage for write access").as_mut().map(|storage| f(storage.as_mut()))
+    }
+
+    //any voxel within a level identifies it. The level may be unloaded by the time it is locked, see LevelContents::loaded.
+    pub fn find_level(&self, pos:&VoxelLocation) -> Option<Arc<SortedLevel>> {
+        let sorted_level = self.levels.get(level_key(pos, self.level_length)?)?;
+        self.touch(&sorted_level.level);
+        Some(sorted_level)
+    }
+
+    //like with_level_mut, but only takes the level's read lock, so readers and streaming are not blocked while f runs
+    pub fn with_level<R, F: FnOnce(&LevelContents) -> R>(&self, pos:&VoxelLocation, f:F) -> Result<(R, bool), String> {
+        let mut loaded_now = false;
+        loop {
+            if let Some(sorted_level) = self.find_level(pos) {
+                let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+                if contents.loaded {
+                    return Ok((f(&contents), loaded_now));
+                }
+            }
+            self.load_level(pos.clone())?;
+            loaded_now = true;
+        }
+    }
+
+    //runs f on the contents of the level holding pos, loading the level first if it is missing.
+    //Returns what f returned and whether the level had to be loaded.
+    pub fn with_level_mut<R, F: FnOnce(&mut LevelContents) -> R>(&self, pos:&VoxelLocation, f:F) -> Result<(R, bool), String> {
+        let mut loaded_now = false;
+        loop {
+            if let Some(sorted_level) = self.find_level(pos) {
+                let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+                if contents.loaded {
+                    return Ok((f(&mut contents), loaded_now));
                 }
-                &self.levels.data[index.1].level
-    }
-
-    pub fn load_level(&mut self, pos:VoxelLocation, mut insecure_hasher:DefaultHasher) -> Option<DefaultHasher> {
-        let linearized = pos.linearize(self.level_length, self.level_length, self.level_length, self.level_depth);
-        let index = self.levels.get(linearized).1;
-        match self.levels.data.get_mut(index) {
-            None => {
-                println!("Level that needs creation requested at {:?}", pos);
-                self.levels.data.insert(
-                    index,
-                    SortedLevel {
-                        ordinal: linearized,
-                        location: pos,
-                        level: Level {
-                            contents:RwLock::new(LevelContents {
-                                loaded:true,
-                                data: {
-
-                                    let volume = self.level_length.pow(3);
-                                    let mut buffer = ParticleVec::new();
-                                    //TODO: read from file
-                                    let half_limit = 1<<15;
-                                    for i in 0..volume {
-                                        buffer.material.push(
-                                            (0..MATERIAL_COUNT).map(
-                                                |m| {
-                                                    ((linearized*volume+(i*MATERIAL_COUNT+m)%256) as u8).hash(&mut insecure_hasher);
-                                                    (insecure_hasher.finish()%256) as u8
-                                                }
-                                            ).collect()
-                                        );
-                                    }
-                                    for _i in 0..volume {
-                                        buffer.pos.push(
-                                            Vector3U16 {
-                                                x: half_limit,
-                                                y: half_limit,
-                                                z: half_limit,
-                                            }
-                                        )
-                                    }
-                                    buffer
-                                },
-                            }),
-                        }
+            }
+            //missing, or evicted between the lookup and the lock
+            self.load_level(pos.clone())?;
+            loaded_now = true;
+        }
+    }
+
+    //Runs f on every voxel of range, along with its index in its level and that level's contents. Goes through one level
+    //at a time under its read lock, loading the levels that are missing.
+    pub fn visit_range<F: FnMut(&VoxelLocation, usize, &LevelContents)>(&self, range:&VoxelRange, mut f:F) -> Result<(), String> {
+        let level_length = self.level_length;
+        for origin in range.level_origins(level_length) {
+            let level_range = match range.within_level(&origin, level_length) {
+                Some(level_range) => level_range,
+                None => continue,
+            };
+            self.with_level(&origin, |contents| {
+                for location in level_range.voxels() {
+                    let index = index_in_level(&location, level_length);
+                    f(&location, index, contents);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn pin_level(&self, pos:&VoxelLocation, pinned:bool) -> bool {
+        match self.find_level(pos) {
+            Some(sorted_level) => {
+                sorted_level.level.contents.write().expect("Could not lock Level for write access").pinned = pinned;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn level_memory_usage(&self, pos:&VoxelLocation) -> Option<usize> {
+        self.find_level(pos).map(|sorted_level| sorted_level.level.contents.read().expect("Could not lock Level for read access").memory_usage())
+    }
+
+    //how the level's materials are kept in memory, see MaterialComposition
+    pub fn level_material_storage(&self, pos:&VoxelLocation) -> Option<MaterialStorageReport> {
+        self.find_level(pos).map(|sorted_level| sorted_level.level.contents.read().expect("Could not lock Level for read access").material_storage())
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.levels.levels().iter().map(|sorted_level| sorted_level.level.contents.read().expect("Could not lock Level for read access").memory_usage()).sum()
+    }
+
+    //frees a level's Particles, writing them to storage first if they have unsaved changes. Returns whether the level was loaded.
+    pub fn unload_level(&self, pos:VoxelLocation) -> Result<bool, String> {
+        let sorted_level = match level_key(&pos, self.level_length).and_then(|key| self.levels.get(key)) {
+            Some(sorted_level) => sorted_level,
+            None => return Ok(false),
+        };
+        let was_loaded = {
+            let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+            if contents.loaded {
+                if contents.pinned {
+                    return Err(format!("Level at {:?} is pinned", sorted_level.location));
+                }
+                if contents.dirty {
+                    match &mut *self.storage.write().expect("Could not lock LevelStorage for write access") {
+                        Some(storage) => storage.write_level(&sorted_level.location, &contents.data)?,
+                        None => return Err(format!("Level at {:?} has unsaved changes and there is no LevelStorage to write them to", sorted_level.location)),
                     }
-                );
+                }
+                contents.loaded = false;
+                contents.dirty = false;
+                contents.data = ParticleVec::new();
+                true
+            } else {
+                false
+            }
+        };
+        //the entry stays in the map while anyone else holds on to it, see SortedLevelList
+        let key = sorted_level.ordinal;
+        drop(sorted_level);
+        self.levels.remove_unused(key);
+        Ok(was_loaded)
+    }
+
+    //writes a level to storage whether or not it has changed, returning whether the level was loaded
+    pub fn save_level(&self, pos:&VoxelLocation) -> Result<bool, String> {
+        if !self.has_storage() {
+            return Err(String::from("There is no LevelStorage to save levels to"));
+        }
+        let sorted_level = match level_key(pos, self.level_length).and_then(|key| self.levels.get(key)) {
+            Some(sorted_level) => sorted_level,
+            None => return Ok(false),
+        };
+        let mut contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+        if !contents.loaded {
+            return Ok(false);
+        }
+        match &mut *self.storage.write().expect("Could not lock LevelStorage for write access") {
+            Some(storage) => storage.write_level(&sorted_level.location, &contents.data)?,
+            None => return Err(String::from("There is no LevelStorage to save levels to")),
+        }
+        contents.dirty = false;
+        Ok(true)
+    }
 
+    //writes every level with unsaved changes to storage, returning how many were written
+    pub fn save_dirty_levels(&self) -> Result<usize, String> {
+        if !self.has_storage() {
+            return Err(String::from("There is no LevelStorage to save levels to"));
+        }
+        let levels = self.levels.levels();
+        let mut dirty = vec![];
+        for sorted_level in &levels {
+            let contents = sorted_level.level.contents.write().expect("Could not lock Level for write access");
+            if contents.loaded && contents.dirty {
+                dirty.push((sorted_level.location.clone(), contents));
             }
-            Some(level) => {
-                (*level.level.contents.write().expect("Could not lock Level for write access")).loaded=true;
+        }
+        let to_write:Vec<(VoxelLocation, &ParticleVec)> = dirty.iter().map(|(location, contents)| (location.clone(), &contents.data)).collect();
+        match &mut *self.storage.write().expect("Could not lock LevelStorage for write access") {
+            Some(storage) => storage.write_levels(&to_write)?,
+            None => return Err(String::from("There is no LevelStorage to save levels to")),
+        }
+        for (_location, contents) in &mut dirty {
+            contents.dirty = false;
+        }
+        Ok(dirty.len())
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        *self.memory_budget.lock().expect("Could not lock memory budget")
+    }
+
+    pub fn set_memory_budget(&self, memory_budget:Option<usize>) -> Result<Vec<VoxelLocation>, String> {
+        *self.memory_budget.lock().expect("Could not lock memory budget") = memory_budget;
+        self.enforce_memory_budget()
+    }
+
+    //unloads least recently used levels until the octree fits in its memory budget, returning the levels that were unloaded
+    pub fn enforce_memory_budget(&self) -> Result<Vec<VoxelLocation>, String> {
+        self.evict_over_budget(None)
+    }
+
+    fn evict_over_budget(&self, keep:Option<LevelKey>) -> Result<Vec<VoxelLocation>, String> {
+        let memory_budget = match self.memory_budget() {
+            Some(memory_budget) => memory_budget,
+            None => return Ok(vec![]),
+        };
+        let has_storage = self.has_storage();
+        let mut usage = 0;
+        let mut candidates = vec![];
+        for sorted_level in self.levels.levels() {
+            let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+            let level_usage = contents.memory_usage();
+            usage+=level_usage;
+            if !contents.loaded || contents.pinned || Some(sorted_level.ordinal)==keep || (contents.dirty && !has_storage) {
+                continue;
+            }
+            candidates.push((sorted_level.level.last_access.load(Ordering::Relaxed), sorted_level.location.clone(), level_usage));
+        }
+        candidates.sort_by_key(|candidate| candidate.0);
+
+        let mut evicted = vec![];
+        for (_last_access, location, level_usage) in candidates {
+            if usage<=memory_budget {
+                break;
+            }
+            //another thread may have unloaded it in the meantime
+            if self.unload_level(location.clone())? {
+                usage-=level_usage;
+                evicted.push(location);
+            }
+        }
+        Ok(evicted)
+    }
+
+    //the loaded level holding pos, without loading anything
+    pub fn try_get_level(&self, pos:&VoxelLocation) -> Result<Arc<SortedLevel>, String> {
+        match self.find_level(pos) {
+            Some(sorted_level) if sorted_level.level.contents.read().expect("Could not lock Level for read access").loaded => Ok(sorted_level),
+            _ => Err(format!("Level holding {:?} is not loaded", pos)),
+        }
+    }
+
+    //like try_get_level, for callers that know the level is loaded. Panics if it is not.
+    pub fn get_level(&self, pos:VoxelLocation) -> Arc<SortedLevel> {
+        match self.try_get_level(&pos) {
+            Ok(sorted_level) => sorted_level,
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    pub fn loaded_level_count(&self) -> usize {
+        self.levels.levels().iter().filter(|sorted_level| sorted_level.level.contents.read().expect("Could not lock Level for read access").loaded).count()
+    }
+
+    //walks from the coarsest LOD down to the LOD of pos, returning the Particle from the finest loaded level covering pos
+    pub fn query(&self, pos:VoxelLocation) -> Option<ParticleRef> {
+        if pos.lod>=self.level_depth {
+            return None;
+        }
+        let mut found = None;
+        for lod in (pos.lod..self.level_depth).rev() {
+            let location = to_lod(&pos, lod);
+            let sorted_level = match self.find_level(&location) {
+                Some(sorted_level) => sorted_level,
+                None => continue,
+            };
+            let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+            if !contents.loaded {
+                continue;
+            }
+            let index = index_in_level(&location, self.level_length);
+            if index>=contents.data.material.len() {
+                continue;
+            }
+            found = Some(ParticleRef {
+                location:location,
+                level:sorted_level.location.clone(),
+                index:index,
+                material:contents.data.material[index].clone(),
+                pos:contents.data.pos[index].clone(),
+            });
+        }
+        found
+    }
+
+    pub fn load_level(&self, pos:VoxelLocation) -> Result<(), String> {
+        let pos = level_origin(&pos, self.level_length);
+        if pos.lod>=self.level_depth {
+            return Err(format!("Level requested at {:?}, but the octree only has {} LODs", pos, self.level_depth));
+        }
+        let key = match level_key(&pos, self.level_length) {
+            Some(key) => key,
+            None => return Err(format!("Level requested at {:?} is outside of the addressable range", pos)),
+        };
+        loop {
+            //holding on to the entry, or to a placeholder put in its place, keeps it from being removed while loading
+            let existing = match self.levels.get(key) {
+                Some(existing) => existing,
+                None => match self.levels.insert(SortedLevel {
+                    ordinal: key,
+                    location: pos.clone(),
+                    level: Level::unloaded(),
+                }, None) {
+                    Some(existing) => existing,
+                    None => continue,
+                },
+            };
+            if existing.level.contents.read().expect("Could not lock Level for read access").loaded {
+                self.touch(&existing.level);
+                return Ok(());
+            }
+
+            //No level is locked while reading or generating, so other levels stay available in the meantime. Reading only
+            //takes the storage's read lock, so several threads can load levels at once, and generating takes no lock at all.
+            let stored = match &*self.storage.read().expect("Could not lock LevelStorage for read access") {
+                Some(storage) => storage.read_level(&pos),
+                None => Ok(None),
+            };
+            let stored = stored.and_then(|stored| match stored {
+                Some(data) => check_particle_vec_len(&data, self.level_length.pow(3) as usize).map(|()| Some(data)),
+                None => Ok(None),
+            });
+            let data = match stored {
+                Ok(Some(data)) => data,
+                Ok(None) => {
+                    let mut buffer = empty_particle_vec(self.level_length.pow(3) as usize);
+                    self.generator.generate(&pos, self.level_length, &mut buffer);
+                    buffer
+                }
+                Err(msg) => {
+                    drop(existing);
+                    self.levels.remove_unused(key);
+                    return Err(format!("Could not read Level at {:?} from storage: {}", pos, msg));
+                }
+            };
+            //If another thread loaded the level first, its copy wins and this one is dropped. If the entry was removed
+            //in the meantime, the level may have been written to storage since it was read, so it is read again.
+            if let Some(sorted_level) = self.levels.insert(
+                SortedLevel {
+                    ordinal: key,
+                    location: pos.clone(),
+                    level: Level::new(data),
+                },
+                Some(&existing),
+            ) {
+                self.touch(&sorted_level.level);
+                break;
             }
         }
-        Some(insecure_hasher)
+        //the level stays loaded either way, but the caller hears that the octree could not be kept within its budget
+        match self.evict_over_budget(Some(key)) {
+            Ok(_) => Ok(()),
+            Err(msg) => Err(format!("Loaded level at {:?}, but could not keep the octree within its memory budget: {}", pos, msg)),
+        }
     }
 }
\ No newline at end of file
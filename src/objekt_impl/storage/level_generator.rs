@@ -0,0 +1,108 @@
+use crate::{
+    math::{
+        hashing::hash_u64s,
+        octree_math::location_in_level,
+        vectors::VoxelLocation,
+    },
+    objekt_impl::storage::{
+        material_composition::MaterialComposition,
+        particle::{
+            ParticleVec,
+            MATERIAL_COUNT,
+        },
+    },
+};
+
+//Fills in a level that is not in storage yet. The octree hands over level_length^3 empty Particles (see particle::empty_particle_vec)
+//stored in octree_math::index_in_level order, so generators can be chained, each one building on the last.
+//Implementations must only depend on their own settings, origin and level_length, so a world comes out byte-identical on any machine.
+pub trait LevelGenerator: Send + Sync {
+    fn generate(&self, origin:&VoxelLocation, level_length:u64, particles:&mut ParticleVec);
+}
+
+//leaves every Particle empty
+pub struct EmptyGenerator;
+
+impl LevelGenerator for EmptyGenerator {
+    fn generate(&self, _origin:&VoxelLocation, _level_length:u64, _particles:&mut ParticleVec) {}
+}
+
+//sets one material weight on every Particle
+pub struct FillGenerator {
+    material:u16,
+    weight:u8,
+}
+
+impl FillGenerator {
+    pub fn new(material:u16, weight:u8) -> Result<Self, String> {
+        if material as u64>=MATERIAL_COUNT {
+            return Err(format!("Material {} is out of range, there are {} materials", material, MATERIAL_COUNT));
+        }
+        Ok(Self {
+            material:material,
+            weight:weight,
+        })
+    }
+
+    pub fn material(&self) -> u16 {
+        self.material
+    }
+
+    pub fn weight(&self) -> u8 {
+        self.weight
+    }
+}
+
+impl LevelGenerator for FillGenerator {
+    fn generate(&self, _origin:&VoxelLocation, _level_length:u64, particles:&mut ParticleVec) {
+        for material in &mut particles.material {
+            material.set_weight(self.material, self.weight);
+        }
+    }
+}
+
+//gives every material of every Particle a random weight, which is mostly useful for stress testing
+pub struct HashNoiseGenerator {
+    pub seed:u64,
+}
+
+impl HashNoiseGenerator {
+    pub fn new(seed:u64) -> Self {
+        Self {
+            seed:seed,
+        }
+    }
+}
+
+impl LevelGenerator for HashNoiseGenerator {
+    fn generate(&self, origin:&VoxelLocation, level_length:u64, particles:&mut ParticleVec) {
+        for (index, material) in particles.material.iter_mut().enumerate() {
+            let location = location_in_level(origin, index, level_length);
+            let weights:Vec<u8> = (0..MATERIAL_COUNT)
+                .map(|m| hash_u64s(self.seed, &[location.lod, location.vec.x, location.vec.y, location.vec.z, m]) as u8)
+                .collect();
+            *material = MaterialComposition::from_weights(&weights);
+        }
+    }
+}
+
+//runs each generator in turn over the same Particles
+#[derive(Default)]
+pub struct LayeredGenerator {
+    pub layers:Vec<Box<dyn LevelGenerator>>,
+}
+
+impl LayeredGenerator {
+    pub fn with(mut self, layer:Box<dyn LevelGenerator>) -> Self {
+        self.layers.push(layer);
+        self
+    }
+}
+
+impl LevelGenerator for LayeredGenerator {
+    fn generate(&self, origin:&VoxelLocation, level_length:u64, particles:&mut ParticleVec) {
+        for layer in &self.layers {
+            layer.generate(origin, level_length, particles);
+        }
+    }
+}
\ No newline at end of file
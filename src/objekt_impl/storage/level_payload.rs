@@ -0,0 +1,111 @@
+use crate::{
+    math::{
+        octree_math::{
+            level_key,
+            level_origin,
+        },
+        vectors::VoxelLocation,
+    },
+    objekt_impl::storage::{
+        compression::LevelCompression,
+        hybrid_octree::{
+            HybridOctree,
+            Level,
+        },
+        particle::ParticleVec,
+        sorted_level_list::SortedLevel,
+    },
+    utils::binary::{
+        ByteReader,
+        write_bytes,
+        write_u64,
+    },
+};
+
+pub const LEVEL_PAYLOAD_MAGIC:&'static [u8;4] = b"MOLV";
+
+//One level packed up for sending to another engine, such as from a server to its clients.
+//Layout: magic, level origin, level length, then the Particles as written by LevelCompression::encode.
+pub fn write_level_payload(origin:&VoxelLocation, level_length:u64, data:&ParticleVec, compression:&LevelCompression) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(LEVEL_PAYLOAD_MAGIC);
+    origin.write(&mut out);
+    write_u64(&mut out, level_length);
+    write_bytes(&mut out, &compression.encode(data));
+    out
+}
+
+//only accepts levels of the given level_length, so a payload cannot ask for more memory than a level takes
+pub fn read_level_payload(payload:&[u8], level_length:u64, compression:&LevelCompression) -> Result<(VoxelLocation, ParticleVec), String> {
+    let mut reader = ByteReader::new(payload);
+    if reader.read_slice(LEVEL_PAYLOAD_MAGIC.len())? != &LEVEL_PAYLOAD_MAGIC[..] {
+        return Err(String::from("Data is not a level payload."));
+    }
+    let origin = VoxelLocation::read(&mut reader)?;
+    let payload_level_length = reader.read_u64()?;
+    if payload_level_length!=level_length {
+        return Err(format!("Level payload holds a level of length {}, expected {}", payload_level_length, level_length));
+    }
+    if level_origin(&origin, level_length)!=origin {
+        return Err(format!("Level payload origin {:?} is not the origin of a level", origin));
+    }
+    let data = compression.decode(reader.read_bytes()?, level_length.pow(3) as usize)?;
+    if reader.remaining()!=0 {
+        return Err(format!("Level payload has {} trailing bytes.", reader.remaining()));
+    }
+    Ok((origin, data))
+}
+
+impl HybridOctree {
+    //packs up a loaded level without loading anything, see write_level_payload
+    pub fn level_payload(&self, origin:&VoxelLocation, compression:&LevelCompression) -> Result<Vec<u8>, String> {
+        let origin = level_origin(origin, self.level_length);
+        let sorted_level = self.find_level(&origin).ok_or_else(|| format!("Level at {:?} does not exist", origin))?;
+        let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+        if !contents.loaded {
+            return Err(format!("Level at {:?} is not loaded", origin));
+        }
+        Ok(write_level_payload(&origin, self.level_length, &contents.data, compression))
+    }
+
+    //Stores a level from level_payload, replacing whatever was loaded there without reading storage or generating it first.
+    //The level is marked changed, so it is written to storage before being evicted and meshes built from it are rebuilt,
+    //and it is queued for downsampling like any other edit.
+    pub fn receive_level_payload(&self, payload:&[u8], compression:&LevelCompression) -> Result<VoxelLocation, String> {
+        let (origin, mut data) = read_level_payload(payload, self.level_length, compression)?;
+        if origin.lod>=self.level_depth {
+            return Err(format!("Level payload is at {:?}, but the octree only has {} LODs", origin, self.level_depth));
+        }
+        let key = level_key(&origin, self.level_length).ok_or_else(|| format!("Level payload at {:?} is outside of the addressable range", origin))?;
+        loop {
+            let existing = self.levels.get(key);
+            if let Some(existing) = &existing {
+                let mut contents = existing.level.contents.write().expect("Could not lock Level for write access");
+                if contents.loaded {
+                    contents.data = data;
+                    contents.mark_changed();
+                    break;
+                }
+            }
+            let mut level = Level::new(data);
+            level.contents.get_mut().expect("Could not lock Level for write access").mark_changed();
+            let sorted_level = SortedLevel {
+                ordinal: key,
+                location: origin.clone(),
+                level: level,
+            };
+            //if the level was loaded or unloaded in the meantime, take the data back and try again
+            match self.levels.try_insert(sorted_level, existing.as_ref()) {
+                Ok(_) => break,
+                Err(rejected) => data = rejected.0.level.contents.into_inner().expect("Could not unwrap Level").data,
+            }
+        }
+        self.queue_downsample(&origin);
+        //looking the level up marks it as the most recently used, so it is the last to be evicted
+        self.find_level(&origin);
+        match self.enforce_memory_budget() {
+            Ok(_) => Ok(origin),
+            Err(msg) => Err(format!("Received level at {:?}, but could not keep the octree within its memory budget: {}", origin, msg)),
+        }
+    }
+}
\ No newline at end of file
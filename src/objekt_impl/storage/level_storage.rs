@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crate::{
+    math::vectors::VoxelLocation,
+    objekt_impl::storage::particle::{
+        ParticleVec,
+        read_particle_vec,
+        write_particle_vec,
+    },
+    utils::binary::ByteReader,
+};
+
+//Somewhere for a HybridOctree to put levels it unloads, and to look for levels before generating them. Reads take
+//&self, since the octree lets several threads read levels at once.
+pub trait LevelStorage: Send + Sync {
+    fn read_level(&self, location:&VoxelLocation) -> Result<Option<ParticleVec>, String>;//Ok(None) means the level has never been stored
+    fn write_level(&mut self, location:&VoxelLocation, data:&ParticleVec) -> Result<(), String>;
+
+    fn write_levels(&mut self, levels:&[(VoxelLocation, &ParticleVec)]) -> Result<(), String> {
+        for (location, data) in levels {
+            self.write_level(location, data)?;
+        }
+        Ok(())
+    }
+
+    //levels whose stored data no longer reads back intact, for HybridOctree::fsck
+    fn corrupt_levels(&mut self) -> Result<Vec<VoxelLocation>, String> {
+        Ok(vec![])
+    }
+
+    //forgets a stored level, so it is generated again the next time it is loaded. Returns whether it was stored.
+    fn remove_level(&mut self, location:&VoxelLocation) -> Result<bool, String> {
+        Err(format!("This LevelStorage can not remove the level at {:?}", location))
+    }
+}
+
+//keeps encoded levels in memory, mostly useful for tests and tools
+#[derive(Default)]
+pub struct MemoryLevelStorage {
+    pub levels:HashMap<VoxelLocation, Vec<u8>>,
+}
+
+impl LevelStorage for MemoryLevelStorage {
+    fn read_level(&self, location:&VoxelLocation) -> Result<Option<ParticleVec>, String> {
+        match self.levels.get(location) {
+            Some(encoded) => Ok(Some(read_particle_vec(&mut ByteReader::new(encoded))?)),
+            None => Ok(None),
+        }
+    }
+
+    fn write_level(&mut self, location:&VoxelLocation, data:&ParticleVec) -> Result<(), String> {
+        let mut encoded = vec![];
+        write_particle_vec(data, &mut encoded);
+        self.levels.insert(location.clone(), encoded);
+        Ok(())
+    }
+
+    //there are no checksums in memory, so this only finds levels that no longer decode
+    fn corrupt_levels(&mut self) -> Result<Vec<VoxelLocation>, String> {
+        Ok(self.levels.iter()
+            .filter(|(_location, encoded)| {
+                let mut reader = ByteReader::new(encoded);
+                read_particle_vec(&mut reader).is_err() || reader.remaining()!=0
+            })
+            .map(|(location, _encoded)| location.clone())
+            .collect())
+    }
+
+    fn remove_level(&mut self, location:&VoxelLocation) -> Result<bool, String> {
+        Ok(self.levels.remove(location).is_some())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,211 @@
+use std::mem::size_of;
+
+use crate::objekt_impl::storage::particle::MATERIAL_COUNT;
+
+//above this many materials a Particle keeps every weight, since the list would not be much smaller and lookups would get slow
+pub const SPARSE_MATERIAL_LIMIT:usize = 32;
+
+//The weights of every material in one Particle. Most Particles hold no material or just one, so rather than always storing
+//MATERIAL_COUNT bytes the smallest of these forms is picked automatically whenever weights change. Weights of materials that
+//are not listed are 0, and two compositions are equal when all of their weights are, whatever form they are in.
+#[derive(Clone, Debug, Default)]
+pub enum MaterialComposition {
+    #[default]
+    Empty,
+    Single(u16, u8),//one material with a nonzero weight
+    Sparse(Box<[(u16, u8)]>),//materials in increasing order with their nonzero weights, at most SPARSE_MATERIAL_LIMIT of them
+    Dense(Box<[u8]>),//one weight per material, for more than SPARSE_MATERIAL_LIMIT materials
+}
+
+impl PartialEq for MaterialComposition {
+    fn eq(&self, other:&Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for MaterialComposition {}
+
+impl MaterialComposition {
+    //nothing but the given material, at full weight
+    pub fn one_hot(material:u16) -> Self {
+        MaterialComposition::Single(material, u8::MAX)
+    }
+
+    //materials past the end of weights have no weight. Weights past MATERIAL_COUNT are dropped.
+    pub fn from_weights(weights:&[u8]) -> Self {
+        Self::from_entries(weights.iter().take(MATERIAL_COUNT as usize).enumerate().map(|(material, weight)| (material as u16, *weight)))
+    }
+
+    //(material, weight) pairs in any order. Zero weights and materials past MATERIAL_COUNT are dropped.
+    pub fn from_entries<I: IntoIterator<Item = (u16, u8)>>(entries:I) -> Self {
+        let mut sparse = vec![];
+        let mut dense:Option<Vec<u8>> = None;
+        for (material, weight) in entries {
+            if weight==0 || material as u64>=MATERIAL_COUNT {
+                continue;
+            }
+            match &mut dense {
+                Some(dense) => dense[material as usize] = weight,
+                None if sparse.len()<SPARSE_MATERIAL_LIMIT => sparse.push((material, weight)),
+                None => {
+                    let mut weights = vec![0; MATERIAL_COUNT as usize];
+                    for (material, weight) in sparse.drain(..) {
+                        weights[material as usize] = weight;
+                    }
+                    weights[material as usize] = weight;
+                    dense = Some(weights);
+                }
+            }
+        }
+        //a material given more than once may have pushed the list over the limit early
+        if let Some(dense) = dense {
+            if dense.iter().filter(|weight| **weight!=0).count()>SPARSE_MATERIAL_LIMIT {
+                return MaterialComposition::Dense(dense.into_boxed_slice());
+            }
+            sparse = dense.iter().enumerate().filter(|(_material, weight)| **weight!=0).map(|(material, weight)| (material as u16, *weight)).collect();
+        }
+        //lookups rely on the order, and a material given twice keeps its last weight
+        sparse.reverse();
+        sparse.sort_by_key(|(material, _weight)| *material);
+        sparse.dedup_by_key(|(material, _weight)| *material);
+        match sparse.len() {
+            0 => MaterialComposition::Empty,
+            1 => MaterialComposition::Single(sparse[0].0, sparse[0].1),
+            _ => MaterialComposition::Sparse(sparse.into_boxed_slice()),
+        }
+    }
+
+    pub fn weight(&self, material:u16) -> u8 {
+        match self {
+            MaterialComposition::Empty => 0,
+            MaterialComposition::Single(single, weight) => if *single==material {*weight} else {0},
+            MaterialComposition::Sparse(entries) => match entries.binary_search_by_key(&material, |(material, _weight)| *material) {
+                Ok(index) => entries[index].1,
+                Err(_) => 0,
+            },
+            MaterialComposition::Dense(weights) => weights.get(material as usize).cloned().unwrap_or(0),
+        }
+    }
+
+    //materials past MATERIAL_COUNT are ignored
+    pub fn set_weight(&mut self, material:u16, weight:u8) {
+        if material as u64>=MATERIAL_COUNT || self.weight(material)==weight {
+            return;
+        }
+        if let MaterialComposition::Dense(weights) = self {
+            weights[material as usize] = weight;
+            //shrinks as soon as the weights fit in a list again, so a Dense always holds more than SPARSE_MATERIAL_LIMIT
+            if weight==0 && weights.iter().filter(|weight| **weight!=0).count()<=SPARSE_MATERIAL_LIMIT {
+                *self = Self::from_weights(weights);
+            }
+            return;
+        }
+        let mut entries:Vec<(u16, u8)> = self.iter().filter(|(other, _weight)| *other!=material).collect();
+        let position = entries.partition_point(|(other, _weight)| *other<material);
+        entries.insert(position, (material, weight));
+        *self = Self::from_entries(entries);
+    }
+
+    pub fn clear(&mut self) {
+        *self = MaterialComposition::Empty;
+    }
+
+    //every weight, one per material
+    pub fn weights(&self) -> Vec<u8> {
+        let mut weights = vec![0; MATERIAL_COUNT as usize];
+        for (material, weight) in self.iter() {
+            weights[material as usize] = weight;
+        }
+        weights
+    }
+
+    //(material, weight) for every material with a nonzero weight, in increasing order of material
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (u16, u8)> + '_> {
+        match self {
+            MaterialComposition::Empty => Box::new(std::iter::empty()),
+            MaterialComposition::Single(material, weight) => Box::new(std::iter::once((*material, *weight)).filter(|(_material, weight)| *weight!=0)),
+            MaterialComposition::Sparse(entries) => Box::new(entries.iter().cloned().filter(|(_material, weight)| *weight!=0)),
+            MaterialComposition::Dense(weights) => Box::new(weights.iter().enumerate().filter(|(_material, weight)| **weight!=0).map(|(material, weight)| (material as u16, *weight))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    //the number of materials with a nonzero weight
+    pub fn material_count(&self) -> usize {
+        self.iter().count()
+    }
+
+    //the material with the highest weight, skipping ignored ones, with ties going to the lower material. None if nothing else has any weight.
+    pub fn dominant(&self, ignored:&[u16]) -> Option<u16> {
+        let mut dominant = None;
+        let mut dominant_weight = 0;
+        for (material, weight) in self.iter() {
+            if weight>dominant_weight && !ignored.contains(&material) {
+                dominant = Some(material);
+                dominant_weight = weight;
+            }
+        }
+        dominant
+    }
+
+    //the sum of all weights, skipping ignored materials
+    pub fn total_weight(&self, ignored:&[u16]) -> u32 {
+        self.iter().filter(|(material, _weight)| !ignored.contains(material)).map(|(_material, weight)| weight as u32).sum()
+    }
+
+    //Checks the form invariants the constructors keep, for compositions that came from elsewhere. Lookups and equality rely on them.
+    pub fn validate(&self) -> Result<(), String> {
+        let check_entry = |material:u16, weight:u8| {
+            if material as u64>=MATERIAL_COUNT {
+                Err(format!("material {} is out of range", material))
+            } else if weight==0 {
+                Err(format!("material {} is listed with no weight", material))
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            MaterialComposition::Empty => Ok(()),
+            MaterialComposition::Single(material, weight) => check_entry(*material, *weight),
+            MaterialComposition::Sparse(entries) => {
+                if entries.len()<2 || entries.len()>SPARSE_MATERIAL_LIMIT {
+                    return Err(format!("sparse list of {} materials", entries.len()));
+                }
+                for (index, (material, weight)) in entries.iter().enumerate() {
+                    check_entry(*material, *weight)?;
+                    if index>0 && entries[index-1].0>=*material {
+                        return Err(format!("material {} is out of order", material));
+                    }
+                }
+                Ok(())
+            }
+            MaterialComposition::Dense(weights) => {
+                if weights.len()!=MATERIAL_COUNT as usize {
+                    return Err(format!("{} dense weights, expected {}", weights.len(), MATERIAL_COUNT));
+                }
+                let count = weights.iter().filter(|weight| **weight!=0).count();
+                if count<=SPARSE_MATERIAL_LIMIT {
+                    return Err(format!("dense weights for {} materials, which fit in a sparse list", count));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    //the same nonzero weights in valid form, dropping materials that are out of range
+    pub fn normalized(&self) -> Self {
+        Self::from_entries(self.iter().collect::<Vec<_>>())
+    }
+
+    //bytes held on the heap, on top of the size of the enum itself
+    pub fn heap_usage(&self) -> usize {
+        match self {
+            MaterialComposition::Empty | MaterialComposition::Single(_, _) => 0,
+            MaterialComposition::Sparse(entries) => entries.len()*size_of::<(u16, u8)>(),
+            MaterialComposition::Dense(weights) => weights.len(),
+        }
+    }
+}
\ No newline at end of file
@@ -1,3 +1,16 @@
+pub mod compression;
+pub mod downsampling;
+pub mod fsck;
+pub mod gpu_layout;
 pub mod hybrid_octree;
+pub mod level_generator;
+pub mod level_payload;
+pub mod level_storage;
+pub mod material_composition;
 pub mod particle;
-pub mod sorted_level_list;
\ No newline at end of file
+pub mod range_query;
+pub mod raycast;
+pub mod region_file;
+pub mod sorted_level_list;
+pub mod svo;
+pub mod voxel_editing;
\ No newline at end of file
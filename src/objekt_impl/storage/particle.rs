@@ -1,18 +1,160 @@
+use std::mem::size_of;
+
 use soa_derive::StructOfArray;
 
 use crate::{
-    math::vectors::{
-        Vector3U16,
-    }
+    math::{
+        octree_math::{
+            voxel_min_corner,
+            voxel_size,
+        },
+        vectors::{
+            Vector3U16,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::material_composition::MaterialComposition,
+    utils::binary::{
+        ByteReader,
+        write_bytes,
+        write_u32,
+        write_u64,
+    },
 };
 
 pub const MATERIAL_COUNT:u64=512;
+pub const HALF_LIMIT:u16=1<<15;//a pos of HALF_LIMIT on every axis is the center of the voxel
 
 #[derive(Debug, PartialEq, StructOfArray)]
 #[soa_derive = "Debug, PartialEq"]
 pub struct Particle {
     pub _gpu_only_level_index_current: u32,// the level containing this Particle
     pub _gpu_only_level_index_next: u32, //the level contained by this Particle
-    pub material: Vec<u8>,
+    pub material: MaterialComposition,
     pub pos: Vector3U16,//within the bounds of the associated voxel
+}
+
+//Particles with no material, centered in their voxels
+pub fn empty_particle_vec(len:usize) -> ParticleVec {
+    let mut particles = ParticleVec::with_capacity(len);
+    for _i in 0..len {
+        particles.push(Particle {
+            _gpu_only_level_index_current: 0,
+            _gpu_only_level_index_next: 0,
+            material: MaterialComposition::Empty,
+            pos: Vector3U16 {
+                x: HALF_LIMIT,
+                y: HALF_LIMIT,
+                z: HALF_LIMIT,
+            },
+        });
+    }
+    particles
+}
+
+//nothing but the given material, at full weight
+pub fn one_hot_material(material:u16) -> MaterialComposition {
+    MaterialComposition::one_hot(material)
+}
+
+//where a Particle sits in world units, with pos spanning its voxel from the lowest corner up to just short of the highest
+pub fn particle_position(location:&VoxelLocation, pos:&Vector3U16) -> [f64;3] {
+    let corner = voxel_min_corner(location);
+    let scale = voxel_size(location.lod) as f64/65536.0;
+    [corner[0]+pos.x as f64*scale, corner[1]+pos.y as f64*scale, corner[2]+pos.z as f64*scale]
+}
+
+//Every column has to hold exactly one Particle per voxel of the level. The columns are pub, so a ParticleVec from
+//a LevelStorage or a snapshot is checked before it is put in an octree.
+pub fn check_particle_vec_len(particles:&ParticleVec, len:usize) -> Result<(), String> {
+    let lengths = [
+        particles._gpu_only_level_index_current.len(),
+        particles._gpu_only_level_index_next.len(),
+        particles.material.len(),
+        particles.pos.len(),
+    ];
+    if lengths.iter().any(|column_len| *column_len!=len) {
+        return Err(format!("Level columns hold {:?} Particles, expected {} in each", lengths, len));
+    }
+    Ok(())
+}
+
+//heap bytes held by the columns, including spare capacity
+pub fn particle_vec_memory_usage(particles:&ParticleVec) -> usize {
+    particles._gpu_only_level_index_current.capacity()*size_of::<u32>()
+        + particles._gpu_only_level_index_next.capacity()*size_of::<u32>()
+        + particles.material.capacity()*size_of::<MaterialComposition>()
+        + particles.material.iter().map(|material| material.heap_usage()).sum::<usize>()
+        + particles.pos.capacity()*size_of::<Vector3U16>()
+}
+
+//how many Particles of a level keep their materials in each form, and the bytes that takes, see MaterialComposition
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaterialStorageReport {
+    pub empty:usize,
+    pub single:usize,
+    pub sparse:usize,
+    pub dense:usize,
+    pub bytes:usize,//of the material column, including spare capacity
+}
+
+pub fn material_storage_report(particles:&ParticleVec) -> MaterialStorageReport {
+    let mut report = MaterialStorageReport {
+        bytes:particles.material.capacity()*size_of::<MaterialComposition>(),
+        ..Default::default()
+    };
+    for material in &particles.material {
+        match material {
+            MaterialComposition::Empty => report.empty+=1,
+            MaterialComposition::Single(_, _) => report.single+=1,
+            MaterialComposition::Sparse(_) => report.sparse+=1,
+            MaterialComposition::Dense(_) => report.dense+=1,
+        }
+        report.bytes+=material.heap_usage();
+    }
+    report
+}
+
+//columns are written one after another, matching the in-memory layout
+pub fn write_particle_vec(particles:&ParticleVec, out:&mut Vec<u8>) {
+    write_u64(out, particles.len() as u64);
+    for index in &particles._gpu_only_level_index_current {
+        write_u32(out, *index);
+    }
+    for index in &particles._gpu_only_level_index_next {
+        write_u32(out, *index);
+    }
+    //every weight is written out, so the format does not depend on how compositions are kept in memory
+    for material in &particles.material {
+        write_bytes(out, &material.weights());
+    }
+    for pos in &particles.pos {
+        pos.write(out);
+    }
+}
+
+pub fn read_particle_vec(reader:&mut ByteReader) -> Result<ParticleVec, String> {
+    let len = reader.read_u64()?;
+    if len>reader.remaining() as u64 {//every particle takes at least one byte, so this catches absurd lengths before allocating
+        return Err(format!("ParticleVec length {} is larger than the remaining data.", len));
+    }
+    let len = len as usize;
+    let mut particles = ParticleVec::with_capacity(len);
+    for _i in 0..len {
+        particles._gpu_only_level_index_current.push(reader.read_u32()?);
+    }
+    for _i in 0..len {
+        particles._gpu_only_level_index_next.push(reader.read_u32()?);
+    }
+    for _i in 0..len {
+        let weights = reader.read_bytes()?;
+        if weights.len()>MATERIAL_COUNT as usize {
+            return Err(format!("Particle holds {} material weights, but there are only {} materials", weights.len(), MATERIAL_COUNT));
+        }
+        particles.material.push(MaterialComposition::from_weights(weights));
+    }
+    for _i in 0..len {
+        particles.pos.push(Vector3U16::read(reader)?);
+    }
+    Ok(particles)
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+use std::vec::IntoIter;
+
+use crate::{
+    math::{
+        octree_math::{
+            index_in_level,
+            level_key,
+            level_may_overlap,
+            voxel_center,
+            voxel_range,
+            VoxelRange,
+        },
+        shapes::{
+            Aabb,
+            Frustum,
+            Sdf,
+            Sphere,
+        },
+        vectors::VoxelLocation,
+    },
+    objekt_impl::storage::hybrid_octree::{
+        HybridOctree,
+        ParticleRef,
+    },
+};
+
+//Yields every Particle at one LOD whose voxel center lies inside a shape, level by level in level key order.
+//Only levels that are already loaded are read, nothing is loaded or generated. Each level is locked once,
+//while its matches are copied out, so the octree may be edited between calls to next.
+pub struct RangeQuery<'a> {
+    octree:&'a HybridOctree,
+    shape:&'a dyn Sdf,
+    range:Option<VoxelRange>,
+    levels:IntoIter<VoxelLocation>,
+    pending:IntoIter<ParticleRef>,
+}
+
+impl<'a> RangeQuery<'a> {
+    fn new(octree:&'a HybridOctree, shape:&'a dyn Sdf, lod:u64) -> Self {
+        let mut query = Self {
+            octree:octree,
+            shape:shape,
+            range:None,
+            levels:vec![].into_iter(),
+            pending:vec![].into_iter(),
+        };
+        if lod>=octree.level_depth {
+            return query;
+        }
+        let level_length = octree.level_length;
+        let range = match voxel_range(&shape.bounds(), lod, level_length) {
+            Some(range) => range,
+            None => return query,
+        };
+
+        //look up each level in range, unless there are fewer loaded levels than that to go through
+        let mut origins:Vec<VoxelLocation> = if range.level_count(level_length)<=octree.levels.len() as u128 {
+            range.level_origins(level_length).collect()
+        } else {
+            octree.levels.levels().iter()
+                .map(|sorted_level| sorted_level.location.clone())
+                .filter(|location| range.within_level(location, level_length).is_some())
+                .collect()
+        };
+        origins.retain(|origin| level_may_overlap(shape, origin, level_length));
+        origins.sort_by_key(|origin| level_key(origin, level_length));
+
+        query.range = Some(range);
+        query.levels = origins.into_iter();
+        query
+    }
+
+    fn read_level(&self, origin:&VoxelLocation) -> Vec<ParticleRef> {
+        let mut matches = vec![];
+        let level_length = self.octree.level_length;
+        let range = match self.range.as_ref().and_then(|range| range.within_level(origin, level_length)) {
+            Some(range) => range,
+            None => return matches,
+        };
+        let sorted_level = match self.octree.find_level(origin) {
+            Some(sorted_level) => sorted_level,
+            None => return matches,
+        };
+        let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+        if !contents.loaded {
+            return matches;
+        }
+        for location in range.voxels() {
+            if self.shape.distance(voxel_center(&location))>0.0 {
+                continue;
+            }
+            let index = index_in_level(&location, level_length);
+            matches.push(ParticleRef {
+                location:location,
+                level:origin.clone(),
+                index:index,
+                material:contents.data.material[index].clone(),
+                pos:contents.data.pos[index].clone(),
+            });
+        }
+        matches
+    }
+}
+
+impl<'a> Iterator for RangeQuery<'a> {
+    type Item = ParticleRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.next() {
+                return Some(item);
+            }
+            let origin = self.levels.next()?;
+            self.pending = self.read_level(&origin).into_iter();
+        }
+    }
+}
+
+impl HybridOctree {
+    pub fn query_shape<'a>(&'a self, shape:&'a dyn Sdf, lod:u64) -> RangeQuery<'a> {
+        RangeQuery::new(self, shape, lod)
+    }
+
+    pub fn query_box<'a>(&'a self, bounds:&'a Aabb, lod:u64) -> RangeQuery<'a> {
+        RangeQuery::new(self, bounds, lod)
+    }
+
+    pub fn query_sphere<'a>(&'a self, sphere:&'a Sphere, lod:u64) -> RangeQuery<'a> {
+        RangeQuery::new(self, sphere, lod)
+    }
+
+    pub fn query_frustum<'a>(&'a self, frustum:&'a Frustum, lod:u64) -> RangeQuery<'a> {
+        RangeQuery::new(self, frustum, lod)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,220 @@
+use crate::{
+    math::{
+        octree_math::{
+            index_in_level,
+            level_origin,
+            voxel_min_corner,
+            voxel_size,
+        },
+        shapes::Aabb,
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::hybrid_octree::HybridOctree,
+};
+
+//how far past a cell boundary the walk continues, so the next lookup lands inside the following cell
+pub const STEP_EPSILON:f64 = 1e-7;
+
+//in world units, see octree_math::voxel_min_corner. The direction does not need to be normalized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin:[f64;3],
+    pub direction:[f64;3],
+}
+
+impl Ray {
+    pub fn new(origin:[f64;3], direction:[f64;3]) -> Self {
+        Self {
+            origin:origin,
+            direction:direction,
+        }
+    }
+
+    pub fn at(&self, distance:f64) -> [f64;3] {
+        [
+            self.origin[0]+self.direction[0]*distance,
+            self.origin[1]+self.direction[1]*distance,
+            self.origin[2]+self.direction[2]*distance,
+        ]
+    }
+
+    //distance along the ray at which it leaves the box, and the axis of the face it leaves through
+    pub fn exit(&self, bounds:&Aabb) -> (f64, usize) {
+        let mut exit = (f64::INFINITY, 0);
+        for axis in 0..3 {
+            let distance = if self.direction[axis]>0.0 {
+                (bounds.max[axis]-self.origin[axis])/self.direction[axis]
+            } else if self.direction[axis]<0.0 {
+                (bounds.min[axis]-self.origin[axis])/self.direction[axis]
+            } else {
+                continue;
+            };
+            if distance<exit.0 {
+                exit = (distance, axis);
+            }
+        }
+        exit
+    }
+
+    //distance along the ray at which it enters the box, and the axis of the face it enters through
+    pub fn entry(&self, bounds:&Aabb) -> (f64, usize) {
+        let mut entry = (f64::NEG_INFINITY, 0);
+        for axis in 0..3 {
+            let distance = if self.direction[axis]>0.0 {
+                (bounds.min[axis]-self.origin[axis])/self.direction[axis]
+            } else if self.direction[axis]<0.0 {
+                (bounds.max[axis]-self.origin[axis])/self.direction[axis]
+            } else if self.origin[axis]<bounds.min[axis] || self.origin[axis]>bounds.max[axis] {
+                f64::INFINITY//parallel to the slab and outside of it, so the ray never enters
+            } else {
+                continue;
+            };
+            if distance>entry.0 {
+                entry = (distance, axis);
+            }
+        }
+        entry
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaycastOptions {
+    pub max_distance:f64,
+    pub ignored_materials:Vec<u16>,//voxels holding only these materials are treated as empty, e.g. water for line of sight
+    pub finest_lod:u64,//the walk does not descend below this LOD
+}
+
+impl Default for RaycastOptions {
+    fn default() -> Self {
+        Self {
+            max_distance:1024.0,
+            ignored_materials:vec![],
+            finest_lod:0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub voxel:VoxelLocation,//at the finest LOD loaded there
+    pub normal:[i8;3],//of the face the ray entered through, or all zero if the ray started inside the voxel
+    pub distance:f64,//along the normalized direction
+    pub position:[f64;3],
+    pub material:u16,//the dominant material of the voxel, see MaterialComposition::dominant
+}
+
+fn voxel_bounds(location:&VoxelLocation, length:u64) -> Aabb {
+    let min = voxel_min_corner(location);
+    let extent = (length*voxel_size(location.lod)) as f64;
+    Aabb::new(min, [min[0]+extent, min[1]+extent, min[2]+extent])
+}
+
+fn voxel_at(point:[f64;3], lod:u64) -> VoxelLocation {
+    let size = voxel_size(lod) as f64;
+    //float to int casts saturate, so points just below zero from rounding land in the first voxel
+    VoxelLocation {
+        lod:lod,
+        vec:Vector3U64 {
+            x:(point[0]/size).floor() as u64,
+            y:(point[1]/size).floor() as u64,
+            z:(point[2]/size).floor() as u64,
+        },
+    }
+}
+
+//The walk moves from cell to cell along the ray. At each step it looks at the voxels containing the current point from the coarsest LOD down:
+//an empty voxel is skipped whole, and a solid one is refined by finer loaded levels until none are left, which makes it the hit.
+//An empty voxel at a coarse LOD is taken to mean everything beneath it is empty too, as it is once finer LODs are downsampled into it.
+//Where no level is loaded at all the walk skips a whole level at finest_lod, and it stops once it leaves every loaded level.
+impl HybridOctree {
+    //None if no loaded level holds the voxel, otherwise its dominant material
+    fn solid_material(&self, location:&VoxelLocation, ignored:&[u16]) -> Option<Option<u16>> {
+        let sorted_level = self.find_level(location)?;
+        let contents = sorted_level.level.contents.read().expect("Could not lock Level for read access");
+        if !contents.loaded {
+            return None;
+        }
+        let weights = contents.data.material.get(index_in_level(location, self.level_length))?;
+        Some(weights.dominant(ignored))
+    }
+
+    fn loaded_bounds(&self, finest_lod:u64) -> Option<Aabb> {
+        let mut bounds:Option<Aabb> = None;
+        for sorted_level in self.levels.levels() {
+            if sorted_level.location.lod<finest_lod || !sorted_level.level.contents.read().expect("Could not lock Level for read access").loaded {
+                continue;
+            }
+            let level_bounds = voxel_bounds(&sorted_level.location, self.level_length);
+            bounds = Some(match bounds {
+                Some(bounds) => Aabb::new(
+                    [bounds.min[0].min(level_bounds.min[0]), bounds.min[1].min(level_bounds.min[1]), bounds.min[2].min(level_bounds.min[2])],
+                    [bounds.max[0].max(level_bounds.max[0]), bounds.max[1].max(level_bounds.max[1]), bounds.max[2].max(level_bounds.max[2])],
+                ),
+                None => level_bounds,
+            });
+        }
+        bounds
+    }
+
+    pub fn raycast(&self, ray:&Ray, options:&RaycastOptions) -> Option<RaycastHit> {
+        let length = (ray.direction[0]*ray.direction[0]+ray.direction[1]*ray.direction[1]+ray.direction[2]*ray.direction[2]).sqrt();
+        if length==0.0 || !length.is_finite() || options.finest_lod>=self.level_depth {
+            return None;
+        }
+        let ray = Ray::new(ray.origin, [ray.direction[0]/length, ray.direction[1]/length, ray.direction[2]/length]);
+        let bounds = self.loaded_bounds(options.finest_lod)?;
+        let (entry, entry_axis) = ray.entry(&bounds);
+        let end = options.max_distance.min(ray.exit(&bounds).0);
+        //where the ray crossed into the current cell, and through which face. None while still in the cell the ray started in.
+        let mut crossing = if entry>0.0 {Some((entry, entry_axis))} else {None};
+        let mut distance = entry.max(0.0)+if entry>0.0 {STEP_EPSILON} else {0.0};
+
+        while distance<=end {
+            let point = ray.at(distance);
+            let mut solid = None;
+            let mut empty = None;
+            for lod in (options.finest_lod..self.level_depth).rev() {
+                let location = voxel_at(point, lod);
+                match self.solid_material(&location, &options.ignored_materials) {
+                    Some(Some(material)) => solid = Some((location, material)),
+                    Some(None) => {
+                        empty = Some(voxel_bounds(&location, 1));
+                        break;
+                    }
+                    None => {}
+                }
+            }
+            let cell = match (empty, solid) {
+                (Some(cell), _) => cell,
+                (None, Some((voxel, material))) => {
+                    let (hit_distance, normal) = match crossing {
+                        Some((hit_distance, axis)) => {
+                            let mut normal = [0;3];
+                            normal[axis] = if ray.direction[axis]>0.0 {-1} else {1};
+                            (hit_distance, normal)
+                        }
+                        None => (0.0, [0;3]),
+                    };
+                    if hit_distance>options.max_distance {
+                        return None;
+                    }
+                    return Some(RaycastHit {
+                        voxel:voxel,
+                        normal:normal,
+                        distance:hit_distance,
+                        position:ray.at(hit_distance),
+                        material:material,
+                    });
+                }
+                (None, None) => voxel_bounds(&level_origin(&voxel_at(point, options.finest_lod), self.level_length), self.level_length),
+            };
+            let (exit, exit_axis) = ray.exit(&cell);
+            crossing = Some((exit, exit_axis));
+            distance = exit.max(distance)+STEP_EPSILON;
+        }
+        None
+    }
+}
\ No newline at end of file
@@ -0,0 +1,460 @@
+use std::{
+    collections::BTreeMap,
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        ErrorKind,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::{
+    get_engine_version,
+    math::{
+        octree_math::{
+            level_coords,
+            level_key,
+            level_key_origin,
+            LevelKey,
+        },
+        vectors::VoxelLocation,
+    },
+    metadata::versions::MoleculeVersion,
+    objekt_impl::storage::{
+        compression::LevelCompression,
+        level_storage::LevelStorage,
+        particle::ParticleVec,
+    },
+    utils::{
+        binary::{
+            ByteReader,
+            write_u32,
+            write_u64,
+            write_u128,
+        },
+        checksum::crc32,
+    },
+};
+
+pub const REGION_MAGIC:&'static [u8;8] = b"MOLREGN\0";
+pub const REGION_FORMAT_VERSION:u32 = 1;
+pub const REGION_LENGTH:u64 = 8;//levels per axis stored in one region file
+pub const REGION_SLOTS:usize = (REGION_LENGTH*REGION_LENGTH*REGION_LENGTH) as usize;
+const REGION_HEADER_LEN:usize = 8+4+12+8;
+const REGION_SLOT_LEN:usize = 16+8+8+4;
+pub const REGION_INDEX_LEN:usize = REGION_HEADER_LEN+REGION_SLOTS*REGION_SLOT_LEN;//where the payloads start
+
+//The lowest bits of a level key interleave the lowest bits of the level coordinates, so every level of a region has a slot of its own
+pub fn region_slot(key:LevelKey) -> usize {
+    (key%REGION_SLOTS as u128) as usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionSlot {
+    pub key:LevelKey,
+    pub offset:u64,//from the start of the file
+    pub length:u64,
+    pub checksum:u32,//crc32 of the payload
+}
+
+//Layout: magic, format version, engine version, level length, then an index of REGION_SLOTS slots, then the payloads.
+//A slot holds the level key, payload offset, payload length and payload crc32 of the level that region_slot puts there,
+//or zeroes when that level is not stored. Each payload is a ParticleVec as written by LevelCompression::encode. The index
+//has a fixed size, so RegionStorage can read one level without reading the rest of the file, and write one by appending
+//its payload and patching its slot.
+pub struct RegionIndex {
+    pub engine_version:MoleculeVersion,//that wrote the file out in full, see RegionFile::write
+    pub level_length:u64,
+    pub slots:Vec<Option<RegionSlot>>,
+}
+
+impl RegionIndex {
+    pub fn new(level_length:u64) -> Self {
+        Self {
+            engine_version:get_engine_version(),
+            level_length:level_length,
+            slots:vec![None; REGION_SLOTS],
+        }
+    }
+
+    pub fn read(data:&[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(data);
+        if reader.read_slice(REGION_MAGIC.len())? != &REGION_MAGIC[..] {
+            return Err(String::from("Data is not a region file."));
+        }
+        let format_version = reader.read_u32()?;
+        if format_version!=REGION_FORMAT_VERSION {
+            return Err(format!("Region format version {} is not the supported version {}.", format_version, REGION_FORMAT_VERSION));
+        }
+        let engine_version = MoleculeVersion::read(&mut reader)?;
+        let level_length = reader.read_u64()?;
+        let mut slots = vec![];
+        for slot in 0..REGION_SLOTS {
+            let (key, offset, length, checksum) = (reader.read_u128()?, reader.read_u64()?, reader.read_u64()?, reader.read_u32()?);
+            if length==0 {
+                slots.push(None);
+                continue;
+            }
+            if region_slot(key)!=slot {
+                return Err(format!("Region entry {} is in slot {} instead of slot {}.", key, slot, region_slot(key)));
+            }
+            if offset<REGION_INDEX_LEN as u64 || offset.checked_add(length).is_none() {
+                return Err(format!("Region entry {} has an invalid payload offset {}.", key, offset));
+            }
+            slots.push(Some(RegionSlot {
+                key:key,
+                offset:offset,
+                length:length,
+                checksum:checksum,
+            }));
+        }
+        Ok(Self {
+            engine_version:engine_version,
+            level_length:level_length,
+            slots:slots,
+        })
+    }
+
+    pub fn write(&self, out:&mut Vec<u8>) {
+        out.extend_from_slice(REGION_MAGIC);
+        write_u32(out, REGION_FORMAT_VERSION);
+        self.engine_version.write(out);
+        write_u64(out, self.level_length);
+        for slot in 0..REGION_SLOTS {
+            Self::write_slot(self.slots[slot].as_ref(), out);
+        }
+    }
+
+    fn write_slot(slot:Option<&RegionSlot>, out:&mut Vec<u8>) {
+        let slot = slot.cloned().unwrap_or(RegionSlot {
+            key:0,
+            offset:0,
+            length:0,
+            checksum:0,
+        });
+        write_u128(out, slot.key);
+        write_u64(out, slot.offset);
+        write_u64(out, slot.length);
+        write_u32(out, slot.checksum);
+    }
+
+    //payload bytes the index still points at
+    pub fn live_bytes(&self) -> u64 {
+        self.slots.iter().flatten().map(|slot| slot.length).sum()
+    }
+}
+
+fn decode_payload(compression:&LevelCompression, level_length:u64, key:LevelKey, payload:&[u8]) -> Result<ParticleVec, String> {
+    let particle_count = level_length.checked_pow(3).filter(|count| *count<=usize::MAX as u64)
+        .ok_or_else(|| format!("Level length {} is too large", level_length))?;
+    compression.decode(payload, particle_count as usize).map_err(|msg| format!("Region entry {} is corrupt: {}", key, msg))
+}
+
+//A whole region file in memory, for checking every entry or writing a region out compactly
+pub struct RegionFile {
+    pub engine_version:MoleculeVersion,
+    pub level_length:u64,
+    pub entries:BTreeMap<LevelKey, Vec<u8>>,
+    pub compression:LevelCompression,//for levels written from now on, any compression can be read
+    //Entries that failed their checksum when read, with the checksum the index holds for them. They are written back
+    //with that checksum, so they stay detectable until they are overwritten or removed.
+    pub corrupt:BTreeMap<LevelKey, u32>,
+}
+
+impl RegionFile {
+    pub fn new(level_length:u64) -> Self {
+        Self {
+            engine_version:get_engine_version(),
+            level_length:level_length,
+            entries:BTreeMap::new(),
+            compression:LevelCompression::default(),
+            corrupt:BTreeMap::new(),
+        }
+    }
+
+    pub fn read(data:&[u8]) -> Result<Self, String> {
+        let index = RegionIndex::read(data)?;
+        let mut entries = BTreeMap::new();
+        let mut corrupt = BTreeMap::new();
+        for slot in index.slots.iter().flatten() {
+            let end = slot.offset+slot.length;
+            if end>data.len() as u64 {
+                return Err(format!("Region entry {} runs past the end of the file.", slot.key));
+            }
+            let payload = data[slot.offset as usize..end as usize].to_vec();
+            if crc32(&payload)!=slot.checksum {
+                corrupt.insert(slot.key, slot.checksum);
+            }
+            entries.insert(slot.key, payload);
+        }
+        Ok(Self {
+            engine_version:index.engine_version,
+            level_length:index.level_length,
+            entries:entries,
+            compression:LevelCompression::default(),
+            corrupt:corrupt,
+        })
+    }
+
+    //the payloads follow the index in key order, without any space left over from earlier writes
+    pub fn write(&self) -> Vec<u8> {
+        let mut index = RegionIndex::new(self.level_length);
+        let mut offset = REGION_INDEX_LEN as u64;
+        for (key, payload) in &self.entries {
+            index.slots[region_slot(*key)] = Some(RegionSlot {
+                key:*key,
+                offset:offset,
+                length:payload.len() as u64,
+                checksum:self.corrupt.get(key).cloned().unwrap_or_else(|| crc32(payload)),
+            });
+            offset+=payload.len() as u64;
+        }
+        let mut out = vec![];
+        index.write(&mut out);
+        for payload in self.entries.values() {
+            out.extend_from_slice(payload);
+        }
+        out
+    }
+
+    pub fn read_level(&self, key:LevelKey) -> Result<Option<ParticleVec>, String> {
+        let payload = match self.entries.get(&key) {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        if self.corrupt.contains_key(&key) {
+            return Err(format!("Region entry {} fails its checksum.", key));
+        }
+        decode_payload(&self.compression, self.level_length, key, payload).map(Some)
+    }
+
+    pub fn write_level(&mut self, key:LevelKey, data:&ParticleVec) {
+        self.entries.insert(key, self.compression.encode(data));
+        self.corrupt.remove(&key);
+    }
+
+    //returns whether there was an entry to remove
+    pub fn remove_level(&mut self, key:LevelKey) -> bool {
+        self.corrupt.remove(&key);
+        self.entries.remove(&key).is_some()
+    }
+}
+
+//Stores levels in region files within one directory, each file holding up to REGION_LENGTH^3 levels of a single LOD.
+//Rewritten levels leave their old payloads behind as dead space, and a file is written out compactly once that is
+//more than the payloads still in use.
+pub struct RegionStorage {
+    pub directory:PathBuf,
+    pub level_length:u64,
+    pub compression:LevelCompression,
+}
+
+impl RegionStorage {
+    pub fn new<P: Into<PathBuf>>(directory:P, level_length:u64) -> Result<Self, String> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|e| format!("Could not create region directory {}: {}", directory.display(), e))?;
+        Ok(Self {
+            directory:directory,
+            level_length:level_length,
+            compression:LevelCompression::default(),
+        })
+    }
+
+    pub fn region_path(&self, location:&VoxelLocation) -> PathBuf {
+        let coords = level_coords(location, self.level_length);
+        self.directory.join(format!("{}.{}.{}.{}.mlr", location.lod, coords.x/REGION_LENGTH, coords.y/REGION_LENGTH, coords.z/REGION_LENGTH))
+    }
+
+    fn key(&self, location:&VoxelLocation) -> Result<LevelKey, String> {
+        match level_key(location, self.level_length) {
+            Some(key) => Ok(key),
+            None => Err(format!("Level at {:?} is outside of the addressable range", location)),
+        }
+    }
+
+    //reads the whole file, see read_index for reading single levels
+    pub fn read_region(&self, path:&Path) -> Result<Option<RegionFile>, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind()==ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Could not read region file {}: {}", path.display(), e)),
+        };
+        let mut region = RegionFile::read(&data).map_err(|msg| format!("Region file {} is corrupt: {}", path.display(), msg))?;
+        if region.level_length!=self.level_length {
+            return Err(format!("Region file {} holds levels of length {}, but this storage uses {}", path.display(), region.level_length, self.level_length));
+        }
+        region.compression = self.compression.clone();
+        Ok(Some(region))
+    }
+
+    pub fn write_region(&self, path:&Path, region:&RegionFile) -> Result<(), String> {
+        //write beside the target and rename over it, so a crash mid-save never leaves a truncated region behind
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, region.write()).map_err(|e| format!("Could not write region file {}: {}", temp_path.display(), e))?;
+        fs::rename(&temp_path, path).map_err(|e| format!("Could not move region file into place at {}: {}", path.display(), e))
+    }
+
+    fn open_region(&self, path:&Path, write:bool) -> Result<Option<File>, String> {
+        match OpenOptions::new().read(true).write(write).create(write).truncate(false).open(path) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) if e.kind()==ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Could not open region file {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn read_index(&self, file:&mut File, path:&Path) -> Result<RegionIndex, String> {
+        let mut data = vec![0; REGION_INDEX_LEN];
+        file.seek(SeekFrom::Start(0)).and_then(|_start| file.read_exact(&mut data))
+            .map_err(|e| format!("Could not read the index of region file {}: {}", path.display(), e))?;
+        let index = RegionIndex::read(&data).map_err(|msg| format!("Region file {} is corrupt: {}", path.display(), msg))?;
+        if index.level_length!=self.level_length {
+            return Err(format!("Region file {} holds levels of length {}, but this storage uses {}", path.display(), index.level_length, self.level_length));
+        }
+        Ok(index)
+    }
+
+    fn write_at(file:&mut File, path:&Path, position:SeekFrom, data:&[u8]) -> Result<u64, String> {
+        file.seek(position)
+            .and_then(|offset| file.write_all(data).map(|()| offset))
+            .map_err(|e| format!("Could not write to region file {}: {}", path.display(), e))
+    }
+
+    fn patch_slot(file:&mut File, path:&Path, index:&RegionIndex, slot:usize) -> Result<(), String> {
+        let mut data = vec![];
+        RegionIndex::write_slot(index.slots[slot].as_ref(), &mut data);
+        Self::write_at(file, path, SeekFrom::Start((REGION_HEADER_LEN+slot*REGION_SLOT_LEN) as u64), &data).map(|_offset| ())
+    }
+
+    //writes the file out compactly once rewritten and removed levels have left more dead space than there are live payloads
+    fn compact_if_sparse(&self, file:&mut File, path:&Path, index:&RegionIndex) -> Result<(), String> {
+        let file_len = file.metadata().map_err(|e| format!("Could not read region file {}: {}", path.display(), e))?.len();
+        let live_bytes = index.live_bytes();
+        if file_len.saturating_sub(REGION_INDEX_LEN as u64+live_bytes)<=live_bytes {
+            return Ok(());
+        }
+        match self.read_region(path)? {
+            Some(region) => self.write_region(path, &region),
+            None => Ok(()),
+        }
+    }
+}
+
+impl LevelStorage for RegionStorage {
+    //reads the index and then only the payload of this level
+    fn read_level(&self, location:&VoxelLocation) -> Result<Option<ParticleVec>, String> {
+        let key = self.key(location)?;
+        let path = self.region_path(location);
+        let mut file = match self.open_region(&path, false)? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let slot = match self.read_index(&mut file, &path)?.slots[region_slot(key)] {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        if slot.key!=key {
+            return Err(format!("Region file {} holds level {} where level {} belongs", path.display(), slot.key, key));
+        }
+        let mut payload = vec![0; slot.length as usize];
+        file.seek(SeekFrom::Start(slot.offset)).and_then(|_offset| file.read_exact(&mut payload))
+            .map_err(|e| format!("Could not read region entry {} from {}: {}", key, path.display(), e))?;
+        if crc32(&payload)!=slot.checksum {
+            return Err(format!("Region entry {} fails its checksum.", key));
+        }
+        decode_payload(&self.compression, self.level_length, key, &payload).map(Some)
+    }
+
+    fn write_level(&mut self, location:&VoxelLocation, data:&ParticleVec) -> Result<(), String> {
+        self.write_levels(&[(location.clone(), data)])
+    }
+
+    //reads every region file in the directory, so this is meant for checks rather than regular use
+    fn corrupt_levels(&mut self) -> Result<Vec<VoxelLocation>, String> {
+        let entries = fs::read_dir(&self.directory).map_err(|e| format!("Could not list region directory {}: {}", self.directory.display(), e))?;
+        let mut corrupt = vec![];
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Could not list region directory {}: {}", self.directory.display(), e))?.path();
+            if path.extension().is_none_or(|extension| extension!="mlr") {
+                continue;
+            }
+            if let Some(region) = self.read_region(&path)? {
+                corrupt.extend(region.corrupt.keys().filter_map(|key| level_key_origin(*key, self.level_length)));
+            }
+        }
+        corrupt.sort_by_key(|location| level_key(location, self.level_length));
+        Ok(corrupt)
+    }
+
+    //an emptied region file is deleted
+    fn remove_level(&mut self, location:&VoxelLocation) -> Result<bool, String> {
+        let key = self.key(location)?;
+        let path = self.region_path(location);
+        let mut file = match self.open_region(&path, true)? {
+            Some(file) => file,
+            None => return Ok(false),
+        };
+        let mut index = self.read_index(&mut file, &path)?;
+        let slot = region_slot(key);
+        if index.slots[slot].is_none_or(|stored| stored.key!=key) {
+            return Ok(false);
+        }
+        index.slots[slot] = None;
+        if index.slots.iter().all(|slot| slot.is_none()) {
+            drop(file);
+            fs::remove_file(&path).map_err(|e| format!("Could not remove region file {}: {}", path.display(), e))?;
+            return Ok(true);
+        }
+        Self::patch_slot(&mut file, &path, &index, slot)?;
+        self.compact_if_sparse(&mut file, &path, &index)?;
+        Ok(true)
+    }
+
+    //Appends the new payloads of each region, and only then patches their slots, so a crash part way through leaves
+    //every slot pointing at either its old payload or its new one.
+    fn write_levels(&mut self, levels:&[(VoxelLocation, &ParticleVec)]) -> Result<(), String> {
+        let mut regions:BTreeMap<PathBuf, Vec<(LevelKey, &ParticleVec)>> = BTreeMap::new();
+        for (location, data) in levels {
+            regions.entry(self.region_path(location)).or_default().push((self.key(location)?, *data));
+        }
+        for (path, region_levels) in regions {
+            let mut file = self.open_region(&path, true)?.expect("Opening to write creates the file");
+            let file_len = file.metadata().map_err(|e| format!("Could not read region file {}: {}", path.display(), e))?.len();
+            let mut index = if file_len==0 {
+                let index = RegionIndex::new(self.level_length);
+                let mut data = vec![];
+                index.write(&mut data);
+                Self::write_at(&mut file, &path, SeekFrom::Start(0), &data)?;
+                index
+            } else {
+                self.read_index(&mut file, &path)?
+            };
+            let mut written = vec![];
+            for (key, data) in region_levels {
+                let payload = self.compression.encode(data);
+                let offset = Self::write_at(&mut file, &path, SeekFrom::End(0), &payload)?;
+                index.slots[region_slot(key)] = Some(RegionSlot {
+                    key:key,
+                    offset:offset,
+                    length:payload.len() as u64,
+                    checksum:crc32(&payload),
+                });
+                written.push(region_slot(key));
+            }
+            for slot in written {
+                Self::patch_slot(&mut file, &path, &index, slot)?;
+            }
+            self.compact_if_sparse(&mut file, &path, &index)?;
+        }
+        Ok(())
+    }
+}
\ No newline at end of file
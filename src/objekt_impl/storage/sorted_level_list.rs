@@ -1,41 +1,118 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        RwLock,
+    },
+};
+
 use crate::{
-    math::vectors::VoxelLocation,
+    math::{
+        hashing::mix64,
+        octree_math::LevelKey,
+        vectors::VoxelLocation,
+    },
     objekt_impl::storage::hybrid_octree::Level,
 };
 
+pub const LEVEL_SHARD_COUNT:usize = 16;
+
+//a level that was not stored, along with whichever level was kept under its key, if any
+pub type RejectedLevel = Box<(SortedLevel, Option<Arc<SortedLevel>>)>;
+
 pub struct SortedLevel {
     pub level: Level,//inner data
     pub location: VoxelLocation,//the location of the voxel on the lowest position on each axis that is still within this level. Remember that VoxelLocations are always global, not local/relative.
-    pub ordinal:u64,//how to position this level relative to other levels
+    pub ordinal:LevelKey,//how to position this level relative to other levels, see octree_math::level_key
 }
 
+//Levels keyed by level key, split over shards that are locked independently, so inserting a level only blocks lookups within its own shard.
+//Shard locks are only ever held for the map operation itself, never while a Level's contents are locked, which rules out lock order deadlocks.
+//An unloaded level stays behind with no Particles (see LevelContents::loaded) as long as anyone holds on to its entry, and
+//is removed by remove_unused once nobody does. Inserts only go through if the entry is still the one the caller saw, and
+//a removed entry counts as changed, so a load that raced an unload and a reload of the same level never puts back data
+//that is out of date. Loads hold on to an entry, if need be one they put in place themselves, for as long as they run.
 pub struct SortedLevelList {
-    pub data:Vec<SortedLevel>,
+    shards:Vec<RwLock<BTreeMap<LevelKey, Arc<SortedLevel>>>>,
+}
+
+impl Default for SortedLevelList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SortedLevelList {
     pub fn new() -> Self {
         Self {
-            data:Vec::new(),
+            shards:(0..LEVEL_SHARD_COUNT).map(|_i| RwLock::new(BTreeMap::new())).collect(),
+        }
+    }
+
+    //neighbouring levels have neighbouring keys, so they are hashed to spread them over the shards
+    fn shard(&self, key:LevelKey) -> &RwLock<BTreeMap<LevelKey, Arc<SortedLevel>>> {
+        &self.shards[(mix64(key as u64^(key>>64) as u64) as usize)%LEVEL_SHARD_COUNT]
+    }
+
+    pub fn get(&self, key:LevelKey) -> Option<Arc<SortedLevel>> {
+        self.shard(key).read().expect("Could not lock level shard for read access").get(&key).cloned()
+    }
+
+    //Stores the level, but only if the entry under its key is still what the caller last saw: `replacing` (None for no entry).
+    //Otherwise someone else got there first and their level is kept. Returns whichever level ends up stored, which is
+    //None if the entry the caller saw has been removed since.
+    pub fn insert(&self, level:SortedLevel, replacing:Option<&Arc<SortedLevel>>) -> Option<Arc<SortedLevel>> {
+        match self.try_insert(level, replacing) {
+            Ok(level) => Some(level),
+            Err(rejected) => rejected.1,
         }
     }
 
-    fn get_recur(&self, pos:u64, lower_i:usize, upper_e:usize)->(bool, usize) {
-        if upper_e==lower_i {
-            return (false, lower_i)
+    //like insert, but hands the level back along with the one that was kept when it is not stored
+    pub fn try_insert(&self, level:SortedLevel, replacing:Option<&Arc<SortedLevel>>) -> Result<Arc<SortedLevel>, RejectedLevel> {
+        let mut shard = self.shard(level.ordinal).write().expect("Could not lock level shard for write access");
+        let current = shard.get(&level.ordinal);
+        let unchanged = match (current, replacing) {
+            (Some(current), Some(replacing)) => Arc::ptr_eq(current, replacing),
+            (current, replacing) => current.is_none() && replacing.is_none(),
+        };
+        if !unchanged {
+            return Err(Box::new((level, current.cloned())));
         }
-        let check_index = (upper_e-lower_i)/2+lower_i;
-        let check = self.data[check_index].ordinal;
-        if pos<check {
-            self.get_recur(pos, lower_i, check_index)
-        } else if pos>check {
-            self.get_recur(pos, check_index+1, upper_e)
-        } else {
-            (true, check_index)
+        let level = Arc::new(level);
+        shard.insert(level.ordinal, level.clone());
+        Ok(level)
+    }
+
+    //Removes the entry under key if its level is unloaded and nobody outside of the list holds on to it, returning
+    //whether it did. Holding the shard's write lock keeps anyone from taking hold of the entry in the meantime, and
+    //nobody can have its contents locked without holding on to it.
+    pub fn remove_unused(&self, key:LevelKey) -> bool {
+        let mut shard = self.shard(key).write().expect("Could not lock level shard for write access");
+        let unused = shard.get(&key).is_some_and(|current| {
+            Arc::strong_count(current)==1 && current.level.contents.try_read().is_ok_and(|contents| !contents.loaded && !contents.dirty)
+        });
+        if unused {
+            shard.remove(&key);
         }
+        unused
+    }
+
+    //counts unloaded levels too
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().expect("Could not lock level shard for read access").len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len()==0
     }
 
-    pub fn get(&self, pos:u64) -> (bool,usize) {
-        self.get_recur(pos, 0, self.data.len())
+    //every level in level key order, as of when each shard was read
+    pub fn levels(&self) -> Vec<Arc<SortedLevel>> {
+        let mut levels:Vec<Arc<SortedLevel>> = self.shards.iter()
+            .flat_map(|shard| shard.read().expect("Could not lock level shard for read access").values().cloned().collect::<Vec<_>>())
+            .collect();
+        levels.sort_by_key(|sorted_level| sorted_level.ordinal);
+        levels
     }
 }
\ No newline at end of file
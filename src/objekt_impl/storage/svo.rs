@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    math::{
+        octree_math::{
+            voxel_min_corner,
+            voxel_size,
+            VoxelRange,
+        },
+        shapes::Aabb,
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        particle::MATERIAL_COUNT,
+        raycast::{
+            Ray,
+            RaycastHit,
+            STEP_EPSILON,
+        },
+    },
+    utils::binary::{
+        ByteReader,
+        write_u16,
+        write_u32,
+        write_u64,
+    },
+};
+
+pub const SVO_MAGIC:&'static [u8;4] = b"MSVO";
+pub const SVO_FORMAT_VERSION:u32 = 1;
+pub const SVO_BRICK_SIZE:u64 = 4;//voxels per axis of a brick
+pub const SVO_BRICK_VOXELS:usize = (SVO_BRICK_SIZE*SVO_BRICK_SIZE*SVO_BRICK_SIZE) as usize;
+pub const SVO_EMPTY_VOXEL:u16 = u16::MAX;
+pub const SVO_MAX_DEPTH:u32 = 19;//the largest depth whose cube has no more than u64::MAX voxels
+const CHILD_INDEX_MASK:u32 = (1<<30)-1;
+
+//What one of the eight child words of a node points at, packed into a u32 with the kind in the top two bits:
+//0 for empty space, 1 for a node, 2 for a brick and 3 for space filled by one material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SvoChild {
+    Empty,
+    Node(u32),//index into the node pool
+    Brick(u32),//index of the brick in the brick pool
+    Uniform(u16),//material filling all of the child
+}
+
+impl SvoChild {
+    pub fn encode(&self) -> u32 {
+        match self {
+            SvoChild::Empty => 0,
+            SvoChild::Node(index) => 1<<30 | index,
+            SvoChild::Brick(index) => 2<<30 | index,
+            SvoChild::Uniform(material) => 3<<30 | *material as u32,
+        }
+    }
+
+    pub fn decode(word:u32) -> Result<Self, String> {
+        let payload = word&CHILD_INDEX_MASK;
+        match word>>30 {
+            0 if payload==0 => Ok(SvoChild::Empty),
+            1 => Ok(SvoChild::Node(payload)),
+            2 => Ok(SvoChild::Brick(payload)),
+            3 if (payload as u64)<MATERIAL_COUNT => Ok(SvoChild::Uniform(payload as u16)),
+            _ => Err(format!("{:#x} is not an SVO child", word)),
+        }
+    }
+}
+
+//A sparse voxel octree over a cube of SVO_BRICK_SIZE << depth voxels at origin.lod, made for ray marching on the GPU.
+//Nodes are eight child words each, in octant order (x in bit 0, y in bit 1 and z in bit 2 of the octant), and the root is a
+//child word of its own. Children that are all empty or all one material are collapsed into a single word, so only
+//the surface needs nodes, and the nodes at depth end in bricks of dominant materials stored in index_in_level order.
+//Nodes always come after their parent in the pool.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Svo {
+    pub origin:VoxelLocation,//lowest voxel of the cube
+    pub depth:u32,//node levels above the bricks
+    pub root:u32,
+    pub nodes:Vec<[u32;8]>,
+    pub bricks:Vec<u16>,//SVO_BRICK_VOXELS dominant materials per brick, SVO_EMPTY_VOXEL where empty
+}
+
+fn octant_offset(octant:usize, half:u64) -> [u64;3] {
+    [(octant as u64&1)*half, (octant as u64>>1&1)*half, (octant as u64>>2&1)*half]
+}
+
+//A child while the tree is put together from the bricks up. Empty children are None.
+enum SvoCell {
+    Uniform(u16),
+    Brick(Vec<u16>),
+    Node(Vec<Option<SvoCell>>),//in octant order
+}
+
+//sets a voxel, given relative to the origin of the SVO, in the brick map taken by Svo::from_bricks
+fn set_brick_voxel(bricks:&mut BTreeMap<[u64;3], Vec<u16>>, local:[u64;3], material:u16) {
+    let brick = bricks.entry(local.map(|coord| coord/SVO_BRICK_SIZE)).or_insert_with(|| vec![SVO_EMPTY_VOXEL; SVO_BRICK_VOXELS]);
+    let [x, y, z] = local.map(|coord| coord%SVO_BRICK_SIZE);
+    brick[((x*SVO_BRICK_SIZE+y)*SVO_BRICK_SIZE+z) as usize] = material;
+}
+
+impl Svo {
+    //voxels per axis of the cube
+    pub fn side(&self) -> u64 {
+        SVO_BRICK_SIZE<<self.depth
+    }
+
+    pub fn brick_count(&self) -> usize {
+        self.bricks.len()/SVO_BRICK_VOXELS
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let min = voxel_min_corner(&self.origin);
+        let extent = (self.side()*voxel_size(self.origin.lod)) as f64;
+        Aabb::new(min, [min[0]+extent, min[1]+extent, min[2]+extent])
+    }
+
+    //Builds the tree from the dominant material of every voxel of the cube, given x-major like levels.
+    pub fn from_grid(origin:VoxelLocation, depth:u32, grid:&[u16]) -> Result<Self, String> {
+        if depth>SVO_MAX_DEPTH {
+            return Err(format!("SVO depth {} exceeds {}", depth, SVO_MAX_DEPTH));
+        }
+        let side = SVO_BRICK_SIZE<<depth;
+        let voxels = side.checked_pow(3).ok_or_else(|| format!("An SVO of depth {} has too many voxels for a grid", depth))?;
+        if grid.len() as u64!=voxels {
+            return Err(format!("An SVO of depth {} needs {} voxels, but was given {}", depth, voxels, grid.len()));
+        }
+        let mut bricks = BTreeMap::new();
+        for (cell, material) in grid.iter().enumerate() {
+            let cell = cell as u64;
+            if *material!=SVO_EMPTY_VOXEL {
+                set_brick_voxel(&mut bricks, [cell/(side*side), cell/side%side, cell%side], *material);
+            }
+        }
+        Self::from_bricks(origin, depth, bricks)
+    }
+
+    //Builds the tree from the bricks holding any material, keyed by their position in bricks from the origin, each with
+    //the dominant materials of its voxels x-major. Bricks that are left out are empty. The tree is put together one
+    //level at a time from the bricks up, so it takes memory for the surface rather than for the whole cube.
+    pub fn from_bricks(origin:VoxelLocation, depth:u32, bricks:BTreeMap<[u64;3], Vec<u16>>) -> Result<Self, String> {
+        if depth>SVO_MAX_DEPTH {
+            return Err(format!("SVO depth {} exceeds {}", depth, SVO_MAX_DEPTH));
+        }
+        let mut cells = BTreeMap::new();
+        for (coords, brick) in bricks {
+            if coords.iter().any(|coord| *coord>=1<<depth) {
+                return Err(format!("Brick {:?} lies outside of an SVO of depth {}", coords, depth));
+            }
+            if brick.len()!=SVO_BRICK_VOXELS {
+                return Err(format!("Brick {:?} holds {} voxels, expected {}", coords, brick.len(), SVO_BRICK_VOXELS));
+            }
+            if let Some(material) = brick.iter().find(|material| **material!=SVO_EMPTY_VOXEL && **material as u64>=MATERIAL_COUNT) {
+                return Err(format!("Material {} is out of range", material));
+            }
+            let first = brick[0];
+            let cell = if brick.iter().any(|material| *material!=first) {
+                SvoCell::Brick(brick)
+            } else if first!=SVO_EMPTY_VOXEL {
+                SvoCell::Uniform(first)
+            } else {
+                continue;
+            };
+            cells.insert(coords, cell);
+        }
+        for _level in 0..depth {
+            let mut parents:BTreeMap<[u64;3], Vec<Option<SvoCell>>> = BTreeMap::new();
+            for (coords, cell) in cells {
+                let octant = (0..3).map(|axis| ((coords[axis]&1) as usize)<<axis).sum::<usize>();
+                let children = parents.entry([coords[0]>>1, coords[1]>>1, coords[2]>>1]).or_insert_with(|| (0..8).map(|_| None).collect());
+                children[octant] = Some(cell);
+            }
+            cells = parents.into_iter().map(|(coords, children)| {
+                let uniform = match &children[0] {
+                    Some(SvoCell::Uniform(first)) if children.iter().all(|child| matches!(child, Some(SvoCell::Uniform(material)) if material==first)) => Some(*first),
+                    _ => None,
+                };
+                let cell = match uniform {
+                    Some(material) => SvoCell::Uniform(material),
+                    None => SvoCell::Node(children),
+                };
+                (coords, cell)
+            }).collect();
+        }
+        let mut svo = Self {
+            origin:origin,
+            depth:depth,
+            root:0,
+            nodes:vec![],
+            bricks:vec![],
+        };
+        svo.root = svo.push_cell(cells.remove(&[0; 3]))?.encode();
+        Ok(svo)
+    }
+
+    //adds the cell and everything below it to the pools, parents first
+    fn push_cell(&mut self, cell:Option<SvoCell>) -> Result<SvoChild, String> {
+        match cell {
+            None => Ok(SvoChild::Empty),
+            Some(SvoCell::Uniform(material)) => Ok(SvoChild::Uniform(material)),
+            Some(SvoCell::Brick(brick)) => {
+                let index = self.brick_count();
+                if index as u32>CHILD_INDEX_MASK {
+                    return Err(String::from("Too many bricks for an SVO"));
+                }
+                self.bricks.extend(brick);
+                Ok(SvoChild::Brick(index as u32))
+            }
+            Some(SvoCell::Node(children)) => {
+                let index = self.nodes.len();
+                if index as u32>CHILD_INDEX_MASK {
+                    return Err(String::from("Too many nodes for an SVO"));
+                }
+                self.nodes.push([0; 8]);
+                for (octant, child) in children.into_iter().enumerate() {
+                    self.nodes[index][octant] = self.push_cell(child)?.encode();
+                }
+                Ok(SvoChild::Node(index as u32))
+            }
+        }
+    }
+
+    //The material at a voxel given relative to the origin, with the lowest voxel and size of the cell it was found in:
+    //a collapsed child, or a single voxel of a brick.
+    pub fn lookup(&self, local:[u64;3]) -> (Option<u16>, [u64;3], u64) {
+        let mut child = SvoChild::decode(self.root).expect("SVO child words are checked when built or read");
+        let mut min = [0; 3];
+        let mut size = self.side();
+        loop {
+            match child {
+                SvoChild::Empty => return (None, min, size),
+                SvoChild::Uniform(material) => return (Some(material), min, size),
+                SvoChild::Brick(index) => {
+                    let [x, y, z] = [0, 1, 2].map(|axis| local[axis]-min[axis]);
+                    let voxel = ((x*SVO_BRICK_SIZE+y)*SVO_BRICK_SIZE+z) as usize;
+                    let material = self.bricks[index as usize*SVO_BRICK_VOXELS+voxel];
+                    let material = if material==SVO_EMPTY_VOXEL {
+                        None
+                    } else {
+                        Some(material)
+                    };
+                    return (material, local, 1);
+                }
+                SvoChild::Node(index) => {
+                    size/=2;
+                    let octant = (0..3).map(|axis| (((local[axis]-min[axis])>=size) as usize)<<axis).sum::<usize>();
+                    let offset = octant_offset(octant, size);
+                    min = [min[0]+offset[0], min[1]+offset[1], min[2]+offset[2]];
+                    child = SvoChild::decode(self.nodes[index as usize][octant]).expect("SVO child words are checked when built or read");
+                }
+            }
+        }
+    }
+
+    //None outside of the cube, otherwise the dominant material of the voxel, if any
+    pub fn get(&self, location:&VoxelLocation) -> Option<Option<u16>> {
+        if location.lod!=self.origin.lod {
+            return None;
+        }
+        let side = self.side();
+        let local = [
+            location.vec.x.checked_sub(self.origin.vec.x)?,
+            location.vec.y.checked_sub(self.origin.vec.y)?,
+            location.vec.z.checked_sub(self.origin.vec.z)?,
+        ];
+        if local.iter().any(|coord| *coord>=side) {
+            return None;
+        }
+        Some(self.lookup(local).0)
+    }
+
+    //Marches the ray through the cube, skipping each collapsed empty child or empty brick voxel it passes through whole,
+    //the way a GPU traversal would. Hits are reported like HybridOctree::raycast reports them.
+    pub fn raycast(&self, ray:&Ray, max_distance:f64) -> Option<RaycastHit> {
+        let length = (ray.direction[0]*ray.direction[0]+ray.direction[1]*ray.direction[1]+ray.direction[2]*ray.direction[2]).sqrt();
+        if length==0.0 || !length.is_finite() {
+            return None;
+        }
+        let ray = Ray::new(ray.origin, [ray.direction[0]/length, ray.direction[1]/length, ray.direction[2]/length]);
+        let bounds = self.bounds();
+        let (entry, entry_axis) = ray.entry(&bounds);
+        let end = max_distance.min(ray.exit(&bounds).0);
+        let mut crossing = if entry>0.0 {Some((entry, entry_axis))} else {None};
+        let mut distance = entry.max(0.0)+if entry>0.0 {STEP_EPSILON} else {0.0};
+        let size = voxel_size(self.origin.lod) as f64;
+        let side = self.side();
+        while distance<=end {
+            let point = ray.at(distance);
+            //float to int casts saturate, so rounding just outside of the cube lands on its border voxels
+            let local = [0, 1, 2].map(|axis| (((point[axis]-bounds.min[axis])/size).floor() as u64).min(side-1));
+            let (material, cell_min, cell_size) = self.lookup(local);
+            if let Some(material) = material {
+                let (hit_distance, normal) = match crossing {
+                    Some((hit_distance, axis)) => {
+                        let mut normal = [0;3];
+                        normal[axis] = if ray.direction[axis]>0.0 {-1} else {1};
+                        (hit_distance, normal)
+                    }
+                    None => (0.0, [0;3]),
+                };
+                if hit_distance>max_distance {
+                    return None;
+                }
+                return Some(RaycastHit {
+                    voxel:VoxelLocation {
+                        lod:self.origin.lod,
+                        vec:Vector3U64 {
+                            x:self.origin.vec.x+local[0],
+                            y:self.origin.vec.y+local[1],
+                            z:self.origin.vec.z+local[2],
+                        },
+                    },
+                    normal:normal,
+                    distance:hit_distance,
+                    position:ray.at(hit_distance),
+                    material:material,
+                });
+            }
+            let cell_start = [0, 1, 2].map(|axis| bounds.min[axis]+cell_min[axis] as f64*size);
+            let cell_end = [0, 1, 2].map(|axis| cell_start[axis]+(cell_size as f64)*size);
+            let (exit, exit_axis) = ray.exit(&Aabb::new(cell_start, cell_end));
+            crossing = Some((exit, exit_axis));
+            distance = exit.max(distance)+STEP_EPSILON;
+        }
+        None
+    }
+
+    //Layout: magic, format version, origin, depth, brick size, root, then the node count and nodes, then the brick count
+    //and bricks. Readers refuse versions they do not know rather than guessing at them.
+    pub fn write(&self) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend_from_slice(SVO_MAGIC);
+        write_u32(&mut out, SVO_FORMAT_VERSION);
+        self.origin.write(&mut out);
+        write_u32(&mut out, self.depth);
+        write_u32(&mut out, SVO_BRICK_SIZE as u32);
+        write_u32(&mut out, self.root);
+        write_u64(&mut out, self.nodes.len() as u64);
+        for node in &self.nodes {
+            for word in node {
+                write_u32(&mut out, *word);
+            }
+        }
+        write_u64(&mut out, self.brick_count() as u64);
+        for material in &self.bricks {
+            write_u16(&mut out, *material);
+        }
+        out
+    }
+
+    pub fn read(data:&[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(data);
+        if reader.read_slice(SVO_MAGIC.len())? != &SVO_MAGIC[..] {
+            return Err(String::from("Data is not an SVO."));
+        }
+        let version = reader.read_u32()?;
+        if version!=SVO_FORMAT_VERSION {
+            return Err(format!("SVO format version {} is not supported, expected {}", version, SVO_FORMAT_VERSION));
+        }
+        let origin = VoxelLocation::read(&mut reader)?;
+        let depth = reader.read_u32()?;
+        if depth>SVO_MAX_DEPTH {
+            return Err(format!("SVO depth {} exceeds {}", depth, SVO_MAX_DEPTH));
+        }
+        let brick_size = reader.read_u32()?;
+        if brick_size as u64!=SVO_BRICK_SIZE {
+            return Err(format!("SVO bricks are {} voxels wide, expected {}", brick_size, SVO_BRICK_SIZE));
+        }
+        let root = reader.read_u32()?;
+        //counts are checked against the data left, so they cannot ask for more memory than was sent
+        let node_count = reader.read_u64()? as usize;
+        if node_count>reader.remaining()/32 {
+            return Err(format!("SVO claims {} nodes, more than the data holds", node_count));
+        }
+        let mut nodes = Vec::with_capacity(node_count);
+        for _node in 0..node_count {
+            let mut node = [0; 8];
+            for word in &mut node {
+                *word = reader.read_u32()?;
+            }
+            nodes.push(node);
+        }
+        let brick_count = reader.read_u64()? as usize;
+        if brick_count>reader.remaining()/(SVO_BRICK_VOXELS*2) {
+            return Err(format!("SVO claims {} bricks, more than the data holds", brick_count));
+        }
+        let mut bricks = Vec::with_capacity(brick_count*SVO_BRICK_VOXELS);
+        for _voxel in 0..brick_count*SVO_BRICK_VOXELS {
+            bricks.push(reader.read_u16()?);
+        }
+        if reader.remaining()!=0 {
+            return Err(format!("SVO has {} trailing bytes.", reader.remaining()));
+        }
+        let svo = Self {
+            origin:origin,
+            depth:depth,
+            root:root,
+            nodes:nodes,
+            bricks:bricks,
+        };
+        svo.validate()?;
+        Ok(svo)
+    }
+
+    //Checks that every node is reached exactly once, at a level above the bricks, and that bricks sit right below
+    //the deepest nodes, so lookups and traversals can trust every child word.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(material) = self.bricks.iter().find(|material| **material!=SVO_EMPTY_VOXEL && **material as u64>=MATERIAL_COUNT) {
+            return Err(format!("SVO brick holds material {}, which is out of range", material));
+        }
+        let mut reached = vec![false; self.nodes.len()];
+        let mut pending = vec![(self.root, 0u32)];
+        while let Some((word, level)) = pending.pop() {
+            match SvoChild::decode(word)? {
+                SvoChild::Empty | SvoChild::Uniform(_) => {}
+                SvoChild::Brick(index) => {
+                    if level!=self.depth {
+                        return Err(format!("SVO brick {} is {} levels down, expected {}", index, level, self.depth));
+                    }
+                    if index as usize>=self.brick_count() {
+                        return Err(format!("SVO brick {} is out of range", index));
+                    }
+                }
+                SvoChild::Node(index) => {
+                    if level>=self.depth {
+                        return Err(format!("SVO node {} is {} levels down, where only bricks may be", index, level));
+                    }
+                    let reached = reached.get_mut(index as usize).ok_or_else(|| format!("SVO node {} is out of range", index))?;
+                    if *reached {
+                        return Err(format!("SVO node {} is reached more than once", index));
+                    }
+                    *reached = true;
+                    pending.extend(self.nodes[index as usize].iter().map(|word| (*word, level+1)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(path:&Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read SVO file {}: {}", path.display(), e))?;
+        Self::read(&data).map_err(|msg| format!("Could not parse SVO file {}: {}", path.display(), msg))
+    }
+
+    pub fn save(&self, path:&Path) -> Result<(), String> {
+        fs::write(path, self.write()).map_err(|e| format!("Could not write SVO file {}: {}", path.display(), e))
+    }
+}
+
+impl HybridOctree {
+    //Builds an SVO over the cube of SVO_BRICK_SIZE << depth voxels at origin from their dominant materials, loading
+    //the levels it covers as needed. Voxels holding only ignored materials count as empty.
+    pub fn build_svo(&self, origin:&VoxelLocation, depth:u32, ignored:&[u16]) -> Result<Svo, String> {
+        if depth>SVO_MAX_DEPTH {
+            return Err(format!("SVO depth {} exceeds {}", depth, SVO_MAX_DEPTH));
+        }
+        let side = SVO_BRICK_SIZE<<depth;
+        let last = [origin.vec.x, origin.vec.y, origin.vec.z].map(|coord| coord.checked_add(side-1));
+        let last = match last {
+            [Some(x), Some(y), Some(z)] => [x, y, z],
+            _ => return Err(format!("An SVO of depth {} at {:?} reaches beyond the addressable range", depth, origin)),
+        };
+        let range = VoxelRange {
+            lod:origin.lod,
+            first:origin.vec.clone(),
+            last:Vector3U64 {
+                x:last[0],
+                y:last[1],
+                z:last[2],
+            },
+        };
+        let mut bricks = BTreeMap::new();
+        self.visit_range(&range, |location, index, contents| {
+            if let Some(material) = contents.data.material[index].dominant(ignored) {
+                set_brick_voxel(&mut bricks, [location.vec.x-origin.vec.x, location.vec.y-origin.vec.y, location.vec.z-origin.vec.z], material);
+            }
+        })?;
+        Svo::from_bricks(origin.clone(), depth, bricks)
+    }
+}
\ No newline at end of file
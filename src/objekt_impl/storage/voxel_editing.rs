@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    math::{
+        octree_math::{
+            index_in_level,
+            level_key,
+            level_key_origin,
+            level_may_overlap,
+            voxel_center,
+            voxel_min_corner,
+            voxel_range,
+            voxel_size,
+            LevelKey,
+            VoxelRange,
+        },
+        shapes::{
+            Aabb,
+            Sdf,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::{
+        hybrid_octree::HybridOctree,
+        material_composition::MaterialComposition,
+        particle::{
+            one_hot_material,
+            MATERIAL_COUNT,
+        },
+    },
+};
+
+//what an edit does to each voxel whose center lies inside the edited shape
+#[derive(Clone, Debug, PartialEq)]
+pub enum Brush {
+    Fill(u16),//replaces the voxel with a single material at full weight
+    Carve,//empties the voxel
+    Replace {//moves the weight of one material onto another, leaving other materials alone
+        from:u16,
+        to:u16,
+    },
+}
+
+impl Brush {
+    fn validate(&self) -> Result<(), String> {
+        let materials = match self {
+            Brush::Fill(material) => vec![*material],
+            Brush::Carve => vec![],
+            Brush::Replace { from, to } => vec![*from, *to],
+        };
+        match materials.into_iter().find(|material| *material as u64>=MATERIAL_COUNT) {
+            Some(material) => Err(format!("Material {} does not exist, there are only {} materials", material, MATERIAL_COUNT)),
+            None => Ok(()),
+        }
+    }
+
+    //returns whether the weights changed
+    fn apply(&self, weights:&mut MaterialComposition) -> bool {
+        match self {
+            Brush::Fill(material) => {
+                let filled = one_hot_material(*material);
+                if *weights==filled {
+                    return false;
+                }
+                *weights = filled;
+                true
+            }
+            Brush::Carve => {
+                if weights.is_empty() {
+                    return false;
+                }
+                weights.clear();
+                true
+            }
+            Brush::Replace { from, to } => {
+                let weight = weights.weight(*from);
+                if weight==0 || from==to {
+                    return false;
+                }
+                weights.set_weight(*from, 0);
+                weights.set_weight(*to, weights.weight(*to).saturating_add(weight));
+                true
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EditReport {
+    pub touched_levels:Vec<VoxelLocation>,//origins of levels in which at least one voxel changed, in the order they were edited
+    pub loaded_levels:Vec<VoxelLocation>,//origins of levels the edit had to load first
+    pub changed_voxels:usize,
+}
+
+impl EditReport {
+    fn merge(&mut self, other:EditReport) {
+        self.touched_levels.extend(other.touched_levels);
+        self.loaded_levels.extend(other.loaded_levels);
+        self.changed_voxels+=other.changed_voxels;
+    }
+}
+
+//Edits work on a single LOD, and go through every level the shape's bounds overlap, loading the ones that are missing.
+//Changed levels are marked dirty, so they are written to storage before being evicted, and queued for downsampling into coarser LODs.
+impl HybridOctree {
+    pub fn edit_shape(&self, shape:&dyn Sdf, lod:u64, brush:&Brush) -> Result<EditReport, String> {
+        brush.validate()?;
+        if lod>=self.level_depth {
+            return Err(format!("Edit requested at LOD {}, but the octree only has {} LODs", lod, self.level_depth));
+        }
+        let bounds = shape.bounds();
+        //walking the levels of an unbounded shape would take practically forever
+        if !bounds.is_finite() {
+            return Err(format!("Edits need a shape with finite bounds, not {:?}", bounds));
+        }
+        let mut report = EditReport::default();
+        let range = match voxel_range(&bounds, lod, self.level_length) {
+            Some(range) => range,
+            None => return Ok(report),
+        };
+        for origin in range.level_origins(self.level_length) {
+            report.merge(self.edit_level(shape, &origin, &range, brush)?);
+        }
+        Ok(report)
+    }
+
+    fn edit_level(&self, shape:&dyn Sdf, origin:&VoxelLocation, range:&VoxelRange, brush:&Brush) -> Result<EditReport, String> {
+        let mut report = EditReport::default();
+        let level_length = self.level_length;
+        //skip levels without any voxel center inside the shape, rather than loading them for nothing
+        if !level_may_overlap(shape, origin, level_length) {
+            return Ok(report);
+        }
+        let range = match range.within_level(origin, level_length) {
+            Some(range) => range,
+            None => return Ok(report),
+        };
+        let (changed_voxels, loaded_now) = self.with_level_mut(origin, |contents| {
+            let mut changed_voxels = 0;
+            for location in range.voxels() {
+                if shape.distance(voxel_center(&location))>0.0 {
+                    continue;
+                }
+                let index = index_in_level(&location, level_length);
+                if brush.apply(&mut contents.data.material[index]) {
+                    changed_voxels+=1;
+                }
+            }
+            //while still locked, so the level cannot be evicted before its changes are marked for saving
+            if changed_voxels>0 {
+                contents.mark_changed();
+            }
+            changed_voxels
+        })?;
+        if loaded_now {
+            report.loaded_levels.push(origin.clone());
+        }
+        if changed_voxels>0 {
+            self.queue_downsample(origin);
+            report.touched_levels.push(origin.clone());
+            report.changed_voxels = changed_voxels;
+        }
+        Ok(report)
+    }
+
+    pub fn set_voxel_material(&self, location:&VoxelLocation, material:u16) -> Result<EditReport, String> {
+        let min = voxel_min_corner(location);
+        let size = voxel_size(location.lod) as f64;
+        self.fill_box(&Aabb::new(min, [min[0]+size, min[1]+size, min[2]+size]), location.lod, material)
+    }
+
+    //fills every voxel whose center lies within the box
+    pub fn fill_box(&self, bounds:&Aabb, lod:u64, material:u16) -> Result<EditReport, String> {
+        self.edit_shape(bounds, lod, &Brush::Fill(material))
+    }
+
+    pub fn fill_shape(&self, shape:&dyn Sdf, lod:u64, material:u16) -> Result<EditReport, String> {
+        self.edit_shape(shape, lod, &Brush::Fill(material))
+    }
+
+    pub fn carve_shape(&self, shape:&dyn Sdf, lod:u64) -> Result<EditReport, String> {
+        self.edit_shape(shape, lod, &Brush::Carve)
+    }
+
+    pub fn replace_material(&self, shape:&dyn Sdf, lod:u64, from:u16, to:u16) -> Result<EditReport, String> {
+        self.edit_shape(shape, lod, &Brush::Replace { from:from, to:to })
+    }
+
+    //Writes each composition into the voxel at its coordinates, for edits that do not follow a shape such as imported models.
+    //Voxels are grouped by level so each level is locked once, and a voxel given twice keeps the last composition.
+    pub fn set_voxels(&self, lod:u64, voxels:&[(Vector3U64, MaterialComposition)]) -> Result<EditReport, String> {
+        if lod>=self.level_depth {
+            return Err(format!("Edit requested at LOD {}, but the octree only has {} LODs", lod, self.level_depth));
+        }
+        let level_length = self.level_length;
+        let mut levels:BTreeMap<LevelKey, Vec<(usize, &MaterialComposition)>> = BTreeMap::new();
+        for (vec, material) in voxels {
+            let location = VoxelLocation {
+                lod:lod,
+                vec:vec.clone(),
+            };
+            let key = level_key(&location, level_length).ok_or_else(|| format!("Voxel at {:?} is outside of the addressable range", location))?;
+            levels.entry(key).or_default().push((index_in_level(&location, level_length), material));
+        }
+        let mut report = EditReport::default();
+        for (key, level_voxels) in levels {
+            let origin = level_key_origin(key, level_length).expect("Keys of voxels always have an origin");
+            let (changed_voxels, loaded_now) = self.with_level_mut(&origin, |contents| {
+                let mut changed = vec![false; contents.data.material.len()];
+                for (index, material) in level_voxels {
+                    changed[index] |= contents.data.material[index]!=*material;
+                    contents.data.material[index] = material.clone();
+                }
+                let changed_voxels = changed.iter().filter(|changed| **changed).count();
+                if changed_voxels>0 {
+                    contents.mark_changed();
+                }
+                changed_voxels
+            })?;
+            if loaded_now {
+                report.loaded_levels.push(origin.clone());
+            }
+            if changed_voxels>0 {
+                self.queue_downsample(&origin);
+                report.touched_levels.push(origin);
+                report.changed_voxels+=changed_voxels;
+            }
+        }
+        Ok(report)
+    }
+}
\ No newline at end of file
@@ -1,4 +1,5 @@
 pub mod controlling;
 pub mod networking;
 pub mod physics;
-pub mod rendering;
\ No newline at end of file
+pub mod rendering;
+pub mod streaming;
\ No newline at end of file
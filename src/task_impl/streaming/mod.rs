@@ -0,0 +1,435 @@
+use std::{
+    cmp::Ordering,
+    collections::{
+        BinaryHeap,
+        HashMap,
+        HashSet,
+    },
+    sync::{
+        Arc,
+        Condvar,
+        Mutex,
+        RwLock,
+    },
+    thread,
+    thread::JoinHandle,
+};
+
+use crate::{
+    concurrency::{
+        molecule_objekt::{
+            clone_objekt_in_list,
+            ObjektList,
+        },
+        synchronization_graph::{
+            SubmissionResult,
+            SynchronizationDependent,
+            TemplateSynchronizationNode,
+        },
+        tasks::task::{
+            Task,
+            TaskControlFlow,
+        },
+    },
+    math::{
+        octree_math::{
+            level_key,
+            voxel_min_corner,
+            voxel_size,
+            LevelKey,
+            MAX_LEVEL_COORD,
+        },
+        shapes::{
+            Aabb,
+            Sdf,
+        },
+        vectors::{
+            Vector3U64,
+            VoxelLocation,
+        },
+    },
+    objekt_impl::storage::hybrid_octree::{
+        HybridOctree,
+        HybridOctreeObjekt,
+    },
+};
+
+pub type ViewerList = Arc<RwLock<Vec<[f64;3]>>>;//positions in world units, see octree_math::voxel_min_corner
+type FailureMap = Arc<Mutex<HashMap<LevelKey, (VoxelLocation, String)>>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum JobKind {
+    Load,
+    Unload,
+}
+
+#[derive(Clone, Debug)]
+struct StreamingJob {
+    kind:JobKind,
+    origin:VoxelLocation,
+    key:LevelKey,
+    distance:f64,//to the nearest viewer
+}
+
+//BinaryHeap pops the greatest job first: every load before any unload, nearest loads first and farthest unloads first
+impl Ord for StreamingJob {
+    fn cmp(&self, other:&Self) -> Ordering {
+        match (self.kind, other.kind) {
+            (JobKind::Load, JobKind::Unload) => Ordering::Greater,
+            (JobKind::Unload, JobKind::Load) => Ordering::Less,
+            (JobKind::Load, JobKind::Load) => other.distance.total_cmp(&self.distance).then(other.key.cmp(&self.key)),
+            (JobKind::Unload, JobKind::Unload) => self.distance.total_cmp(&other.distance).then(self.key.cmp(&other.key)),
+        }
+    }
+}
+
+impl PartialOrd for StreamingJob {
+    fn partial_cmp(&self, other:&Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for StreamingJob {
+    fn eq(&self, other:&Self) -> bool {
+        self.cmp(other)==Ordering::Equal
+    }
+}
+
+impl Eq for StreamingJob {}
+
+#[derive(Default)]
+struct QueueState {
+    jobs:BinaryHeap<StreamingJob>,
+    in_flight:HashSet<LevelKey>,//taken by a worker and not finished yet
+    completed:u64,//jobs finished so far, so the tick can tell when the octree may have changed under it
+    stopping:bool,
+}
+
+#[derive(Default)]
+struct JobQueue {
+    state:Mutex<QueueState>,
+    wake:Condvar,
+}
+
+impl JobQueue {
+    //blocks until there is a job to take, or None once the workers are stopping
+    fn take(&self) -> Option<StreamingJob> {
+        let mut state = self.state.lock().expect("Could not lock streaming queue");
+        loop {
+            if state.stopping {
+                return None;
+            }
+            if let Some(job) = state.jobs.pop() {
+                state.in_flight.insert(job.key);
+                return Some(job);
+            }
+            state = self.wake.wait(state).expect("Could not lock streaming queue");
+        }
+    }
+
+    fn finish(&self, job:&StreamingJob) {
+        let mut state = self.state.lock().expect("Could not lock streaming queue");
+        state.in_flight.remove(&job.key);
+        state.completed+=1;
+    }
+}
+
+fn work(octree:Arc<HybridOctree>, queue:Arc<JobQueue>, failures:FailureMap) {
+    while let Some(job) = queue.take() {
+        let result = match job.kind {
+            JobKind::Load => octree.load_level(job.origin.clone()),
+            JobKind::Unload => octree.unload_level(job.origin.clone()).map(|_was_loaded| ()),
+        };
+        {
+            let mut failures = failures.lock().expect("Could not lock streaming failures");
+            match result {
+                Ok(()) => {
+                    failures.remove(&job.key);
+                }
+                Err(msg) => {
+                    failures.insert(job.key, (job.origin.clone(), format!("Could not {} level: {}", if job.kind==JobKind::Load {"load"} else {"unload"}, msg)));
+                }
+            }
+        }
+        queue.finish(&job);
+    }
+}
+
+//Keeps levels loaded around a set of viewers. A level at some LOD is wanted while any viewer is within radii[lod] of it,
+//LODs past the end of radii are left alone. Each tick only works out what is missing and what is no longer wanted;
+//loading, generating and writing levels out happens on worker threads, nearest levels first, and workers publish
+//straight into the octree. Wanted levels that another system unloads are loaded again, unless the octree's memory budget
+//can not hold every wanted level, in which case only the nearest ones that fit are kept loaded, see over_budget.
+pub struct LevelStreaming {
+    pub name: String,
+    pub submitted_node_list: Vec<String>,
+    pub octree_name: String,//the HybridOctreeObjekt to stream, looked up in init
+    pub viewers: ViewerList,
+    pub radii: Vec<f64>,//in world units, indexed by LOD
+    pub unload_margin: f64,//how much farther than its radius a level has to be before it is unloaded, so levels on the edge do not flicker in and out
+    pub worker_count: usize,
+    octree: Option<Arc<HybridOctree>>,
+    queue: Arc<JobQueue>,
+    workers: Vec<JoinHandle<()>>,
+    failures: FailureMap,//why the last job on each level failed, until a later job on it succeeds
+    panicked_workers: usize,
+    over_budget: usize,
+    last_viewers: Vec<[f64;3]>,
+    last_completed: Option<u64>,
+}
+
+impl LevelStreaming {
+    pub fn new(name:String, octree_name:String, viewers:ViewerList, radii:Vec<f64>, worker_count:usize) -> Self {
+        Self {
+            name:name,
+            submitted_node_list:vec![],
+            octree_name:octree_name,
+            viewers:viewers,
+            radii:radii,
+            unload_margin:0.0,
+            worker_count:worker_count,
+            octree:None,
+            queue:Arc::new(JobQueue::default()),
+            workers:vec![],
+            failures:Arc::new(Mutex::new(HashMap::new())),
+            panicked_workers:0,
+            over_budget:0,
+            last_viewers:vec![],
+            last_completed:None,
+        }
+    }
+
+    //levels queued or being worked on
+    pub fn pending(&self) -> usize {
+        let state = self.queue.state.lock().expect("Could not lock streaming queue");
+        state.jobs.len()+state.in_flight.len()
+    }
+
+    //levels whose last load or unload failed, in level key order, with the reason. Wanted levels are retried on later ticks.
+    pub fn failures(&self) -> Vec<(VoxelLocation, String)> {
+        let failures = self.failures.lock().expect("Could not lock streaming failures");
+        let mut keys:Vec<&LevelKey> = failures.keys().collect();
+        keys.sort();
+        keys.into_iter().map(|key| failures[key].clone()).collect()
+    }
+
+    //workers that panicked and were found when the workers were last stopped, e.g. by init
+    pub fn panicked_workers(&self) -> usize {
+        self.panicked_workers
+    }
+
+    //wanted levels left out on the last tick for not fitting in the octree's memory budget, 0 while every wanted level fits
+    pub fn over_budget(&self) -> usize {
+        self.over_budget
+    }
+
+    fn stop_workers(&mut self) {
+        self.queue.state.lock().expect("Could not lock streaming queue").stopping = true;
+        self.queue.wake.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                self.panicked_workers+=1;
+            }
+        }
+        //a fresh queue, so anything left over from before is dropped along with the old workers
+        self.queue = Arc::new(JobQueue::default());
+        self.last_completed = None;
+    }
+
+    fn start_workers(&mut self, octree:Arc<HybridOctree>) {
+        for _i in 0..self.worker_count.max(1) {
+            let octree = octree.clone();
+            let queue = self.queue.clone();
+            let failures = self.failures.clone();
+            self.workers.push(thread::spawn(move || work(octree, queue, failures)));
+        }
+    }
+
+    //every level within reach of a viewer, keyed by level key, with the distance to the nearest viewer
+    fn wanted_levels(&self, octree:&HybridOctree, viewers:&[[f64;3]]) -> HashMap<LevelKey, (VoxelLocation, f64)> {
+        let mut wanted = HashMap::new();
+        for (lod, radius) in self.radii.iter().enumerate().take(octree.level_depth as usize) {
+            let lod = lod as u64;
+            let extent = (octree.level_length*voxel_size(lod)) as f64;
+            //a level ending exactly radius away is still wanted, so the range starts one level early when coord-radius falls on a boundary
+            let first = |coord:f64| (((coord-radius)/extent).ceil()-1.0).max(0.0) as u64;
+            let last = |coord:f64| (((coord+radius)/extent).floor().max(0.0) as u64).min(MAX_LEVEL_COORD);
+            for viewer in viewers {
+                if viewer[0]+radius<0.0 || viewer[1]+radius<0.0 || viewer[2]+radius<0.0 {
+                    continue;
+                }
+                for x in first(viewer[0])..=last(viewer[0]) {
+                    for y in first(viewer[1])..=last(viewer[1]) {
+                        for z in first(viewer[2])..=last(viewer[2]) {
+                            let origin = VoxelLocation {
+                                lod:lod,
+                                vec:Vector3U64 {
+                                    x:x*octree.level_length,
+                                    y:y*octree.level_length,
+                                    z:z*octree.level_length,
+                                },
+                            };
+                            let distance = level_bounds(&origin, extent).distance(*viewer).max(0.0);
+                            if distance>*radius {
+                                continue;
+                            }
+                            let key = match level_key(&origin, octree.level_length) {
+                                Some(key) => key,
+                                None => continue,
+                            };
+                            let entry = wanted.entry(key).or_insert((origin, distance));
+                            entry.1 = entry.1.min(distance);
+                        }
+                    }
+                }
+            }
+        }
+        wanted
+    }
+}
+
+fn level_bounds(origin:&VoxelLocation, extent:f64) -> Aabb {
+    let min = voxel_min_corner(origin);
+    Aabb::new(min, [min[0]+extent, min[1]+extent, min[2]+extent])
+}
+
+impl Drop for LevelStreaming {
+    fn drop(&mut self) {
+        self.stop_workers();
+    }
+}
+
+impl SynchronizationDependent for LevelStreaming {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn push_submitted_node_list(&mut self, name:String) {
+        self.submitted_node_list.push(name);
+    }
+
+    fn submit_node(&mut self, _node: Arc<Mutex<TemplateSynchronizationNode>>) -> SubmissionResult {
+        SubmissionResult::NotFound
+    }
+
+    fn submitted_node_list(&self) -> &Vec<String> {
+        &self.submitted_node_list
+    }
+
+    fn values_filled(&self) -> bool {
+        true
+    }
+}
+
+impl Task for LevelStreaming {
+    fn init(&mut self, objekt_list_lock: ObjektList) {
+        self.stop_workers();
+        let objekt_list = objekt_list_lock.lock().unwrap();
+        self.octree = clone_objekt_in_list::<HybridOctreeObjekt>(&objekt_list, &self.octree_name).map(|objekt| objekt.inner());
+        if let Some(octree) = self.octree.clone() {
+            self.start_workers(octree);
+        }
+    }
+
+    //never waits on a level: levels that are locked right now are simply looked at again on a later tick
+    fn tick(&mut self) -> TaskControlFlow {
+        let octree = match &self.octree {
+            Some(octree) => octree.clone(),
+            None => return TaskControlFlow::Stop(format!("Level streaming found no HybridOctreeObjekt named {}", self.octree_name)),
+        };
+        let viewers = self.viewers.read().expect("Could not lock viewer list").clone();
+        let completed = self.queue.state.lock().expect("Could not lock streaming queue").completed;
+        //nothing moved and no job finished, so the plan from last tick still stands
+        if viewers==self.last_viewers && Some(completed)==self.last_completed {
+            return TaskControlFlow::Continue;
+        }
+
+        let wanted = self.wanted_levels(&octree, &viewers);
+        let has_storage = octree.has_storage();
+        let mut jobs = BinaryHeap::new();
+        let mut busy = false;
+        //nearest first, along with the memory taken by the ones that are loaded
+        let mut nearest:Vec<(&LevelKey, &(VoxelLocation, f64))> = wanted.iter().collect();
+        nearest.sort_by(|a, b| (a.1).1.total_cmp(&(b.1).1).then(a.0.cmp(b.0)));
+        let mut levels = vec![];
+        for (key, (origin, distance)) in nearest {
+            let usage = match octree.levels.get(*key) {
+                Some(sorted_level) => match sorted_level.level.contents.try_read() {
+                    Ok(contents) => contents.loaded.then(|| contents.memory_usage()),
+                    Err(_) => {
+                        busy = true;
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            levels.push((key, origin, distance, usage));
+        }
+        //Levels past what the memory budget holds are not loaded, since loading them would only evict nearer wanted levels
+        //for those to be loaded again. Levels that are not loaded yet are assumed to take as much as the loaded ones on average.
+        let loaded_usage:Vec<usize> = levels.iter().filter_map(|level| level.3).collect();
+        let estimate = if loaded_usage.is_empty() {0} else {loaded_usage.iter().sum::<usize>()/loaded_usage.len()};
+        let memory_budget = octree.memory_budget();
+        let mut wanted_usage = 0;
+        self.over_budget = 0;
+        for (key, origin, distance, usage) in levels {
+            wanted_usage+=usage.unwrap_or(estimate);
+            if memory_budget.is_some_and(|memory_budget| wanted_usage>memory_budget) {
+                self.over_budget+=1;
+                continue;
+            }
+            //looking a kept level up counts as an access, so levels that are not kept are evicted before it
+            if usage.is_some() {
+                octree.find_level(origin);
+            } else {
+                jobs.push(StreamingJob {
+                    kind:JobKind::Load,
+                    origin:origin.clone(),
+                    key:*key,
+                    distance:*distance,
+                });
+            }
+        }
+        for sorted_level in octree.levels.levels() {
+            let lod = sorted_level.location.lod as usize;
+            if lod>=self.radii.len() || wanted.contains_key(&sorted_level.ordinal) {
+                continue;
+            }
+            let unloadable = match sorted_level.level.contents.try_read() {
+                Ok(contents) => contents.loaded && !contents.pinned && (has_storage || !contents.dirty),
+                Err(_) => {
+                    busy = true;
+                    continue;
+                }
+            };
+            if !unloadable {
+                continue;
+            }
+            let extent = (octree.level_length*voxel_size(sorted_level.location.lod)) as f64;
+            let bounds = level_bounds(&sorted_level.location, extent);
+            let distance = viewers.iter().map(|viewer| bounds.distance(*viewer).max(0.0)).fold(f64::INFINITY, f64::min);
+            if distance<=self.radii[lod]+self.unload_margin {
+                continue;
+            }
+            jobs.push(StreamingJob {
+                kind:JobKind::Unload,
+                origin:sorted_level.location.clone(),
+                key:sorted_level.ordinal,
+                distance:distance,
+            });
+        }
+
+        {
+            let mut state = self.queue.state.lock().expect("Could not lock streaming queue");
+            //the new plan replaces whatever was still queued, levels already being worked on are left to their worker
+            let in_flight = &state.in_flight;
+            let jobs:BinaryHeap<StreamingJob> = jobs.into_iter().filter(|job| !in_flight.contains(&job.key)).collect();
+            state.jobs = jobs;
+        }
+        self.queue.wake.notify_all();
+        self.last_viewers = viewers;
+        //levels skipped for being locked need another look even if nothing else changes
+        self.last_completed = if busy {None} else {Some(completed)};
+        TaskControlFlow::Continue
+    }
+}
\ No newline at end of file
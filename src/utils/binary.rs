@@ -0,0 +1,125 @@
+//little-endian helpers shared by every on-disk and over-the-wire format in the engine
+
+pub fn write_u8(out:&mut Vec<u8>, value:u8) {
+    out.push(value);
+}
+
+pub fn write_u16(out:&mut Vec<u8>, value:u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u32(out:&mut Vec<u8>, value:u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u64(out:&mut Vec<u8>, value:u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u128(out:&mut Vec<u8>, value:u128) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+//LEB128: seven bits per byte, low bits first, with the top bit set on every byte but the last
+pub fn write_varint(out:&mut Vec<u8>, mut value:u64) {
+    while value>=0x80 {
+        out.push((value as u8)|0x80);
+        value>>=7;
+    }
+    out.push(value as u8);
+}
+
+pub fn write_bytes(out:&mut Vec<u8>, value:&[u8]) {
+    write_u64(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+pub fn write_str(out:&mut Vec<u8>, value:&str) {
+    write_bytes(out, value.as_bytes());
+}
+
+pub struct ByteReader<'a> {
+    data:&'a [u8],
+    offset:usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data:&'a [u8]) -> Self {
+        Self {
+            data:data,
+            offset:0,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()-self.offset
+    }
+
+    pub fn read_slice(&mut self, len:usize) -> Result<&'a [u8], String> {
+        if len>self.remaining() {
+            return Err(format!("Unexpected end of data: wanted {} bytes at offset {}, but only {} remain.", len, self.offset, self.remaining()));
+        }
+        let slice = &self.data[self.offset..self.offset+len];
+        self.offset+=len;
+        Ok(slice)
+    }
+
+    fn read_array<const N:usize>(&mut self) -> Result<[u8;N], String> {
+        let mut array = [0u8;N];
+        array.copy_from_slice(self.read_slice(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_slice(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u128(&mut self) -> Result<u128, String> {
+        Ok(u128::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, String> {
+        let start = self.offset;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            if shift==63 && byte>1 {
+                break;
+            }
+            value|=((byte&0x7f) as u64)<<shift;
+            if byte&0x80==0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("Variable length integer at offset {} does not fit in 64 bits.", start))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.read_u64()?;
+        if len>self.remaining() as u64 {
+            return Err(format!("Byte string of length {} at offset {} runs past the end of the data.", len, self.offset));
+        }
+        self.read_slice(len as usize)
+    }
+
+    pub fn read_str(&mut self) -> Result<String, String> {
+        let bytes = self.read_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| format!("Invalid UTF-8 string: {}", e))
+    }
+}
\ No newline at end of file
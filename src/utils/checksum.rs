@@ -0,0 +1,28 @@
+//CRC-32 as used by zlib, PNG and Ethernet, so stored checksums can be checked with common tools
+const CRC32_POLYNOMIAL:u32 = 0xEDB8_8320;
+
+const fn crc32_table() -> [u32;256] {
+    let mut table = [0u32;256];
+    let mut index = 0;
+    while index<256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit<8 {
+            value = if value&1!=0 {(value>>1)^CRC32_POLYNOMIAL} else {value>>1};
+            bit+=1;
+        }
+        table[index] = value;
+        index+=1;
+    }
+    table
+}
+
+const CRC32_TABLE:[u32;256] = crc32_table();
+
+pub fn crc32(data:&[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = CRC32_TABLE[((crc^*byte as u32)&0xff) as usize]^(crc>>8);
+    }
+    !crc
+}
\ No newline at end of file
@@ -1 +1,3 @@
+pub mod binary;
+pub mod checksum;
 pub mod shaders;
\ No newline at end of file